@@ -0,0 +1,68 @@
+//! Index build and query latency over a generated corpus, so that changes
+//! to analysis or scoring can be measured: `cargo bench`.
+use criterion::{criterion_group, criterion_main, Criterion};
+use fulltext::{corpus::CorpusBuilder, SearchRequest};
+use std::hint::black_box;
+
+const DOCUMENTS: usize = 5_000;
+const VOCABULARY: u64 = 5_000;
+
+/// The same corpus on every run: documents of 20 to 200 words drawn from a
+/// vocabulary where lower-numbered words are much more common, roughly as in
+/// natural text.
+fn corpus() -> CorpusBuilder {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..DOCUMENTS)
+        .map(|doc| {
+            let length = 20 + next() % 180;
+            let words: Vec<String> = (0..length)
+                .map(|_| {
+                    let word = (next() % VOCABULARY) * (next() % VOCABULARY) / VOCABULARY;
+                    format!("w{}", word)
+                })
+                .collect();
+            (format!("doc-{:05}", doc), words.join(" "))
+        })
+        .collect()
+}
+
+fn build(c: &mut Criterion) {
+    let corpus = corpus();
+    c.bench_function("build", |b| b.iter(|| black_box(corpus.build())));
+}
+
+fn query(c: &mut Criterion) {
+    let mut index = corpus().build();
+    // Every search is timed in full rather than answered from the cache.
+    index.set_cache_capacity(0);
+    let mut group = c.benchmark_group("query");
+    for (name, query) in [
+        ("common term", "w1"),
+        ("rare term", "w3000"),
+        ("three terms", "w2 w40 w700"),
+        ("eight terms", "w1 w3 w9 w27 w81 w243 w729 w2187"),
+        ("boolean", "w2 AND (w40 OR w700) NOT w5"),
+        ("phrase", "\"w1 w2\""),
+        ("fuzzy", "w1234~1"),
+    ] {
+        let request = SearchRequest {
+            query: query.to_string(),
+            filters: vec![],
+            offset: 0,
+            limit: 10,
+            min_score: None,
+            facets: vec![],
+        };
+        group.bench_function(name, |b| b.iter(|| black_box(index.search(&request))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build, query);
+criterion_main!(benches);
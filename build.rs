@@ -0,0 +1,41 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The C header is only generated for the `ffi` feature, into OUT_DIR so
+    // the build never writes to the source tree. The committed
+    // include/fulltext.h is only checked against it.
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-changed=include/fulltext.h");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+            .expect("cbindgen.toml is valid");
+        let generated = format!("{}/fulltext.h", out_dir);
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("the C header can be generated")
+            .write_to_file(&generated);
+        let committed = format!("{}/include/fulltext.h", crate_dir);
+        if std::fs::read(&generated).ok() != std::fs::read(&committed).ok() {
+            println!(
+                "cargo:warning=include/fulltext.h is out of date; \
+                 run `cbindgen --config cbindgen.toml --output include/fulltext.h`"
+            );
+        }
+    }
+    // The gRPC service is generated without needing protoc installed.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/fulltext.proto");
+        let descriptors = protox::compile(["proto/fulltext.proto"], ["proto"])
+            .expect("proto/fulltext.proto is valid");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("the gRPC service can be generated");
+    }
+}
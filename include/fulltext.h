@@ -0,0 +1,85 @@
+#ifndef FULLTEXT_H
+#define FULLTEXT_H
+
+/* Generated by cbindgen from src/ffi.rs with the `ffi` feature. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An index, owned by the caller between `bm25_index_new` and
+// `bm25_index_free`.
+typedef struct Bm25Index Bm25Index;
+
+// One search result: the number `bm25_index_add` returned for the document,
+// and its score.
+typedef struct Bm25Hit {
+  uint64_t doc;
+  float score;
+} Bm25Hit;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty index with the default analyzer and parameters.
+struct Bm25Index *bm25_index_new(void);
+
+// Sets the term frequency saturation `k1` and length normalization `b`.
+//
+// # Safety
+//
+// `index` must come from `bm25_index_new` and not have been freed.
+void bm25_index_set_parameters(struct Bm25Index *index, float k1, float b);
+
+// Analyzes and adds a document, returning its number, or -1 if `text` is
+// null or not UTF-8.
+//
+// # Safety
+//
+// `index` must come from `bm25_index_new` and not have been freed, and
+// `text` must be null or a NUL-terminated string.
+int64_t bm25_index_add(struct Bm25Index *index, const char *text);
+
+// Removes the document with the given number, returning whether it was in
+// the index.
+//
+// # Safety
+//
+// `index` must come from `bm25_index_new` and not have been freed.
+bool bm25_index_remove(struct Bm25Index *index, uint64_t doc);
+
+// Number of documents in the index.
+//
+// # Safety
+//
+// `index` must come from `bm25_index_new` and not have been freed.
+size_t bm25_index_len(const struct Bm25Index *index);
+
+// Writes the `k` best documents for `query` into `hits`, best first, and
+// returns how many were written.
+//
+// # Safety
+//
+// `index` must come from `bm25_index_new` and not have been freed, `query`
+// must be null or a NUL-terminated string, and `hits` must have room for `k`
+// results.
+size_t bm25_index_search(const struct Bm25Index *index,
+                         const char *query,
+                         struct Bm25Hit *hits,
+                         size_t k);
+
+// Frees an index. Null is ignored.
+//
+// # Safety
+//
+// `index` must be null or come from `bm25_index_new`, and not be used again.
+void bm25_index_free(struct Bm25Index *index);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FULLTEXT_H */
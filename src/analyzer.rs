@@ -0,0 +1,357 @@
+use rust_stemmers::Algorithm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Turns raw text into the terms that are indexed and queried.
+pub trait Analyzer: Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<String>;
+}
+
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+pub trait TokenFilter: Send + Sync {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String>;
+}
+
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split_whitespace().map(|s| s.to_string()).collect()
+    }
+}
+
+/// Splits text into words at Unicode (UAX #29) word boundaries, dropping
+/// punctuation and whitespace.
+pub struct UnicodeTokenizer;
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.unicode_words().map(|s| s.to_string()).collect()
+    }
+}
+
+/// Whether `c` belongs to a script that is written without spaces between
+/// words, so word boundaries cannot be found without a dictionary.
+fn is_unspaced(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}' // CJK Extensions B and later
+        | '\u{0E00}'..='\u{0EFF}' // Thai, Lao
+        | '\u{1000}'..='\u{109F}' // Myanmar
+        | '\u{1780}'..='\u{17FF}' // Khmer
+    )
+}
+
+/// Like [`UnicodeTokenizer`], but text in scripts written without spaces (CJK,
+/// Thai, ...) is turned into overlapping character bigrams, the usual
+/// dictionary-free way to index it. A lone character stays a unigram.
+pub struct CjkTokenizer;
+
+impl CjkTokenizer {
+    fn push_bigrams(run: &[char], tokens: &mut Vec<String>) {
+        if run.len() == 1 {
+            tokens.push(run[0].to_string());
+        }
+        tokens.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+    }
+}
+
+impl Tokenizer for CjkTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        let mut run: Vec<char> = vec![];
+        let mut run_end = 0;
+        for (start, word) in text.unicode_word_indices() {
+            if !word.chars().all(is_unspaced) {
+                Self::push_bigrams(&run, &mut tokens);
+                run.clear();
+                tokens.push(word.to_string());
+                continue;
+            }
+            if start != run_end {
+                Self::push_bigrams(&run, &mut tokens);
+                run.clear();
+            }
+            run.extend(word.chars());
+            run_end = start + word.len();
+        }
+        Self::push_bigrams(&run, &mut tokens);
+        tokens
+    }
+}
+
+/// Which tokenizer an [`AnalyzerConfig`] builds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TokenizerKind {
+    #[default]
+    Unicode,
+    Whitespace,
+    Cjk,
+}
+
+impl TokenizerKind {
+    pub fn parse(value: &str) -> Option<TokenizerKind> {
+        match value {
+            "unicode" => Some(TokenizerKind::Unicode),
+            "whitespace" => Some(TokenizerKind::Whitespace),
+            "cjk" => Some(TokenizerKind::Cjk),
+            _ => None,
+        }
+    }
+
+    fn build(self) -> Box<dyn Tokenizer> {
+        match self {
+            TokenizerKind::Unicode => Box::new(UnicodeTokenizer),
+            TokenizerKind::Whitespace => Box::new(WhitespaceTokenizer),
+            TokenizerKind::Cjk => Box::new(CjkTokenizer),
+        }
+    }
+}
+
+pub struct Lowercase;
+
+impl TokenFilter for Lowercase {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        tokens.into_iter().map(|s| s.to_lowercase()).collect()
+    }
+}
+
+/// Trims punctuation from both ends of each token and drops tokens that were
+/// only punctuation.
+pub struct StripPunctuation;
+
+impl TokenFilter for StripPunctuation {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        tokens
+            .into_iter()
+            .map(|s| s.trim_matches(|c: char| !c.is_alphanumeric()).to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+}
+
+pub struct StopWords {
+    words: HashSet<String>,
+}
+
+impl StopWords {
+    pub fn new(words: HashSet<String>) -> StopWords {
+        StopWords { words }
+    }
+}
+
+impl TokenFilter for StopWords {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        tokens
+            .into_iter()
+            .filter(|s| !self.words.contains(&s.to_lowercase()))
+            .collect()
+    }
+}
+
+/// Snowball stemming for one language, except for the words it is told to
+/// leave as they are.
+pub struct Stemmer {
+    stemmer: rust_stemmers::Stemmer,
+    exceptions: HashSet<String>,
+}
+
+impl Stemmer {
+    /// Looks up a stemmer by lowercase language name, e.g. `english`.
+    pub fn for_language(language: &str) -> Option<Stemmer> {
+        let algorithm = match language {
+            "arabic" => Algorithm::Arabic,
+            "danish" => Algorithm::Danish,
+            "dutch" => Algorithm::Dutch,
+            "english" => Algorithm::English,
+            "finnish" => Algorithm::Finnish,
+            "french" => Algorithm::French,
+            "german" => Algorithm::German,
+            "greek" => Algorithm::Greek,
+            "hungarian" => Algorithm::Hungarian,
+            "italian" => Algorithm::Italian,
+            "norwegian" => Algorithm::Norwegian,
+            "portuguese" => Algorithm::Portuguese,
+            "romanian" => Algorithm::Romanian,
+            "russian" => Algorithm::Russian,
+            "spanish" => Algorithm::Spanish,
+            "swedish" => Algorithm::Swedish,
+            "tamil" => Algorithm::Tamil,
+            "turkish" => Algorithm::Turkish,
+            _ => return None,
+        };
+        Some(Stemmer {
+            stemmer: rust_stemmers::Stemmer::create(algorithm),
+            exceptions: HashSet::new(),
+        })
+    }
+
+    /// Leaves the lowercase `exceptions` unstemmed, such as names the
+    /// stemmer would merge with unrelated words.
+    pub fn with_exceptions(mut self, exceptions: HashSet<String>) -> Stemmer {
+        self.exceptions = exceptions;
+        self
+    }
+}
+
+impl TokenFilter for Stemmer {
+    fn filter(&self, tokens: Vec<String>) -> Vec<String> {
+        tokens
+            .into_iter()
+            .map(|s| {
+                if self.exceptions.contains(&s) {
+                    s
+                } else {
+                    self.stemmer.stem(&s).into_owned()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Appends a `first_second` term for every adjacent pair of tokens.
+pub struct Bigrams;
+
+impl TokenFilter for Bigrams {
+    fn filter(&self, mut tokens: Vec<String>) -> Vec<String> {
+        let bigrams: Vec<String> = tokens
+            .windows(2)
+            .map(|pair| format!("{}_{}", pair[0], pair[1]))
+            .collect();
+        tokens.extend(bigrams);
+        tokens
+    }
+}
+
+/// Token n-grams to append, with sizes from `min` to `max` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Ngrams {
+    /// Runs of adjacent tokens joined with `_`, like [`Bigrams`].
+    Words { min: usize, max: usize },
+    /// Runs of characters within each token, for partial-word matching.
+    Chars { min: usize, max: usize },
+}
+
+impl Ngrams {
+    /// Parses `2..3` (word n-grams), `word:2..3` or `char:3..3`. A single
+    /// number means that size only.
+    pub fn parse(value: &str) -> Option<Ngrams> {
+        let (kind, sizes) = value.split_once(':').unwrap_or(("word", value));
+        let (min, max) = sizes.split_once("..").unwrap_or((sizes, sizes));
+        let (min, max): (usize, usize) = (min.parse().ok()?, max.parse().ok()?);
+        if min == 0 || min > max {
+            return None;
+        }
+        match kind {
+            "word" => Some(Ngrams::Words { min, max }),
+            "char" => Some(Ngrams::Chars { min, max }),
+            _ => None,
+        }
+    }
+}
+
+impl TokenFilter for Ngrams {
+    /// Keeps the tokens and appends the n-grams after them, so the positions
+    /// of the original tokens do not change.
+    fn filter(&self, mut tokens: Vec<String>) -> Vec<String> {
+        let mut ngrams = vec![];
+        match *self {
+            Ngrams::Words { min, max } => {
+                for n in min.max(2)..=max {
+                    ngrams.extend(tokens.windows(n).map(|window| window.join("_")));
+                }
+            }
+            Ngrams::Chars { min, max } => {
+                for token in &tokens {
+                    let chars: Vec<char> = token.chars().collect();
+                    for n in min..=max.min(chars.len().saturating_sub(1)) {
+                        ngrams.extend(chars.windows(n).map(|window| window.iter().collect()));
+                    }
+                }
+            }
+        }
+        tokens.extend(ngrams);
+        tokens
+    }
+}
+
+/// A tokenizer followed by filters applied in order.
+pub struct ChainAnalyzer {
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl ChainAnalyzer {
+    pub fn new(tokenizer: Box<dyn Tokenizer>) -> ChainAnalyzer {
+        ChainAnalyzer {
+            tokenizer,
+            filters: vec![],
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Box<dyn TokenFilter>) -> ChainAnalyzer {
+        self.filters.push(filter);
+        self
+    }
+}
+
+impl Analyzer for ChainAnalyzer {
+    fn analyze(&self, text: &str) -> Vec<String> {
+        self.filters
+            .iter()
+            .fold(self.tokenizer.tokenize(text), |tokens, filter| {
+                filter.filter(tokens)
+            })
+    }
+}
+
+impl Default for ChainAnalyzer {
+    fn default() -> Self {
+        ChainAnalyzer::new(Box::new(UnicodeTokenizer)).with_filter(Box::new(Lowercase))
+    }
+}
+
+/// Serializable description of a [`ChainAnalyzer`], so an index saved to disk
+/// can be queried with the same analysis it was built with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalyzerConfig {
+    pub tokenizer: TokenizerKind,
+    pub strip_punctuation: bool,
+    pub stopwords: HashSet<String>,
+    pub stemmer: Option<String>,
+    /// Words the stemmer leaves as they are.
+    pub stem_exceptions: HashSet<String>,
+    pub bigrams: bool,
+    pub ngrams: Vec<Ngrams>,
+}
+
+impl AnalyzerConfig {
+    pub fn build(&self) -> ChainAnalyzer {
+        let mut analyzer =
+            ChainAnalyzer::new(self.tokenizer.build()).with_filter(Box::new(Lowercase));
+        if self.strip_punctuation {
+            analyzer = analyzer.with_filter(Box::new(StripPunctuation));
+        }
+        if !self.stopwords.is_empty() {
+            analyzer = analyzer.with_filter(Box::new(StopWords::new(self.stopwords.clone())));
+        }
+        if let Some(stemmer) = self.stemmer.as_deref().and_then(Stemmer::for_language) {
+            let stemmer = stemmer.with_exceptions(self.stem_exceptions.clone());
+            analyzer = analyzer.with_filter(Box::new(stemmer));
+        }
+        if self.bigrams {
+            analyzer = analyzer.with_filter(Box::new(Bigrams));
+        }
+        for ngrams in &self.ngrams {
+            analyzer = analyzer.with_filter(Box::new(*ngrams));
+        }
+        analyzer
+    }
+}
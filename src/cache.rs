@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// How many queries an index keeps the results of unless told otherwise.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// How well the query cache of an index has been doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Number of queries whose results are cached now.
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// Results of recent queries by key, dropping the least recently used when
+/// full. A capacity of zero caches nothing. Values are shared so a lookup
+/// does not copy a whole ranking.
+pub(crate) struct QueryCache<V> {
+    capacity: usize,
+    /// Each key's value and when it was last used.
+    entries: HashMap<String, (u64, Arc<V>)>,
+    /// The keys by when they were last used, oldest first.
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<V> QueryCache<V> {
+    pub fn new(capacity: usize) -> QueryCache<V> {
+        QueryCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The value of `key`, counting a hit or a miss.
+    pub fn get(&mut self, key: &str) -> Option<Arc<V>> {
+        let Some((used, value)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        self.clock += 1;
+        let key = self.recency.remove(used).unwrap();
+        *used = self.clock;
+        self.recency.insert(self.clock, key);
+        Some(Arc::clone(value))
+    }
+
+    pub fn insert(&mut self, key: String, value: Arc<V>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((used, _)) = self.entries.remove(&key) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key, (self.clock, value));
+        self.shrink_to(self.capacity);
+    }
+
+    fn shrink_to(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let (_, key) = self.recency.pop_first().unwrap();
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Drops every value, e.g. because the index changed, keeping the counts.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink_to(capacity);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
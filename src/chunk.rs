@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+/// Where a passage lies in the text of the document it was split from. Byte
+/// offsets are into the extracted text; lines count from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Passage {
+    pub start: usize,
+    pub end: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// How documents are split into passages at index time.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Chunking {
+    /// Windows of `size` words, each starting `size - overlap` words after the
+    /// previous one.
+    Words { size: usize, overlap: usize },
+    /// Runs of text separated by blank lines.
+    Paragraphs,
+}
+
+impl Chunking {
+    /// Parses `paragraphs`, `200` or `200:50` (window size and overlap in words).
+    pub fn parse(value: &str) -> Option<Chunking> {
+        if value == "paragraphs" {
+            return Some(Chunking::Paragraphs);
+        }
+        let (size, overlap) = value.split_once(':').unwrap_or((value, "0"));
+        let size: usize = size.parse().ok()?;
+        let overlap: usize = overlap.parse().ok()?;
+        (size > 0 && overlap < size).then_some(Chunking::Words { size, overlap })
+    }
+
+    /// Splits `text` into passages. Text without any words gives none.
+    pub fn split(&self, text: &str) -> Vec<Passage> {
+        let spans = match *self {
+            Chunking::Words { size, overlap } => word_windows(text, size, overlap),
+            Chunking::Paragraphs => paragraphs(text),
+        };
+        let line_at = |offset: usize| text[..offset].matches('\n').count() + 1;
+        spans
+            .into_iter()
+            .map(|(start, end)| Passage {
+                start,
+                end,
+                start_line: line_at(start),
+                end_line: line_at(end),
+            })
+            .collect()
+    }
+}
+
+/// Byte ranges of the whitespace-separated words of `text`.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (offset, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push((word_start, offset));
+                start = None;
+            }
+            (false, None) => start = Some(offset),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, text.len()));
+    }
+    words
+}
+
+fn word_windows(text: &str, size: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let words = words(text);
+    let step = size - overlap;
+    let mut windows = vec![];
+    let mut first = 0;
+    while first < words.len() {
+        let last = (first + size).min(words.len()) - 1;
+        windows.push((words[first].0, words[last].1));
+        if last + 1 == words.len() {
+            break;
+        }
+        first += step;
+    }
+    windows
+}
+
+fn paragraphs(text: &str) -> Vec<(usize, usize)> {
+    let mut paragraphs = vec![];
+    let mut start = None;
+    let mut end = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(paragraph_start) = start.take() {
+                paragraphs.push((paragraph_start, end));
+            }
+        } else {
+            start.get_or_insert(offset + (line.len() - line.trim_start().len()));
+            end = offset + line.trim_end().len();
+        }
+        offset += line.len();
+    }
+    if let Some(paragraph_start) = start {
+        paragraphs.push((paragraph_start, end));
+    }
+    paragraphs
+}
@@ -0,0 +1,103 @@
+use crate::{analyzer::Analyzer, page, BM25Index, SearchRequest};
+use std::collections::BTreeMap;
+
+/// A hit from [`Collections::search`]: a document of the named collection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionHit {
+    pub collection: String,
+    pub doc: i32,
+    pub score: f32,
+}
+
+/// Several independent indexes, e.g. "notes", "code" and "mail", each with its
+/// own analyzer and scoring settings.
+#[derive(Default)]
+pub struct Collections {
+    indexes: BTreeMap<String, BM25Index>,
+}
+
+impl Collections {
+    pub fn new() -> Collections {
+        Collections::default()
+    }
+
+    /// Adds a collection, returning the index it replaces, if any.
+    pub fn insert(&mut self, name: &str, index: BM25Index) -> Option<BM25Index> {
+        self.indexes.insert(name.to_string(), index)
+    }
+
+    /// Adds an empty collection that analyzes text with `analyzer`.
+    pub fn create(&mut self, name: &str, analyzer: Box<dyn Analyzer>) -> &mut BM25Index {
+        self.indexes
+            .insert(name.to_string(), BM25Index::with_analyzer(analyzer));
+        self.indexes.get_mut(name).unwrap()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BM25Index> {
+        self.indexes.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut BM25Index> {
+        self.indexes.get_mut(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<BM25Index> {
+        self.indexes.remove(name)
+    }
+
+    /// The collection names, in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.indexes.keys().map(|name| name.as_str()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    /// Runs `request` against every collection and merges the hits by score,
+    /// breaking ties by collection name and then document, before taking the
+    /// requested page. Each collection scores with its own statistics, so
+    /// scores are only comparable between collections of similar content.
+    pub fn search(&self, request: &SearchRequest) -> Vec<CollectionHit> {
+        let names: Vec<&str> = self.names();
+        self.search_in(&names, request)
+    }
+
+    /// Like [`Collections::search`], over the named collections only. Unknown
+    /// names are ignored.
+    pub fn search_in(&self, names: &[&str], request: &SearchRequest) -> Vec<CollectionHit> {
+        // Any collection may have hits on the requested page, so each one is
+        // asked for everything up to its end.
+        let up_to_page = SearchRequest {
+            offset: 0,
+            limit: request.offset.saturating_add(request.limit),
+            ..request.clone()
+        };
+        let mut hits: Vec<CollectionHit> = names
+            .iter()
+            .filter_map(|name| Some((*name, self.indexes.get(*name)?)))
+            .flat_map(|(name, index)| {
+                index
+                    .search(&up_to_page)
+                    .hits
+                    .into_iter()
+                    .map(move |(doc, score)| CollectionHit {
+                        collection: name.to_string(),
+                        doc,
+                        score,
+                    })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.collection.cmp(&b.collection))
+                .then(a.doc.cmp(&b.doc))
+        });
+        page(hits, request.offset, request.limit)
+    }
+}
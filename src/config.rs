@@ -0,0 +1,153 @@
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, fs, path::Path};
+
+/// The file read from the working directory when `--config` is not given.
+pub const DEFAULT_PATH: &str = "bm25.toml";
+
+/// Settings read from a `bm25.toml` file. Command-line flags take precedence.
+///
+/// ```toml
+/// k1 = 1.2
+/// b = 0.75
+/// idf = "lucene"
+/// limit = 10
+/// cache_size = 64
+/// shards = 4
+///
+/// [analyzer]
+/// tokenizer = "unicode"
+/// strip_punctuation = true
+/// stemmer = "english"
+/// ngrams = ["2..3", "char:3..4"]
+/// stop_language = "english"
+/// stopwords = ["extra-stopwords.txt"]
+/// stem_exceptions = ["names.txt"]
+///
+/// [collections.notes]
+/// path = "notes"
+/// scorer = "bm25l"
+///
+/// [collections.code]
+/// path = "code.bm25"
+/// k1 = 1.8
+///
+/// [collections.code.analyzer]
+/// tokenizer = "whitespace"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub k1: Option<f32>,
+    pub b: Option<f32>,
+    /// How BM25 weights very common terms: `classic`, `clamp`,
+    /// `floor[:WEIGHT]` or `lucene`.
+    pub idf: Option<String>,
+    pub limit: Option<usize>,
+    pub scorer: Option<String>,
+    /// How many queries' results an index caches; 0 turns caching off.
+    pub cache_size: Option<usize>,
+    /// How many shards a searched directory is indexed into; more than one
+    /// indexes and searches them in parallel.
+    pub shards: Option<usize>,
+    pub analyzer: AnalyzerSection,
+    pub collections: BTreeMap<String, CollectionSection>,
+}
+
+/// A named collection for `--collection`: the directory or index file it is
+/// searched from, and settings that replace the top-level ones. Without an
+/// `[analyzer]` table of its own it uses the top-level one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionSection {
+    pub path: Option<String>,
+    pub k1: Option<f32>,
+    pub b: Option<f32>,
+    pub idf: Option<String>,
+    pub limit: Option<usize>,
+    pub scorer: Option<String>,
+    pub analyzer: Option<AnalyzerSection>,
+}
+
+impl Config {
+    /// The settings for a collection, or `None` if there is no such section.
+    pub fn collection(&self, name: &str) -> Option<(Option<String>, Config)> {
+        let section = self.collections.get(name)?;
+        let config = Config {
+            k1: section.k1.or(self.k1),
+            b: section.b.or(self.b),
+            idf: section.idf.clone().or(self.idf.clone()),
+            limit: section.limit.or(self.limit),
+            scorer: section.scorer.clone().or(self.scorer.clone()),
+            cache_size: self.cache_size,
+            shards: self.shards,
+            analyzer: section
+                .analyzer
+                .clone()
+                .unwrap_or_else(|| self.analyzer.clone()),
+            collections: BTreeMap::new(),
+        };
+        Some((section.path.clone(), config))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerSection {
+    pub tokenizer: Option<String>,
+    pub strip_punctuation: bool,
+    pub stemmer: Option<String>,
+    pub bigrams: bool,
+    pub ngrams: Vec<String>,
+    pub stop_language: Option<String>,
+    pub stopwords: Vec<String>,
+    pub stem_exceptions: Vec<String>,
+}
+
+/// Reads a config file. Stop-word, stem-exception and collection paths are
+/// relative to the file's directory.
+pub fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+    let mut config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    let relative = |path: &mut String| *path = base.join(&*path).to_string_lossy().into_owned();
+    config.analyzer.stopwords.iter_mut().for_each(relative);
+    config
+        .analyzer
+        .stem_exceptions
+        .iter_mut()
+        .for_each(relative);
+    for section in config.collections.values_mut() {
+        section.path.iter_mut().for_each(relative);
+        if let Some(analyzer) = &mut section.analyzer {
+            analyzer.stopwords.iter_mut().for_each(relative);
+            analyzer.stem_exceptions.iter_mut().for_each(relative);
+        }
+    }
+    Ok(config)
+}
+
+/// The config named by `--config`, or `bm25.toml` if it exists.
+pub fn find(explicit: Option<&str>) -> Result<Config, Box<dyn Error>> {
+    match explicit {
+        Some(path) => load(path),
+        None if Path::new(DEFAULT_PATH).is_file() => load(DEFAULT_PATH),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Sets `k1` and `b` at the top of the config file at `path`, creating the file
+/// if need be. The rest of the file, comments included, is kept as it is.
+pub fn write_parameters(path: &str, k1: f32, b: f32) -> Result<(), Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut document: toml_edit::DocumentMut = text.parse()?;
+    // Through their shortest decimal form, so that 1.2 is not written as
+    // 1.2000000476837158.
+    let decimal = |value: f32| value.to_string().parse::<f64>().unwrap();
+    document["k1"] = toml_edit::value(decimal(k1));
+    document["b"] = toml_edit::value(decimal(b));
+    fs::write(path, document.to_string())?;
+    Ok(())
+}
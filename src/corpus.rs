@@ -0,0 +1,113 @@
+//! Building an index from documents held in memory rather than read from
+//! files, for tests and for programs that embed the index:
+//!
+//! ```no_run
+//! use fulltext::corpus::CorpusBuilder;
+//!
+//! let index = CorpusBuilder::new()
+//!     .with_document("b", "the quick brown fox")
+//!     .with_document("a", "a lazy dog")
+//!     .build();
+//! for (doc, score) in index.rank_top_k(&["fox"], 10) {
+//!     println!("{:?} {}", CorpusBuilder::id(&index, doc as usize), score);
+//! }
+//! ```
+use crate::{analyzer::AnalyzerConfig, BM25Index};
+use std::collections::BTreeMap;
+
+/// Documents given as `(id, text)` pairs, and how to index them. They are
+/// added to the index in the order of their ids, whatever order they were
+/// given in, so the same documents always get the same document indexes and
+/// ids, break ties between equal scores the same way and score the same on
+/// every run.
+#[derive(Debug, Clone, Default)]
+pub struct CorpusBuilder {
+    analysis: AnalyzerConfig,
+    parameters: Option<(f32, f32)>,
+    documents: BTreeMap<String, String>,
+}
+
+impl CorpusBuilder {
+    pub fn new() -> CorpusBuilder {
+        CorpusBuilder::default()
+    }
+
+    /// Analyzes the documents, and later queries, as `analysis` says instead
+    /// of with the default analysis.
+    pub fn with_analysis(mut self, analysis: AnalyzerConfig) -> CorpusBuilder {
+        self.analysis = analysis;
+        self
+    }
+
+    /// Sets BM25's `k1` and `b` instead of the defaults of
+    /// [`BM25Index::set_parameters`].
+    pub fn with_parameters(mut self, k1: f32, b: f32) -> CorpusBuilder {
+        self.parameters = Some((k1, b));
+        self
+    }
+
+    /// Adds a document, replacing any given before with the same id.
+    pub fn with_document(
+        mut self,
+        id: impl Into<String>,
+        text: impl Into<String>,
+    ) -> CorpusBuilder {
+        self.documents.insert(id.into(), text.into());
+        self
+    }
+
+    /// Number of documents given so far.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// The documents as `(id, text)` pairs, in the order they are indexed.
+    pub fn documents(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.documents
+            .iter()
+            .map(|(id, text)| (id.as_str(), text.as_str()))
+    }
+
+    /// Indexes the documents. Each one's id is kept as the `id` value of its
+    /// metadata, as for records, and read back by [`CorpusBuilder::id`].
+    pub fn build(&self) -> BM25Index {
+        let mut index = BM25Index::with_analyzer(Box::new(self.analysis.build()));
+        if let Some((k1, b)) = self.parameters {
+            index.set_parameters(k1, b);
+        }
+        let texts: Vec<String> = self.documents.values().cloned().collect();
+        let docs = index.add_texts(&texts);
+        for (doc, id) in docs.zip(self.documents.keys()) {
+            let mut metadata = index.metadata(doc).clone();
+            metadata.values.insert("id".to_string(), id.clone());
+            index.set_metadata(doc, metadata);
+        }
+        index
+    }
+
+    /// The id the document at `doc_index` of an index this built was given.
+    pub fn id(index: &BM25Index, doc_index: usize) -> Option<&str> {
+        let metadata = index.metadata.get(doc_index)?;
+        metadata.values.get("id").map(|id| id.as_str())
+    }
+}
+
+impl<I: Into<String>, T: Into<String>> FromIterator<(I, T)> for CorpusBuilder {
+    fn from_iter<D: IntoIterator<Item = (I, T)>>(documents: D) -> CorpusBuilder {
+        let mut builder = CorpusBuilder::new();
+        builder.extend(documents);
+        builder
+    }
+}
+
+impl<I: Into<String>, T: Into<String>> Extend<(I, T)> for CorpusBuilder {
+    fn extend<D: IntoIterator<Item = (I, T)>>(&mut self, documents: D) {
+        for (id, text) in documents {
+            self.documents.insert(id.into(), text.into());
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Cutting a search short. A pathological query, e.g. a fuzzy term that
+//! expands to much of the dictionary, can take far longer than a server can
+//! wait for it; with a [`Deadline`] the search stops expanding, collecting
+//! and scoring candidates once it passes, and returns what it has.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// When a search gives up: after a time limit, once cancelled, or never, as
+/// by default. Clones share the cancellation, so one thread can cancel a
+/// search another is running.
+#[derive(Debug, Clone, Default)]
+pub struct Deadline {
+    at: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+    /// Set by the first check that finds the deadline passed, as from then
+    /// on the search is missing some of its work.
+    reached: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    /// A deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline {
+            at: Some(Instant::now() + timeout),
+            ..Deadline::default()
+        }
+    }
+
+    /// Makes searches with this deadline, or a clone of it, stop at their
+    /// next check.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a search was cut short by this deadline, so that its results
+    /// are partial.
+    pub fn reached(&self) -> bool {
+        self.reached.load(Ordering::Relaxed)
+    }
+
+    /// Whether the search should stop now.
+    pub(crate) fn expired(&self) -> bool {
+        if self.reached() {
+            return true;
+        }
+        let expired = self.cancelled.load(Ordering::Relaxed)
+            || self.at.is_some_and(|at| Instant::now() >= at);
+        if expired {
+            self.reached.store(true, Ordering::Relaxed);
+        }
+        expired
+    }
+}
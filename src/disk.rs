@@ -0,0 +1,408 @@
+use crate::{
+    analyzer::{Analyzer, AnalyzerConfig},
+    postings::PostingSlice,
+    scorer::{self, IdfMode, Scorer, TermStats},
+    snapshot, BM25Index, Bm25Error, DocId, IndexStats, Metadata, TermInfo, TermOrder,
+};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+};
+
+/// Identifies an on-disk index, including the version of its layout.
+const MAGIC: &[u8; 8] = b"FTDISK03";
+
+/// What every layout's magic starts with.
+const MAGIC_PREFIX: &[u8; 6] = b"FTDISK";
+
+/// Bytes per document in the document table: its length, or -1 if it was
+/// removed, and the end of its metadata.
+const DOC_ENTRY: usize = 12;
+
+/// Where one term's postings are in the file.
+#[derive(Serialize, Deserialize)]
+struct TermEntry {
+    offset: u64,
+    bytes: u32,
+    docs: u32,
+    collection_freq: i32,
+}
+
+/// Everything that is loaded into memory when an index is opened. It is
+/// written after the postings, and the file ends with its offset.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    k1: f32,
+    b: f32,
+    idf_mode: IdfMode,
+    /// The scorer, by the name [`scorer::by_name`] parses.
+    scorer: String,
+    doc_count: i32,
+    total_doc_length: i32,
+    avg_doc_length: i32,
+    slots: u32,
+    docs_offset: u64,
+    metadata_offset: u64,
+    analysis: AnalyzerConfig,
+    terms: HashMap<String, TermEntry>,
+}
+
+/// A read-only index that is memory-mapped rather than loaded, so it can be
+/// searched on a machine with less memory than the index takes on disk. Only
+/// the term dictionary is held in memory; postings, document lengths and
+/// metadata are read from the mapping as queries touch them.
+///
+/// It is written from a [`BM25Index`] and ranks bag-of-words queries with the
+/// same scores, but has no phrase queries, fields or titles.
+pub struct DiskIndex {
+    map: Mmap,
+    header: Header,
+    analyzer: Box<dyn Analyzer>,
+    scorer: Box<dyn Scorer>,
+}
+
+fn invalid(path: &str, message: impl ToString) -> Bm25Error {
+    Bm25Error::InvalidIndex {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+impl DiskIndex {
+    /// Writes `index`, along with the analysis and the scorer, by the name
+    /// [`scorer::by_name`] parses, it was built with, in the on-disk format.
+    pub fn write(
+        index: &BM25Index,
+        analysis: &AnalyzerConfig,
+        scorer: &str,
+        path: &str,
+    ) -> Result<(), Bm25Error> {
+        let io_error = |source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        };
+        snapshot::write_atomic(path, |out| {
+            out.write_all(MAGIC).map_err(io_error)?;
+            let mut offset = MAGIC.len() as u64;
+            let mut terms = HashMap::new();
+            for (term_id, postings) in index.postings.iter().enumerate() {
+                if postings.is_empty() {
+                    continue;
+                }
+                let bytes = postings.as_slice().bytes();
+                out.write_all(bytes).map_err(io_error)?;
+                let (docs, collection_freq) = index.live_freqs(postings);
+                terms.insert(
+                    index.terms.name(term_id as u32).to_string(),
+                    TermEntry {
+                        offset,
+                        bytes: bytes.len() as u32,
+                        docs: docs as u32,
+                        collection_freq,
+                    },
+                );
+                offset += bytes.len() as u64;
+            }
+            let blobs: Vec<Vec<u8>> = (0..index.slot_count())
+                .map(|doc| bincode::serialize(&(index.doc_id(doc), index.metadata(doc))))
+                .collect::<Result<_, _>>()
+                .map_err(|err| invalid(path, err))?;
+            let docs_offset = offset;
+            let mut metadata_end = 0u64;
+            for (doc, blob) in blobs.iter().enumerate() {
+                let length = if index.is_live(doc) {
+                    index.doc_lengths[doc]
+                } else {
+                    -1
+                };
+                metadata_end += blob.len() as u64;
+                out.write_all(&length.to_le_bytes()).map_err(io_error)?;
+                out.write_all(&metadata_end.to_le_bytes())
+                    .map_err(io_error)?;
+            }
+            let metadata_offset = docs_offset + (blobs.len() * DOC_ENTRY) as u64;
+            for blob in &blobs {
+                out.write_all(blob).map_err(io_error)?;
+            }
+            let header = Header {
+                k1: index.k1,
+                b: index.b,
+                idf_mode: index.idf_mode,
+                scorer: scorer.to_string(),
+                doc_count: index.doc_count,
+                total_doc_length: index.total_doc_length,
+                avg_doc_length: index.avg_doc_length,
+                slots: blobs.len() as u32,
+                docs_offset,
+                metadata_offset,
+                analysis: analysis.clone(),
+                terms,
+            };
+            let header_offset = metadata_offset + metadata_end;
+            bincode::serialize_into(&mut *out, &header).map_err(|err| invalid(path, err))?;
+            out.write_all(&header_offset.to_le_bytes())
+                .map_err(io_error)?;
+            out.flush().map_err(io_error)
+        })
+    }
+
+    /// Whether the file at `path` is in the on-disk format, in this layout
+    /// or an older one.
+    pub fn is_disk_index(path: &str) -> bool {
+        let mut magic = [0; MAGIC_PREFIX.len()];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .is_ok_and(|_| &magic == MAGIC_PREFIX)
+    }
+
+    pub fn open(path: &str) -> Result<DiskIndex, Bm25Error> {
+        let file = File::open(path).map_err(|source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        // Safety: the index is only read, and index files are replaced by
+        // renaming a new file over them rather than modified in place.
+        let map = unsafe { Mmap::map(&file) }.map_err(|source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        if map.len() < MAGIC.len() + 8 || !map.starts_with(MAGIC_PREFIX) {
+            return Err(invalid(path, "not an on-disk index"));
+        }
+        if &map[..MAGIC.len()] != MAGIC {
+            return Err(invalid(
+                path,
+                "it is in an older on-disk layout; build it again with `index --mmap`",
+            ));
+        }
+        let footer = map.len() - 8;
+        let header_offset = u64::from_le_bytes(map[footer..].try_into().unwrap()) as usize;
+        let header: Header = map
+            .get(header_offset..footer)
+            .ok_or_else(|| invalid(path, "truncated"))
+            .and_then(|bytes| bincode::deserialize(bytes).map_err(|err| invalid(path, err)))?;
+        let docs_end = (header.slots as usize)
+            .checked_mul(DOC_ENTRY)
+            .and_then(|table| (header.docs_offset as usize).checked_add(table));
+        if (header.docs_offset as usize) < MAGIC.len()
+            || docs_end != Some(header.metadata_offset as usize)
+            || header.metadata_offset as usize > header_offset
+        {
+            return Err(invalid(path, "its document table is out of bounds"));
+        }
+        for (term, entry) in &header.terms {
+            let end = (entry.offset as usize).checked_add(entry.bytes as usize);
+            if (entry.offset as usize) < MAGIC.len()
+                || end.is_none_or(|end| end > header.docs_offset as usize)
+            {
+                return Err(invalid(
+                    path,
+                    format!("the postings of {term:?} are out of bounds"),
+                ));
+            }
+        }
+        let analyzer = Box::new(header.analysis.build());
+        let scorer = scorer::by_name(&header.scorer).ok_or_else(|| {
+            invalid(
+                path,
+                format!("it was built with an unknown scorer {}", header.scorer),
+            )
+        })?;
+        Ok(DiskIndex {
+            map,
+            header,
+            analyzer,
+            scorer,
+        })
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.analyzer.analyze(text)
+    }
+
+    pub fn doc_count(&self) -> usize {
+        self.header.doc_count as usize
+    }
+
+    pub fn vocabulary_size(&self) -> usize {
+        self.header.terms.len()
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats::new(
+            self.header.doc_count,
+            self.header.total_doc_length,
+            self.vocabulary_size(),
+        )
+    }
+
+    /// Like [`BM25Index::top_terms`].
+    pub fn top_terms(&self, order: TermOrder, n: usize) -> Vec<TermInfo> {
+        let terms = self
+            .header
+            .terms
+            .iter()
+            .map(|(term, entry)| TermInfo {
+                term: term.clone(),
+                doc_freq: entry.docs as i32,
+                collection_freq: entry.collection_freq,
+                idf: self
+                    .header
+                    .idf_mode
+                    .idf(entry.docs as i32, self.header.doc_count),
+            })
+            .collect();
+        crate::top_terms(terms, order, n)
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.header.slots as usize
+    }
+
+    pub fn k1(&self) -> f32 {
+        self.header.k1
+    }
+
+    pub fn b(&self) -> f32 {
+        self.header.b
+    }
+
+    /// How the text of the index was analyzed, which queries are analyzed
+    /// with too.
+    pub fn analysis(&self) -> &AnalyzerConfig {
+        &self.header.analysis
+    }
+
+    pub fn set_parameters(&mut self, k1: f32, b: f32) {
+        self.header.k1 = k1;
+        self.header.b = b;
+    }
+
+    pub fn idf_mode(&self) -> IdfMode {
+        self.header.idf_mode
+    }
+
+    pub fn set_idf_mode(&mut self, idf_mode: IdfMode) {
+        self.header.idf_mode = idf_mode;
+    }
+
+    /// The name of the scorer the index was built with.
+    pub fn scorer(&self) -> &str {
+        &self.header.scorer
+    }
+
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = scorer;
+    }
+
+    fn doc_entry(&self, doc: usize) -> (i32, u64) {
+        let start = self.header.docs_offset as usize + doc * DOC_ENTRY;
+        let entry = &self.map[start..start + DOC_ENTRY];
+        (
+            i32::from_le_bytes(entry[..4].try_into().unwrap()),
+            u64::from_le_bytes(entry[4..].try_into().unwrap()),
+        )
+    }
+
+    fn doc_length(&self, doc: usize) -> i32 {
+        self.doc_entry(doc).0.max(0)
+    }
+
+    pub fn is_live(&self, doc: usize) -> bool {
+        doc < self.slot_count() && self.doc_entry(doc).0 >= 0
+    }
+
+    fn document(&self, doc: usize) -> (DocId, Metadata) {
+        let start = match doc {
+            0 => 0,
+            doc => self.doc_entry(doc - 1).1,
+        };
+        let end = self.doc_entry(doc).1;
+        let offset = self.header.metadata_offset;
+        let metadata = &self.map[offset as usize..];
+        // A damaged table can point anywhere; such a document has no metadata.
+        metadata
+            .get(start as usize..end as usize)
+            .and_then(|bytes| bincode::deserialize(bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn doc_id(&self, doc: usize) -> DocId {
+        self.document(doc).0
+    }
+
+    pub fn metadata(&self, doc: usize) -> Metadata {
+        self.document(doc).1
+    }
+
+    fn postings(&self, term: &str) -> Option<(&TermEntry, PostingSlice<'_>)> {
+        let entry = self.header.terms.get(term)?;
+        let start = entry.offset as usize;
+        let bytes = &self.map[start..start + entry.bytes as usize];
+        Some((entry, PostingSlice::new(bytes, entry.docs)))
+    }
+
+    /// The `k` best documents for a bag-of-words query, ranked and tie-broken
+    /// exactly as by [`BM25Index::rank`] without title bonuses or fields.
+    pub fn rank_top_k(&self, query: &[&str], k: usize) -> Vec<(i32, f32)> {
+        let mut frequencies: HashMap<i32, Vec<i32>> = HashMap::new();
+        let mut stats = vec![];
+        for (position, term) in query.iter().enumerate() {
+            let Some((entry, postings)) = self.postings(term) else {
+                stats.push((0, 0));
+                continue;
+            };
+            stats.push((entry.docs as i32, entry.collection_freq));
+            for (doc, tf) in postings.frequencies() {
+                // Deleted documents keep their postings until compacted.
+                if !self.is_live(doc as usize) {
+                    continue;
+                }
+                frequencies
+                    .entry(doc)
+                    .or_insert_with(|| vec![0; query.len()])[position] = tf;
+            }
+        }
+        let header = &self.header;
+        let mut hits: Vec<(i32, f32)> = frequencies
+            .into_iter()
+            .map(|(doc, tfs)| {
+                let score = tfs
+                    .iter()
+                    .zip(&stats)
+                    .map(|(&tf, &(doc_freq, collection_freq))| {
+                        self.scorer.score(&TermStats {
+                            tf,
+                            doc_length: self.doc_length(doc as usize),
+                            avg_doc_length: header.avg_doc_length.max(1) as f32,
+                            doc_freq,
+                            doc_count: header.doc_count,
+                            collection_freq,
+                            collection_length: header.total_doc_length,
+                            k1: header.k1,
+                            b: header.b,
+                            idf_mode: header.idf_mode,
+                        })
+                    })
+                    .sum();
+                (doc, score)
+            })
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        hits.truncate(k);
+        hits
+    }
+
+    /// Analyzes `text` and returns its `k` best documents for it as a bag
+    /// of words. Query syntax is not parsed, so callers should refuse text
+    /// that [`crate::query::is_structured`] accepts rather than rank its
+    /// operators and quotes as words.
+    pub fn search(&self, text: &str, k: usize) -> Vec<(i32, f32)> {
+        let terms = self.analyze(text);
+        let terms: Vec<&str> = terms.iter().map(|term| term.as_str()).collect();
+        self.rank_top_k(&terms, k)
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt, io};
+
+/// Errors returned by the library.
+#[derive(Debug)]
+pub enum Bm25Error {
+    /// A document could not be read.
+    Io { path: String, source: io::Error },
+    /// A document is not valid UTF-8.
+    Encoding { path: String },
+    /// A document looks like a binary file rather than text.
+    Binary { path: String },
+    /// Text could not be extracted from a document, e.g. a malformed PDF.
+    Extraction { path: String, message: String },
+    /// There were no documents to index.
+    EmptyCorpus,
+    /// A document was added under an id other than the next free one.
+    InvalidDocumentId { expected: i32, found: i32 },
+    /// An index file could not be written or is not in the expected format.
+    InvalidIndex { path: String, message: String },
+    /// An index file is in a format version newer than this release reads.
+    UnsupportedVersion { path: String, version: u32 },
+    /// An index is used with other analysis than it was built with; `settings`
+    /// names those that differ.
+    AnalyzerMismatch { path: String, settings: String },
+    /// A re-ranker could not score the hits of a query.
+    Rerank { message: String },
+}
+
+impl fmt::Display for Bm25Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bm25Error::Io { path, source } => write!(f, "could not read {}: {}", path, source),
+            Bm25Error::Encoding { path } => write!(f, "{} is not valid UTF-8", path),
+            Bm25Error::Binary { path } => write!(f, "{} is a binary file", path),
+            Bm25Error::Extraction { path, message } => {
+                write!(f, "could not extract text from {}: {}", path, message)
+            }
+            Bm25Error::EmptyCorpus => write!(f, "no documents to index"),
+            Bm25Error::InvalidDocumentId { expected, found } => write!(
+                f,
+                "document ids are assigned sequentially: expected {}, got {}",
+                expected, found
+            ),
+            Bm25Error::InvalidIndex { path, message } => {
+                write!(f, "invalid index {}: {}", path, message)
+            }
+            Bm25Error::UnsupportedVersion { path, version } => write!(
+                f,
+                "index {} is in format version {}, but this release reads up to version {}",
+                path,
+                version,
+                crate::format::FORMAT_VERSION
+            ),
+            Bm25Error::AnalyzerMismatch { path, settings } => write!(
+                f,
+                "index {} was built with different analysis ({}); search it with the analysis it was built with or rebuild it",
+                path, settings
+            ),
+            Bm25Error::Rerank { message } => write!(f, "could not re-rank: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Bm25Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Bm25Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
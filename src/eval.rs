@@ -0,0 +1,293 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+};
+
+/// How many results are considered per query, as in `trec_eval`.
+pub const DEPTH: usize = 1000;
+
+/// The cutoff of the reported nDCG.
+pub const NDCG_CUTOFF: usize = 10;
+
+fn invalid_line(number: usize, line: &str) -> String {
+    format!("line {}: {:?}", number + 1, line)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Relevance judgements in the TREC format: one `query_id iteration doc_id
+/// relevance` line per judged document, separated by whitespace. The iteration
+/// is ignored, and a relevance above zero means relevant, higher being better.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Qrels {
+    judgements: HashMap<String, HashMap<String, i32>>,
+}
+
+impl Qrels {
+    pub fn parse(text: &str) -> Result<Qrels, String> {
+        let mut qrels = Qrels::default();
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [] => {}
+                [query, _, doc, relevance] => {
+                    let relevance = relevance.parse().map_err(|_| invalid_line(number, line))?;
+                    qrels
+                        .judgements
+                        .entry(query.to_string())
+                        .or_default()
+                        .insert(doc.to_string(), relevance);
+                }
+                _ => return Err(invalid_line(number, line)),
+            }
+        }
+        Ok(qrels)
+    }
+
+    pub fn load(path: &str) -> io::Result<Qrels> {
+        Qrels::parse(&std::fs::read_to_string(path)?).map_err(invalid_data)
+    }
+
+    /// The judged documents of a query with their relevance, if it was judged.
+    pub fn get(&self, query: &str) -> Option<&HashMap<String, i32>> {
+        self.judgements.get(query)
+    }
+}
+
+/// Queries to evaluate, read from `query_id<TAB>text` lines. Blank lines are
+/// ignored.
+pub fn parse_queries(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let (id, query) = line
+                .split_once('\t')
+                .ok_or_else(|| invalid_line(number, line))?;
+            Ok((id.trim().to_string(), query.trim().to_string()))
+        })
+        .collect()
+}
+
+pub fn load_queries(path: &str) -> io::Result<Vec<(String, String)>> {
+    parse_queries(&std::fs::read_to_string(path)?).map_err(invalid_data)
+}
+
+/// The measures of one query's ranking, or their means over several queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Metrics {
+    /// Average precision; its mean over queries is MAP.
+    pub map: f64,
+    #[serde(rename = "ndcg@10")]
+    pub ndcg: f64,
+    /// Reciprocal rank of the first relevant document; its mean is MRR.
+    pub mrr: f64,
+    /// Share of the relevant documents that were retrieved at all.
+    pub recall: f64,
+}
+
+impl Metrics {
+    /// Measures `ranking`, a list of document ids in ranked order, against
+    /// the judgements of its query. Only the first occurrence of an id counts.
+    pub fn of(ranking: &[String], judgements: &HashMap<String, i32>) -> Metrics {
+        let mut seen = HashSet::new();
+        let ranking: Vec<&str> = ranking
+            .iter()
+            .map(|doc| doc.as_str())
+            .filter(|doc| seen.insert(*doc))
+            .take(DEPTH)
+            .collect();
+        let relevance = |doc: &str| judgements.get(doc).copied().unwrap_or(0).max(0);
+        let relevant = judgements.values().filter(|&&grade| grade > 0).count();
+        if relevant == 0 {
+            return Metrics::default();
+        }
+        let mut found = 0;
+        let mut precisions = 0.0;
+        let mut mrr = 0.0;
+        for (rank, doc) in ranking.iter().enumerate() {
+            if relevance(doc) > 0 {
+                found += 1;
+                precisions += found as f64 / (rank + 1) as f64;
+                if found == 1 {
+                    mrr = 1.0 / (rank + 1) as f64;
+                }
+            }
+        }
+        let discounted = |grades: &mut dyn Iterator<Item = i32>| -> f64 {
+            grades
+                .take(NDCG_CUTOFF)
+                .enumerate()
+                .fold(0.0, |dcg, (rank, grade)| {
+                    dcg + grade as f64 / (rank as f64 + 2.0).log2()
+                })
+        };
+        let dcg = discounted(&mut ranking.iter().map(|doc| relevance(doc)));
+        let mut ideal: Vec<i32> = judgements.values().map(|&grade| grade.max(0)).collect();
+        ideal.sort_unstable_by(|a, b| b.cmp(a));
+        let ideal_dcg = discounted(&mut ideal.into_iter());
+        Metrics {
+            map: precisions / relevant as f64,
+            ndcg: dcg / ideal_dcg,
+            mrr,
+            recall: found as f64 / relevant as f64,
+        }
+    }
+
+    /// The mean of each measure over `metrics`.
+    pub fn mean<'a>(metrics: impl IntoIterator<Item = &'a Metrics>) -> Metrics {
+        let mut count = 0;
+        let mut sum = Metrics::default();
+        for metrics in metrics {
+            count += 1;
+            sum.map += metrics.map;
+            sum.ndcg += metrics.ndcg;
+            sum.mrr += metrics.mrr;
+            sum.recall += metrics.recall;
+        }
+        if count == 0 {
+            return sum;
+        }
+        let count = count as f64;
+        Metrics {
+            map: sum.map / count,
+            ndcg: sum.ndcg / count,
+            mrr: sum.mrr / count,
+            recall: sum.recall / count,
+        }
+    }
+}
+
+/// The outcome of evaluating a set of queries.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    /// The means over the evaluated queries.
+    pub all: Metrics,
+    pub queries: BTreeMap<String, Metrics>,
+    /// Queries that have no relevance judgements and were left out.
+    pub unjudged: Vec<String>,
+}
+
+/// Runs every query through `rank`, which returns document ids in ranked
+/// order, and measures the rankings against `qrels`.
+pub fn evaluate(
+    queries: &[(String, String)],
+    qrels: &Qrels,
+    mut rank: impl FnMut(&str) -> Vec<String>,
+) -> Evaluation {
+    let mut evaluated = BTreeMap::new();
+    let mut unjudged = vec![];
+    for (id, query) in queries {
+        match qrels.get(id) {
+            Some(judgements) => {
+                evaluated.insert(id.clone(), Metrics::of(&rank(query), judgements));
+            }
+            None => unjudged.push(id.clone()),
+        }
+    }
+    Evaluation {
+        all: Metrics::mean(evaluated.values()),
+        queries: evaluated,
+        unjudged,
+    }
+}
+
+/// The measure that [`grid_search`] maximizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    Map,
+    Ndcg,
+    Mrr,
+    Recall,
+}
+
+impl Measure {
+    /// Parses `map`, `ndcg`, `mrr` or `recall`.
+    pub fn parse(value: &str) -> Option<Measure> {
+        match value {
+            "map" => Some(Measure::Map),
+            "ndcg" => Some(Measure::Ndcg),
+            "mrr" => Some(Measure::Mrr),
+            "recall" => Some(Measure::Recall),
+            _ => None,
+        }
+    }
+
+    pub fn of(&self, metrics: &Metrics) -> f64 {
+        match self {
+            Measure::Map => metrics.map,
+            Measure::Ndcg => metrics.ndcg,
+            Measure::Mrr => metrics.mrr,
+            Measure::Recall => metrics.recall,
+        }
+    }
+}
+
+/// Evenly spaced values of a parameter, from `min` up to and including `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParameterRange {
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+impl ParameterRange {
+    /// Parses `min..max` or `min..max:step`, with `default_step` when there
+    /// is no step.
+    pub fn parse(value: &str, default_step: f32) -> Option<ParameterRange> {
+        let (range, step) = match value.split_once(':') {
+            Some((range, step)) => (range, step.parse().ok()?),
+            None => (value, default_step),
+        };
+        let (min, max) = range.split_once("..")?;
+        let (min, max): (f32, f32) = (min.parse().ok()?, max.parse().ok()?);
+        (min <= max && step > 0.0).then_some(ParameterRange { min, max, step })
+    }
+
+    /// The values in increasing order, rounded to four decimals so that
+    /// repeated steps do not drift.
+    pub fn values(&self) -> Vec<f32> {
+        let count = ((self.max - self.min) / self.step + 1e-4).floor() as usize + 1;
+        (0..count)
+            .map(|i| ((self.min + i as f32 * self.step) * 1e4).round() / 1e4)
+            .collect()
+    }
+}
+
+/// The best parameters found by [`grid_search`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    pub k1: f32,
+    pub b: f32,
+    pub metrics: Metrics,
+}
+
+/// Evaluates every combination of `k1` and `b` and returns the one with the
+/// highest `measure`, the first one tried among equals. `on_result` sees each
+/// combination as it is evaluated.
+pub fn grid_search(
+    k1: &ParameterRange,
+    b: &ParameterRange,
+    measure: Measure,
+    mut evaluate: impl FnMut(f32, f32) -> Metrics,
+    mut on_result: impl FnMut(&Tuning),
+) -> Option<Tuning> {
+    let mut best: Option<Tuning> = None;
+    for k1 in k1.values() {
+        for b in b.values() {
+            let tuning = Tuning {
+                k1,
+                b,
+                metrics: evaluate(k1, b),
+            };
+            on_result(&tuning);
+            if best.is_none_or(|best| measure.of(&tuning.metrics) > measure.of(&best.metrics)) {
+                best = Some(tuning);
+            }
+        }
+    }
+    best
+}
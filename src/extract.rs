@@ -0,0 +1,358 @@
+use crate::Bm25Error;
+use encoding_rs::{Encoding, WINDOWS_1252};
+use std::{collections::HashMap, path::Path};
+
+/// How much of a file is looked at for NUL bytes to tell binary files apart.
+const BINARY_SNIFF: usize = 8000;
+
+/// Turns the raw bytes of a file into the plain text that gets analyzed.
+pub trait Extractor: Send + Sync {
+    fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error>;
+
+    /// Whether the extractor takes text, which [`Extractors`] then decodes to
+    /// UTF-8 before passing it on. Extractors of binary formats keep the
+    /// default.
+    fn reads_text(&self) -> bool {
+        false
+    }
+}
+
+/// Decodes text in an unknown encoding: by its byte order mark if it has
+/// one, as UTF-8 if it is valid UTF-8, and otherwise as `fallback`, with
+/// undecodable bytes replaced. Content with NUL bytes and no UTF-16 byte order
+/// mark is taken to be binary.
+pub fn decode(path: &str, bytes: &[u8], fallback: &'static Encoding) -> Result<String, Bm25Error> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return Ok(text.into_owned());
+    }
+    if bytes[..bytes.len().min(BINARY_SNIFF)].contains(&0) {
+        return Err(Bm25Error::Binary {
+            path: path.to_string(),
+        });
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.to_string()),
+        Err(_) => Ok(fallback.decode_without_bom_handling(bytes).0.into_owned()),
+    }
+}
+
+fn utf8(path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| Bm25Error::Encoding {
+        path: path.to_string(),
+    })
+}
+
+/// UTF-8 text, unchanged.
+pub struct PlainText;
+
+impl Extractor for PlainText {
+    fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+        utf8(path, bytes)
+    }
+
+    fn reads_text(&self) -> bool {
+        true
+    }
+}
+
+/// Drops tags, comments and the contents of `script` and `style` elements,
+/// and decodes the common character entities.
+pub struct Html;
+
+/// Tags that separate blocks of text and so end a line.
+fn is_block(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "div"
+            | "br"
+            | "li"
+            | "tr"
+            | "td"
+            | "th"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "title"
+            | "pre"
+            | "blockquote"
+            | "section"
+            | "article"
+            | "header"
+            | "footer"
+    )
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|&end| end <= 10) else {
+            decoded.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+impl Html {
+    pub fn strip(html: &str) -> String {
+        let mut text = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            let Some(end) = rest.find('>') else {
+                rest = "";
+                break;
+            };
+            let tag = &rest[1..end];
+            let opening = !tag.starts_with('/') && !tag.ends_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or("")
+                .to_ascii_lowercase();
+            rest = &rest[end + 1..];
+            if opening && (name == "script" || name == "style") {
+                // ASCII lowercasing keeps byte offsets valid in `rest`.
+                let closing = format!("</{}", name);
+                rest = match rest.to_ascii_lowercase().find(&closing) {
+                    Some(close) => rest[close..]
+                        .find('>')
+                        .map_or("", |end| &rest[close + end + 1..]),
+                    None => "",
+                };
+            }
+            text.push(if is_block(&name) { '\n' } else { ' ' });
+        }
+        text.push_str(rest);
+        decode_entities(&text)
+    }
+}
+
+impl Extractor for Html {
+    fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+        Ok(Html::strip(&utf8(path, bytes)?))
+    }
+
+    fn reads_text(&self) -> bool {
+        true
+    }
+}
+
+/// Removes Markdown syntax (headings, emphasis, code fences, link targets)
+/// while keeping the words. A leading front matter block is kept as is.
+pub struct Markdown;
+
+impl Markdown {
+    fn strip_line(line: &str) -> String {
+        let line = line.trim_start();
+        let line = line
+            .trim_start_matches('#')
+            .trim_start_matches('>')
+            .trim_start();
+        let line = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|marker| line.strip_prefix(marker))
+            .unwrap_or(line);
+        let mut text = String::with_capacity(line.len());
+        let mut chars = line.chars().peekable();
+        let mut previous = ' ';
+        while let Some(c) = chars.next() {
+            match c {
+                '!' if chars.peek() == Some(&'[') => {}
+                '(' if previous == ']' => {
+                    // The target of `[text](url)`.
+                    for c in chars.by_ref() {
+                        if c == ')' {
+                            break;
+                        }
+                    }
+                }
+                '[' | ']' | '*' | '_' | '`' => {}
+                c => text.push(c),
+            }
+            previous = c;
+        }
+        text
+    }
+
+    pub fn strip(markdown: &str) -> String {
+        let (front_matter, body) = match markdown
+            .strip_prefix("---\n")
+            .and_then(|rest| rest.find("\n---\n").map(|end| end + 4 + "\n---\n".len()))
+        {
+            Some(end) => markdown.split_at(end),
+            None => ("", markdown),
+        };
+        let mut text = front_matter.to_string();
+        for line in body.lines() {
+            if line.trim_start().starts_with("```") {
+                continue;
+            }
+            text.push_str(&Markdown::strip_line(line));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Extractor for Markdown {
+    fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+        Ok(Markdown::strip(&utf8(path, bytes)?))
+    }
+
+    fn reads_text(&self) -> bool {
+        true
+    }
+}
+
+/// Text from the content streams of a PDF.
+#[cfg(feature = "pdf")]
+pub struct Pdf;
+
+#[cfg(feature = "pdf")]
+impl Extractor for Pdf {
+    fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+        pdf_extract::extract_text_from_mem(bytes).map_err(|err| Bm25Error::Extraction {
+            path: path.to_string(),
+            message: err.to_string(),
+        })
+    }
+}
+
+/// Reports PDFs as unsupported when the `pdf` feature is off.
+#[cfg(not(feature = "pdf"))]
+struct Pdf;
+
+#[cfg(not(feature = "pdf"))]
+impl Extractor for Pdf {
+    fn extract(&self, path: &str, _bytes: &[u8]) -> Result<String, Bm25Error> {
+        Err(Bm25Error::Extraction {
+            path: path.to_string(),
+            message: "built without the `pdf` feature".to_string(),
+        })
+    }
+}
+
+/// Picks an extractor by lowercase file extension, falling back to sniffing
+/// the content for PDF and HTML and then to plain text. Text is decoded with
+/// [`decode`] first, so files that are not UTF-8 are read with the fallback
+/// encoding, Windows-1252 (a superset of Latin-1) unless set otherwise.
+pub struct Extractors {
+    by_extension: HashMap<String, Box<dyn Extractor>>,
+    fallback_encoding: &'static Encoding,
+}
+
+impl Default for Extractors {
+    fn default() -> Self {
+        Extractors::new()
+            .with("html", Box::new(Html))
+            .with("htm", Box::new(Html))
+            .with("xhtml", Box::new(Html))
+            .with("md", Box::new(Markdown))
+            .with("markdown", Box::new(Markdown))
+            .with("pdf", Box::new(Pdf))
+    }
+}
+
+impl Extractors {
+    /// No extractors registered; everything is read as plain text.
+    pub fn new() -> Extractors {
+        Extractors {
+            by_extension: HashMap::new(),
+            fallback_encoding: WINDOWS_1252,
+        }
+    }
+
+    /// Sets the encoding of text that is neither UTF-8 nor marked with a byte
+    /// order mark.
+    pub fn with_fallback_encoding(mut self, encoding: &'static Encoding) -> Extractors {
+        self.fallback_encoding = encoding;
+        self
+    }
+
+    fn extract_with(
+        &self,
+        extractor: &dyn Extractor,
+        path: &str,
+        bytes: &[u8],
+    ) -> Result<String, Bm25Error> {
+        if !extractor.reads_text() {
+            return extractor.extract(path, bytes);
+        }
+        let text = decode(path, bytes, self.fallback_encoding)?;
+        extractor.extract(path, text.as_bytes())
+    }
+
+    /// Registers (or replaces) the extractor for a file extension.
+    pub fn with(mut self, extension: &str, extractor: Box<dyn Extractor>) -> Extractors {
+        self.by_extension
+            .insert(extension.to_lowercase(), extractor);
+        self
+    }
+
+    pub fn extract(&self, path: &str, bytes: &[u8]) -> Result<String, Bm25Error> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        if let Some(extractor) = extension.and_then(|e| self.by_extension.get(&e)) {
+            return self.extract_with(extractor.as_ref(), path, bytes);
+        }
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(256)]).to_lowercase();
+        if bytes.starts_with(b"%PDF-") {
+            Pdf.extract(path, bytes)
+        } else if head.trim_start().starts_with("<!doctype html") || head.contains("<html") {
+            self.extract_with(&Html, path, bytes)
+        } else {
+            self.extract_with(&PlainText, path, bytes)
+        }
+    }
+
+    /// Reads a file and extracts its text.
+    pub fn read(&self, path: &str) -> Result<String, Bm25Error> {
+        let bytes = std::fs::read(path).map_err(|source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        self.extract(path, &bytes)
+    }
+}
@@ -0,0 +1,49 @@
+//! Counts of the values of metadata keys over the documents a search
+//! matches, e.g. how many hits each file extension or directory has, to show
+//! alongside the ranking and to narrow it with filters.
+use crate::Metadata;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A value of a facet's key and how many matching documents have it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// The values one metadata key takes over the matching documents, most
+/// common first. Documents without the key are not counted. Values are
+/// counted whole, as filters compare them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Facet {
+    pub key: String,
+    pub counts: Vec<FacetCount>,
+}
+
+/// Counts the values of each of `keys`, by the names [`Metadata::get`]
+/// takes, over `documents`.
+pub fn count<'a>(keys: &[String], documents: impl Iterator<Item = &'a Metadata>) -> Vec<Facet> {
+    let mut counts: Vec<HashMap<String, usize>> = vec![HashMap::new(); keys.len()];
+    for metadata in documents {
+        for (key, counts) in keys.iter().zip(&mut counts) {
+            if let Some(value) = metadata.get(key) {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+    }
+    keys.iter()
+        .zip(counts)
+        .map(|(key, counts)| {
+            let mut counts: Vec<FacetCount> = counts
+                .into_iter()
+                .map(|(value, count)| FacetCount { value, count })
+                .collect();
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+            Facet {
+                key: key.clone(),
+                counts,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,106 @@
+use crate::query::{Occur, Query};
+use std::collections::HashMap;
+
+/// Settings of pseudo-relevance feedback: the query is run once, the best
+/// `docs` hits are assumed relevant, and the query is expanded with the
+/// `terms` words most likely under their language model (RM3) before it is
+/// run again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feedback {
+    pub docs: usize,
+    pub terms: usize,
+    /// How much of the expanded query's weight stays with the original
+    /// query, from 0 to 1.
+    pub original_weight: f32,
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Feedback {
+            docs: 10,
+            terms: 20,
+            original_weight: 0.5,
+        }
+    }
+}
+
+impl Feedback {
+    /// Parses `10`, `10:20` or `10:20:0.5` (feedback documents, expansion
+    /// terms and original query weight).
+    pub fn parse(value: &str) -> Option<Feedback> {
+        let mut parts = value.split(':');
+        let mut feedback = Feedback {
+            docs: parts.next()?.parse().ok()?,
+            ..Feedback::default()
+        };
+        if let Some(terms) = parts.next() {
+            feedback.terms = terms.parse().ok()?;
+        }
+        if let Some(weight) = parts.next() {
+            feedback.original_weight = weight.parse().ok()?;
+        }
+        let valid = feedback.docs > 0 && (0.0..=1.0).contains(&feedback.original_weight);
+        (valid && parts.next().is_none()).then_some(feedback)
+    }
+
+    /// The `terms` most likely words of the relevance model of `docs`, each
+    /// given as its score in the first pass and its term frequencies and
+    /// length. A document's share of the model is its score over the total,
+    /// and a word's probability within it is its frequency over the length.
+    /// The probabilities are renormalized over the words kept.
+    pub fn relevance_model<'a>(
+        &self,
+        docs: impl IntoIterator<Item = (f32, Vec<(&'a str, i32)>, i32)>,
+    ) -> Vec<(&'a str, f32)> {
+        let docs: Vec<_> = docs
+            .into_iter()
+            .filter(|(score, _, length)| *score > 0.0 && *length > 0)
+            .collect();
+        let total: f32 = docs.iter().map(|(score, _, _)| score).sum();
+        let mut model: HashMap<&str, f32> = HashMap::new();
+        for (score, terms, length) in docs {
+            for (term, tf) in terms {
+                *model.entry(term).or_default() += score / total * tf as f32 / length as f32;
+            }
+        }
+        let mut model: Vec<(&str, f32)> = model.into_iter().collect();
+        model.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        model.truncate(self.terms);
+        let kept: f32 = model.iter().map(|(_, p)| p).sum();
+        for (_, p) in &mut model {
+            *p /= kept;
+        }
+        model
+    }
+
+    /// Mixes `query` with the relevance model. The original query keeps
+    /// `original_weight`, and the expansion terms share the rest in
+    /// proportion to their probabilities, scaled by the number of original
+    /// terms so the two parts are comparable. Documents excluded by a `NOT`
+    /// clause of the query stay excluded.
+    pub fn expand(&self, query: Query, model: &[(&str, f32)]) -> Query {
+        if model.is_empty() {
+            return query;
+        }
+        let scale = query.weighted_terms().len() + query.weighted_phrases().len();
+        let scale = (1.0 - self.original_weight) * scale.max(1) as f32;
+        let mut clauses = vec![];
+        if let Query::Group(original) = &query {
+            clauses.extend(
+                original
+                    .iter()
+                    .filter(|(occur, _)| *occur == Occur::MustNot)
+                    .cloned(),
+            );
+        }
+        clauses.push((
+            Occur::Should,
+            Query::Boost(Box::new(query), self.original_weight),
+        ));
+        clauses.extend(model.iter().map(|&(term, p)| {
+            let term = Query::Term(term.to_string());
+            (Occur::Should, Query::Boost(Box::new(term), scale * p))
+        }));
+        Query::Group(clauses)
+    }
+}
@@ -0,0 +1,147 @@
+//! A C ABI for embedding the engine, compiled in with the `ffi` feature. Its
+//! header is `include/fulltext.h`, regenerated with
+//! `cbindgen --config cbindgen.toml --output include/fulltext.h`:
+//!
+//! ```c
+//! Bm25Index *index = bm25_index_new();
+//! bm25_index_add(index, "the quick brown fox");
+//! Bm25Hit hits[10];
+//! size_t count = bm25_index_search(index, "fox", hits, 10);
+//! bm25_index_free(index);
+//! ```
+//!
+//! Strings are NUL-terminated UTF-8. An index must not be used from several
+//! threads at once while documents are being added.
+use crate::BM25Index;
+use std::{
+    ffi::{c_char, CStr},
+    slice,
+};
+
+/// An index, owned by the caller between `bm25_index_new` and
+/// `bm25_index_free`.
+pub struct Bm25Index {
+    index: BM25Index,
+}
+
+/// One search result: the number `bm25_index_add` returned for the document,
+/// and its score.
+#[repr(C)]
+pub struct Bm25Hit {
+    pub doc: u64,
+    pub score: f32,
+}
+
+unsafe fn text<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
+
+/// Creates an empty index with the default analyzer and parameters.
+#[no_mangle]
+pub extern "C" fn bm25_index_new() -> *mut Bm25Index {
+    Box::into_raw(Box::new(Bm25Index {
+        index: BM25Index::new(),
+    }))
+}
+
+/// Sets the term frequency saturation `k1` and length normalization `b`.
+///
+/// # Safety
+///
+/// `index` must come from `bm25_index_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_set_parameters(index: *mut Bm25Index, k1: f32, b: f32) {
+    if let Some(index) = index.as_mut() {
+        index.index.set_parameters(k1, b);
+    }
+}
+
+/// Analyzes and adds a document, returning its number, or -1 if `text` is
+/// null or not UTF-8.
+///
+/// # Safety
+///
+/// `index` must come from `bm25_index_new` and not have been freed, and
+/// `text` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_add(index: *mut Bm25Index, text: *const c_char) -> i64 {
+    match (index.as_mut(), self::text(text)) {
+        (Some(index), Some(text)) => index.index.add_text(text) as i64,
+        _ => -1,
+    }
+}
+
+/// Removes the document with the given number, returning whether it was in
+/// the index.
+///
+/// # Safety
+///
+/// `index` must come from `bm25_index_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_remove(index: *mut Bm25Index, doc: u64) -> bool {
+    match index.as_mut() {
+        Some(index) if (doc as usize) < index.index.slot_count() => {
+            index.index.remove_document(doc as usize)
+        }
+        _ => false,
+    }
+}
+
+/// Number of documents in the index.
+///
+/// # Safety
+///
+/// `index` must come from `bm25_index_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_len(index: *const Bm25Index) -> usize {
+    index.as_ref().map_or(0, |index| index.index.doc_count())
+}
+
+/// Writes the `k` best documents for `query` into `hits`, best first, and
+/// returns how many were written.
+///
+/// # Safety
+///
+/// `index` must come from `bm25_index_new` and not have been freed, `query`
+/// must be null or a NUL-terminated string, and `hits` must have room for `k`
+/// results.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_search(
+    index: *const Bm25Index,
+    query: *const c_char,
+    hits: *mut Bm25Hit,
+    k: usize,
+) -> usize {
+    let (Some(index), Some(query)) = (index.as_ref(), text(query)) else {
+        return 0;
+    };
+    if hits.is_null() || k == 0 {
+        return 0;
+    }
+    let terms = index.index.analyze(query);
+    let terms: Vec<&str> = terms.iter().map(|term| term.as_str()).collect();
+    let ranked = index.index.rank_top_k(&terms, k);
+    let hits = slice::from_raw_parts_mut(hits, k);
+    for (hit, (doc, score)) in hits.iter_mut().zip(&ranked) {
+        *hit = Bm25Hit {
+            doc: *doc as u64,
+            score: *score,
+        };
+    }
+    ranked.len()
+}
+
+/// Frees an index. Null is ignored.
+///
+/// # Safety
+///
+/// `index` must be null or come from `bm25_index_new`, and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn bm25_index_free(index: *mut Bm25Index) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
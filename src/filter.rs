@@ -0,0 +1,105 @@
+use crate::Metadata;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on a document's metadata, e.g. `ext=md` or `size<10000`.
+/// Values that both parse as numbers are compared numerically, others as
+/// strings. Documents without the key never match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub key: String,
+    pub comparison: Comparison,
+    pub value: String,
+}
+
+/// Days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parses `2024-01-01` or `2024-01-01T12:30:00` (UTC) as seconds since the
+/// Unix epoch.
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once('T').unwrap_or((value, "00:00:00"));
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let hours = time.next()??;
+    let minutes = time.next().unwrap_or(Some(0))?;
+    let seconds = time.next().unwrap_or(Some(0))?;
+    Some(days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+impl Filter {
+    /// Parses `key=value`, `key!=value`, `key<value`, `key<=value`, `key>value`
+    /// or `key>=value`. Dates given for `mtime` are converted to its unit,
+    /// seconds since the Unix epoch.
+    pub fn parse(text: &str) -> Option<Filter> {
+        let start = text.find(['=', '!', '<', '>'])?;
+        let (key, rest) = text.split_at(start);
+        let (comparison, value) = [
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("=", Comparison::Equal),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .iter()
+        .find_map(|(operator, comparison)| Some((*comparison, rest.strip_prefix(operator)?)))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return None;
+        }
+        let value = match parse_timestamp(value.trim()) {
+            Some(timestamp) if key == "mtime" => timestamp.to_string(),
+            _ => value.trim().to_string(),
+        };
+        Some(Filter {
+            key: key.to_string(),
+            comparison,
+            value,
+        })
+    }
+
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let Some(actual) = metadata.get(&self.key) else {
+            return false;
+        };
+        let ordering = match (actual.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected),
+            _ => Some(actual.as_str().cmp(self.value.as_str())),
+        };
+        ordering.is_some_and(|ordering| self.comparison.accepts(ordering))
+    }
+}
@@ -0,0 +1,180 @@
+//! The header an index file starts with: the version of the file's format,
+//! so that an index written by another release is recognized rather than
+//! misread, and the settings the index was built with.
+use crate::{
+    analyzer::{AnalyzerConfig, Ngrams, TokenizerKind},
+    Bm25Error,
+};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{BufRead, Read, Write},
+};
+
+/// The first bytes of an index file with a header. A file without them was
+/// written before formats were versioned, and is in version 0.
+const MAGIC: &[u8; 8] = b"FTINDEX\0";
+
+/// The version of the format this release writes. Indexes in an older one
+/// are only read to be upgraded.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// [`AnalyzerConfig`] as versions before 2 wrote it, without stem exceptions.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalyzerConfigV1 {
+    tokenizer: TokenizerKind,
+    strip_punctuation: bool,
+    stopwords: HashSet<String>,
+    stemmer: Option<String>,
+    bigrams: bool,
+    ngrams: Vec<Ngrams>,
+}
+
+impl From<AnalyzerConfigV1> for AnalyzerConfig {
+    fn from(old: AnalyzerConfigV1) -> AnalyzerConfig {
+        AnalyzerConfig {
+            tokenizer: old.tokenizer,
+            strip_punctuation: old.strip_punctuation,
+            stopwords: old.stopwords,
+            stemmer: old.stemmer,
+            stem_exceptions: HashSet::new(),
+            bigrams: old.bigrams,
+            ngrams: old.ngrams,
+        }
+    }
+}
+
+/// The most a header takes, far more than any real one.
+const MAX_HEADER_BYTES: u64 = 1 << 20;
+
+/// Reads a value written by [`bincode::serialize_into`] from `input`, taking
+/// at most `limit` bytes, so that a corrupt length in a damaged or foreign
+/// file is an error rather than an attempt to allocate that much.
+pub fn deserialize_bounded<T: DeserializeOwned>(
+    input: impl Read,
+    limit: u64,
+) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+        .deserialize_from(input)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexHeader {
+    pub version: u32,
+    /// The release of this crate that wrote the index.
+    pub written_by: String,
+    pub analysis: AnalyzerConfig,
+    /// The scorer, by the name [`crate::scorer::by_name`] parses.
+    pub scorer: String,
+    pub k1: f32,
+    pub b: f32,
+}
+
+fn invalid(path: &str, message: impl ToString) -> Bm25Error {
+    Bm25Error::InvalidIndex {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+impl IndexHeader {
+    /// A header in the current format, written by this release.
+    pub fn new(analysis: AnalyzerConfig, scorer: &str, k1: f32, b: f32) -> IndexHeader {
+        IndexHeader {
+            version: FORMAT_VERSION,
+            written_by: env!("CARGO_PKG_VERSION").to_string(),
+            analysis,
+            scorer: scorer.to_string(),
+            k1,
+            b,
+        }
+    }
+
+    /// Writes the header to the start of an index file at `path`.
+    pub fn write(&self, out: &mut impl Write, path: &str) -> Result<(), Bm25Error> {
+        out.write_all(MAGIC).map_err(|source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        bincode::serialize_into(out, self).map_err(|err| invalid(path, err))
+    }
+
+    /// Reads the header at the start of the index file at `path`, leaving
+    /// `input` at what follows it. A file without one is left as it was and
+    /// gives `None`. Versions newer than this release writes are an error.
+    pub fn read(input: &mut impl BufRead, path: &str) -> Result<Option<IndexHeader>, Bm25Error> {
+        let start = input.fill_buf().map_err(|source| Bm25Error::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        if !start.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        input.consume(MAGIC.len());
+        // The version comes first, so a newer header is reported as such
+        // even if the rest of it is laid out differently.
+        let version: u32 =
+            deserialize_bounded(&mut *input, MAX_HEADER_BYTES).map_err(|err| invalid(path, err))?;
+        if version > FORMAT_VERSION {
+            return Err(Bm25Error::UnsupportedVersion {
+                path: path.to_string(),
+                version,
+            });
+        }
+        let (written_by, analysis, scorer, k1, b) = if version < 2 {
+            let (written_by, analysis, scorer, k1, b): (_, AnalyzerConfigV1, _, _, _) =
+                deserialize_bounded(input, MAX_HEADER_BYTES).map_err(|err| invalid(path, err))?;
+            (written_by, analysis.into(), scorer, k1, b)
+        } else {
+            deserialize_bounded(input, MAX_HEADER_BYTES).map_err(|err| invalid(path, err))?
+        };
+        Ok(Some(IndexHeader {
+            version,
+            written_by,
+            analysis,
+            scorer,
+            k1,
+            b,
+        }))
+    }
+}
+
+/// Checks that the index at `path`, built with `built` analysis, is searched
+/// or added to with `given`, since other analysis produces terms the index
+/// does not have, or has for other words.
+pub fn check_analysis(
+    path: &str,
+    built: &AnalyzerConfig,
+    given: &AnalyzerConfig,
+) -> Result<(), Bm25Error> {
+    let mut settings = vec![];
+    if built.tokenizer != given.tokenizer {
+        settings.push("tokenizer");
+    }
+    if built.strip_punctuation != given.strip_punctuation {
+        settings.push("punctuation stripping");
+    }
+    if built.stopwords != given.stopwords {
+        settings.push("stopwords");
+    }
+    if built.stemmer != given.stemmer {
+        settings.push("stemmer");
+    }
+    if built.stem_exceptions != given.stem_exceptions {
+        settings.push("stem exceptions");
+    }
+    if built.bigrams != given.bigrams || built.ngrams != given.ngrams {
+        settings.push("n-grams");
+    }
+    if settings.is_empty() {
+        return Ok(());
+    }
+    Err(Bm25Error::AnalyzerMismatch {
+        path: path.to_string(),
+        settings: settings.join(", "),
+    })
+}
@@ -0,0 +1,174 @@
+use crate::StoredIndex;
+use fulltext::{deadline::Deadline, Filter, SearchRequest};
+use proto::fulltext_server::{Fulltext, FulltextServer};
+use std::{
+    error::Error,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio_stream::Iter;
+use tonic::{transport::Server, Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("fulltext");
+}
+
+/// The gRPC service of proto/fulltext.proto over one index. Searches share
+/// it, while adding and deleting documents take it for themselves.
+struct Service {
+    stored: Arc<RwLock<StoredIndex>>,
+    /// How long a search may run before the hits it has are sent.
+    timeout: Option<Duration>,
+}
+
+type Hits = Iter<std::vec::IntoIter<Result<proto::SearchHit, Status>>>;
+
+impl Service {
+    /// Runs `work` on the index on a thread for blocking work, so that waiting
+    /// for the lock or a long search does not hold up the runtime's threads.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&RwLock<StoredIndex>) -> Result<T, Status> + Send + 'static,
+    ) -> Result<T, Status> {
+        let stored = Arc::clone(&self.stored);
+        tokio::task::spawn_blocking(move || work(&stored))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+    }
+}
+
+#[tonic::async_trait]
+impl Fulltext for Service {
+    type SearchStream = Hits;
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<Hits>, Status> {
+        let request = request.into_inner();
+        let filters = request
+            .filters
+            .iter()
+            .map(|filter| {
+                Filter::parse(filter)
+                    .ok_or_else(|| Status::invalid_argument(format!("invalid filter: {}", filter)))
+            })
+            .collect::<Result<_, _>>()?;
+        let deadline = self.timeout.map(Deadline::after).unwrap_or_default();
+        let hits = self
+            .blocking(move |stored| {
+                let stored = stored.read().unwrap();
+                let limit = match request.limit {
+                    0 => stored.index.slot_count(),
+                    limit => limit as usize,
+                };
+                let offset = request.offset as usize;
+                let request = SearchRequest {
+                    query: request.query,
+                    filters,
+                    offset,
+                    limit,
+                    min_score: request.min_score,
+                    facets: vec![],
+                };
+                let ranks = stored.index.search_until(&request, &deadline);
+                let hits: Vec<_> = ranks
+                    .hits
+                    .iter()
+                    .enumerate()
+                    .map(|(rank, &(doc, score))| {
+                        let metadata = stored.index.metadata(doc as usize);
+                        Ok(proto::SearchHit {
+                            rank: (offset + rank + 1) as u32,
+                            id: stored.index.doc_id(doc as usize),
+                            path: stored.name(doc as usize).to_string(),
+                            score,
+                            title: metadata.title.clone(),
+                            values: metadata.values.clone().into_iter().collect(),
+                        })
+                    })
+                    .collect();
+                Ok(hits)
+            })
+            .await?;
+        Ok(Response::new(tokio_stream::iter(hits)))
+    }
+
+    async fn add_document(
+        &self,
+        request: Request<proto::AddDocumentRequest>,
+    ) -> Result<Response<proto::AddDocumentResponse>, Status> {
+        let request = request.into_inner();
+        let response = self
+            .blocking(move |stored| {
+                let mut stored = stored.write().unwrap();
+                let path = request
+                    .path
+                    .unwrap_or_else(|| format!("document-{}", stored.index.slot_count()));
+                let doc = stored.add_text(&path, &request.text);
+                let id = stored.index.doc_id(doc);
+                Ok(proto::AddDocumentResponse { id, path })
+            })
+            .await?;
+        Ok(Response::new(response))
+    }
+
+    async fn delete_document(
+        &self,
+        request: Request<proto::DeleteDocumentRequest>,
+    ) -> Result<Response<proto::DeleteDocumentResponse>, Status> {
+        let id = request.into_inner().id;
+        let deleted = self
+            .blocking(move |stored| {
+                let mut stored = stored.write().unwrap();
+                Ok(match stored.index.doc_index(id) {
+                    Some(doc) => {
+                        stored.texts.remove(&id);
+                        stored.index.remove_document(doc)
+                    }
+                    None => false,
+                })
+            })
+            .await?;
+        Ok(Response::new(proto::DeleteDocumentResponse { deleted }))
+    }
+
+    async fn stats(
+        &self,
+        _: Request<proto::StatsRequest>,
+    ) -> Result<Response<proto::StatsResponse>, Status> {
+        let stats = self
+            .blocking(|stored| Ok(stored.read().unwrap().index.stats()))
+            .await?;
+        Ok(Response::new(proto::StatsResponse {
+            documents: stats.doc_count as u64,
+            total_length: stats.total_doc_length,
+            average_length: stats.avg_doc_length,
+            vocabulary_size: stats.vocabulary_size as u64,
+        }))
+    }
+}
+
+/// Serves the gRPC service on `addr` until the process is stopped. Unlike the
+/// HTTP server, requests are handled concurrently, and search hits are
+/// streamed one message each, so a large result set is never one message.
+/// A search that runs past `timeout` streams the hits it has.
+pub fn serve(
+    stored: StoredIndex,
+    addr: &str,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let addr = addr.parse()?;
+    let service = Service {
+        stored: Arc::new(RwLock::new(stored)),
+        timeout,
+    };
+    let runtime = tokio::runtime::Runtime::new()?;
+    println!("Listening for gRPC on {}", addr);
+    runtime.block_on(
+        Server::builder()
+            .add_service(FulltextServer::new(service))
+            .serve(addr),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use serde_json::Value;
+use std::{collections::HashMap, error::Error, fs::File, io::BufRead, io::BufReader};
+
+/// One document of a JSONL or CSV corpus.
+pub struct Record {
+    pub id: String,
+    pub fields: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Which JSON keys or CSV columns hold the document id and body. Every other
+/// key or column becomes a field of the same name.
+pub struct Columns {
+    pub id: String,
+    pub body: String,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            id: "id".to_string(),
+            body: "body".to_string(),
+        }
+    }
+}
+
+impl Columns {
+    /// Applies a `--column id=doc_id` or `--column body=text` mapping.
+    pub fn set(&mut self, mapping: &str) -> Option<()> {
+        let (role, column) = mapping.split_once('=')?;
+        match role {
+            "id" => self.id = column.to_string(),
+            "body" => self.body = column.to_string(),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Builds a record from its values, numbering it `position` (from 1) if it
+    /// has no id. A record without a body is an error naming the columns it
+    /// lacks, as it is more likely read with the wrong columns than empty.
+    fn record(&self, position: usize, values: Vec<(String, String)>) -> Result<Record, String> {
+        let mut id = None;
+        let mut body = None;
+        let mut fields = vec![];
+        for (key, value) in values {
+            if key == self.id {
+                id = Some(value);
+            } else if key == self.body {
+                body = Some(value);
+            } else {
+                fields.push((key.to_lowercase(), value));
+            }
+        }
+        let Some(body) = body else {
+            let missing = match id {
+                Some(_) => format!("body column `{}`", self.body),
+                None => format!("body column `{}` or id column `{}`", self.body, self.id),
+            };
+            return Err(format!(
+                "record {} has no {}; name the columns with --column body=<column> and \
+                 --column id=<column>",
+                position, missing
+            ));
+        };
+        Ok(Record {
+            id: id.unwrap_or_else(|| position.to_string()),
+            fields,
+            body,
+        })
+    }
+}
+
+fn json_text(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text),
+        Value::Array(values) => Some(
+            values
+                .into_iter()
+                .filter_map(json_text)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        value => Some(value.to_string()),
+    }
+}
+
+fn read_jsonl(path: &str, columns: &Columns) -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut records = vec![];
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: HashMap<String, Value> = serde_json::from_str(&line)
+            .map_err(|err| format!("{} line {}: {}", path, number + 1, err))?;
+        let values = object
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, json_text(value)?)))
+            .collect();
+        let record = columns
+            .record(records.len() + 1, values)
+            .map_err(|err| format!("{} line {}: {}", path, number + 1, err))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn read_csv(path: &str, columns: &Columns) -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let mut records = vec![];
+    for row in reader.records() {
+        let row = row?;
+        let values = headers
+            .iter()
+            .zip(row.iter())
+            .map(|(header, value)| (header.to_string(), value.to_string()))
+            .collect();
+        let record = columns
+            .record(records.len() + 1, values)
+            .map_err(|err| format!("{}: {}", path, err))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Reads a `.csv` file, or JSON Lines otherwise.
+pub fn read_records(path: &str, columns: &Columns) -> Result<Vec<Record>, Box<dyn Error>> {
+    if path.to_lowercase().ends_with(".csv") {
+        read_csv(path, columns)
+    } else {
+        read_jsonl(path, columns)
+    }
+}
@@ -0,0 +1,2598 @@
+pub mod analyzer;
+pub mod cache;
+pub mod chunk;
+pub mod collections;
+pub mod corpus;
+pub mod deadline;
+pub mod disk;
+pub mod error;
+pub mod eval;
+pub mod extract;
+pub mod facet;
+pub mod feedback;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod format;
+pub mod metadata;
+pub mod postings;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod rerank;
+pub mod scorer;
+pub mod segment;
+pub mod shard;
+pub mod snapshot;
+pub mod snippet;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stopwords;
+pub mod storage;
+pub mod synonyms;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod writer;
+
+use analyzer::{Analyzer, ChainAnalyzer};
+use cache::{CacheStats, QueryCache};
+use deadline::Deadline;
+pub use error::Bm25Error;
+use extract::Extractors;
+use facet::Facet;
+use feedback::Feedback;
+pub use filter::Filter;
+pub use metadata::{DocId, Metadata};
+pub use postings::Posting;
+use postings::{PostingList, Terms};
+use query::{Occur, Query};
+use rayon::prelude::*;
+use rerank::{Candidate, Fusion, Reranker};
+use scorer::{IdfMode, Scorer, TermStats};
+use serde::{Deserialize, Serialize};
+use snippet::Span;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    ops::Range,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::{Duration, Instant},
+};
+use synonyms::Synonyms;
+
+/// How many dictionary terms a `prefix*` query expands to at most; the most
+/// frequent ones are kept.
+pub const MAX_PREFIX_EXPANSIONS: usize = 64;
+
+/// How many of a document's terms [`BM25Index::more_like_this`] searches
+/// for.
+pub const MORE_LIKE_THIS_TERMS: usize = 25;
+
+/// How many candidates are scored together, a query term at a time, over
+/// arrays of their term frequencies and lengths.
+pub const SCORE_BATCH: usize = 1024;
+
+/// How many documents [`BM25Index::add_stream`] analyzes at a time.
+pub const STREAM_BATCH: usize = 256;
+
+/// How far [`BM25Index::add_stream_with_progress`] has got, reported after
+/// each batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Documents added so far.
+    pub documents: usize,
+    /// Bytes of document text analyzed so far.
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+/// An owned BM25 index over tokenized documents, addressed by insertion order.
+/// Each document also has a stable [`DocId`] and [`Metadata`].
+#[derive(Serialize, Deserialize)]
+pub struct BM25Index {
+    k1: f32,
+    b: f32,
+    idf_mode: IdfMode,
+    doc_lengths: Vec<i32>,
+    total_doc_length: i32,
+    avg_doc_length: i32,
+    doc_count: i32,
+    /// Each document's term ids and frequencies, sorted by term id.
+    doc_terms: Vec<Vec<(u32, i32)>>,
+    live: Vec<bool>,
+    /// Documents deleted with [`BM25Index::delete_document`], which keep their
+    /// postings until the index is compacted but no longer count towards the
+    /// statistics.
+    deleted: usize,
+    terms: Terms,
+    /// Compressed postings, indexed by term id.
+    postings: Vec<PostingList>,
+    /// The terms some live document contains, in order for prefix lookups.
+    dictionary: BTreeSet<String>,
+    titles: Vec<Vec<String>>,
+    title_bonus: f32,
+    length_adjustment: LengthAdjustment,
+    fields: BTreeMap<String, Field>,
+    field_boosts: HashMap<String, f32>,
+    /// Replacements of `b` for some fields in BM25F.
+    field_b: HashMap<String, f32>,
+    /// The metadata value a document's score is multiplied by.
+    boost_key: Option<String>,
+    doc_ids: Vec<DocId>,
+    next_doc_id: DocId,
+    metadata: Vec<Metadata>,
+    #[serde(skip, default = "default_analyzer")]
+    analyzer: Arc<dyn Analyzer>,
+    #[serde(skip, default = "default_scorer")]
+    scorer: Arc<dyn Scorer>,
+    /// Rankings of recent queries, dropped whenever the index changes.
+    #[serde(skip, default = "default_cache")]
+    cache: Mutex<QueryCache<RankedResults>>,
+    #[serde(skip)]
+    field_doc_freqs: Mutex<HashMap<String, i32>>,
+    /// Analyzed synonyms and the weight of an expansion; set per session.
+    #[serde(skip)]
+    synonyms: HashMap<String, Vec<Vec<String>>>,
+    #[serde(skip)]
+    synonym_weight: f32,
+    /// Weight of the bonus for query terms that occur close together; set per
+    /// session.
+    #[serde(skip)]
+    proximity_weight: f32,
+    /// Pseudo-relevance feedback that searches expand their query with, if
+    /// any; set per session.
+    #[serde(skip)]
+    feedback: Option<Feedback>,
+}
+
+/// The tokens of one named field, e.g. `title`, for every document.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Field {
+    tf: Vec<HashMap<String, i32>>,
+    lengths: Vec<i32>,
+    total_length: i32,
+    docs: HashMap<String, HashSet<i32>>,
+}
+
+impl Field {
+    fn clear(&mut self, doc_index: usize) {
+        let Some(counts) = self.tf.get_mut(doc_index) else {
+            return;
+        };
+        for term in std::mem::take(counts).keys() {
+            if let Some(docs) = self.docs.get_mut(term) {
+                docs.remove(&(doc_index as i32));
+                if docs.is_empty() {
+                    self.docs.remove(term);
+                }
+            }
+        }
+        self.total_length -= self.lengths[doc_index];
+        self.lengths[doc_index] = 0;
+    }
+
+    /// Keeps only the documents at `kept`, renumbered by `new_doc`.
+    fn compact(&mut self, kept: &[usize], new_doc: &[Option<i32>]) {
+        self.tf = kept
+            .iter()
+            .map(|&doc| self.tf.get_mut(doc).map(std::mem::take).unwrap_or_default())
+            .collect();
+        self.lengths = kept
+            .iter()
+            .map(|&doc| self.lengths.get(doc).copied().unwrap_or(0))
+            .collect();
+        self.total_length = self.lengths.iter().sum();
+        for docs in self.docs.values_mut() {
+            *docs = docs
+                .iter()
+                .filter_map(|&doc| new_doc[doc as usize])
+                .collect();
+        }
+        self.docs.retain(|_, docs| !docs.is_empty());
+    }
+
+    fn tf(&self, term: &str, doc_index: usize) -> i32 {
+        self.tf
+            .get(doc_index)
+            .and_then(|counts| counts.get(term))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Positions of each distinct term of the current query, by document, for
+/// the proximity bonus.
+struct Proximity {
+    positions: Vec<HashMap<i32, Vec<i32>>>,
+}
+
+/// The length of the shortest run of tokens that contains a position from
+/// each of `positions`.
+fn min_window(positions: &[&[i32]]) -> i32 {
+    let mut events: Vec<(i32, usize)> = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(term, positions)| positions.iter().map(move |&position| (position, term)))
+        .collect();
+    events.sort_unstable();
+    let mut counts = vec![0; positions.len()];
+    let mut covered = 0;
+    let mut start = 0;
+    let mut shortest = i32::MAX;
+    for &(end, term) in &events {
+        if counts[term] == 0 {
+            covered += 1;
+        }
+        counts[term] += 1;
+        while covered == positions.len() {
+            let (first, first_term) = events[start];
+            shortest = shortest.min(end - first + 1);
+            counts[first_term] -= 1;
+            if counts[first_term] == 0 {
+                covered -= 1;
+            }
+            start += 1;
+        }
+    }
+    shortest
+}
+
+/// Per-document frequency of a phrase, or of a `field:term` term, in the
+/// current query.
+struct PhraseStats {
+    frequencies: HashMap<i32, i32>,
+    collection_freq: i32,
+    weight: f32,
+    /// The field the frequencies were counted in, if not the body.
+    field: Option<String>,
+}
+
+/// Query terms paired with the weight their scores are multiplied by.
+type WeightedTerms<'a> = [(&'a str, f32)];
+
+fn unweighted<'a>(query: &[&'a str]) -> Vec<(&'a str, f32)> {
+    query.iter().map(|term| (*term, 1.0)).collect()
+}
+
+/// Orders hits so that a greater value ranks higher: by score, then by lower
+/// document index.
+#[derive(Debug, Clone, Copy)]
+struct RankedDoc {
+    score: f32,
+    doc: i32,
+}
+
+impl Ord for RankedDoc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.doc.cmp(&self.doc))
+    }
+}
+
+impl PartialOrd for RankedDoc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankedDoc {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedDoc {}
+
+/// How one query term or phrase contributed to a document's score. `idf` is
+/// `None` for scorers without one, e.g. Dirichlet smoothing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermExplanation {
+    pub term: String,
+    /// What the term's score was multiplied by, e.g. for a synonym.
+    pub weight: f32,
+    pub tf: i32,
+    pub doc_freq: i32,
+    pub idf: Option<f32>,
+    pub length_norm: f32,
+    pub score: f32,
+}
+
+/// A document's score broken down as returned by [`BM25Index::explain`]:
+/// `score` is the sum of the term scores, times `length_factor` and
+/// `doc_boost`, plus `title_bonus` and `proximity_bonus`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub doc: i32,
+    pub terms: Vec<TermExplanation>,
+    pub length_factor: f32,
+    pub doc_boost: f32,
+    pub title_bonus: f32,
+    pub proximity_bonus: f32,
+    pub score: f32,
+}
+
+/// A query string, as typed by a user, restricted to the documents whose
+/// metadata matches every filter. `offset` skips that many of the best hits,
+/// so that consecutive requests page through the ranking. `facets` names
+/// metadata keys whose values are counted over every matching document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRequest {
+    pub query: String,
+    pub filters: Vec<Filter>,
+    pub offset: usize,
+    pub limit: usize,
+    /// Hits scoring below it are dropped. Without one, hits scoring zero or
+    /// below are, as they match nothing the query asks for or only terms in
+    /// half or more of the documents.
+    pub min_score: Option<f32>,
+    pub facets: Vec<String>,
+}
+
+impl SearchRequest {
+    /// Whether a hit with `score` is worth returning.
+    pub fn keeps(&self, score: f32) -> bool {
+        keeps_score(self.min_score, score)
+    }
+
+    /// Identifies the results of the request once its query is analyzed or
+    /// parsed, so that spellings analyzing to the same terms share them.
+    fn cache_key(&self, query: &impl std::fmt::Debug) -> String {
+        format!(
+            "{:?} {:?} {} {} {:?}",
+            query, self.filters, self.offset, self.limit, self.min_score
+        )
+    }
+}
+
+/// Whether a hit with `score` passes `min_score`, as in
+/// [`SearchRequest::min_score`].
+pub fn keeps_score(min_score: Option<f32>, score: f32) -> bool {
+    match min_score {
+        Some(min_score) => score >= min_score,
+        None => score > 0.0,
+    }
+}
+
+/// The hits from `offset` on, at most `limit` of them.
+pub(crate) fn page<T>(mut hits: Vec<T>, offset: usize, limit: usize) -> Vec<T> {
+    hits.drain(..offset.min(hits.len()));
+    hits.truncate(limit);
+    hits
+}
+
+/// Sorts scored documents best first, ties broken by document index.
+fn rank_hits(mut hits: Vec<(i32, f32)>) -> Vec<(i32, f32)> {
+    let _span = tracing::debug_span!("rank", hits = hits.len()).entered();
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    hits
+}
+
+/// Corpus-wide statistics over the live documents.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexStats {
+    pub doc_count: usize,
+    /// Total number of tokens.
+    pub total_doc_length: u64,
+    pub avg_doc_length: f32,
+    /// Number of distinct terms.
+    pub vocabulary_size: usize,
+    /// Documents deleted but not yet compacted away, whose postings and terms
+    /// are still stored but which are no longer counted in the documents and
+    /// lengths above.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub deleted: usize,
+    /// The query cache of an in-memory index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStats>,
+}
+
+impl IndexStats {
+    fn new(doc_count: i32, total_doc_length: i32, vocabulary_size: usize) -> IndexStats {
+        IndexStats {
+            doc_count: doc_count as usize,
+            total_doc_length: total_doc_length as u64,
+            avg_doc_length: match doc_count {
+                0 => 0.0,
+                count => total_doc_length as f32 / count as f32,
+            },
+            vocabulary_size,
+            deleted: 0,
+            cache: None,
+        }
+    }
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// How [`BM25Index::top_terms`] orders the vocabulary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermOrder {
+    /// Most documents first.
+    DocFreq,
+    /// Highest IDF, i.e. rarest, first.
+    Idf,
+}
+
+impl TermOrder {
+    pub fn parse(value: &str) -> Option<TermOrder> {
+        match value {
+            "df" => Some(TermOrder::DocFreq),
+            "idf" => Some(TermOrder::Idf),
+            _ => None,
+        }
+    }
+}
+
+/// One term of the vocabulary and how it is spread over the documents.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermInfo {
+    pub term: String,
+    pub doc_freq: i32,
+    pub collection_freq: i32,
+    pub idf: f32,
+}
+
+/// The first `n` of `terms` in `order`, ties broken alphabetically.
+pub(crate) fn top_terms(mut terms: Vec<TermInfo>, order: TermOrder, n: usize) -> Vec<TermInfo> {
+    terms.sort_by(|a, b| {
+        let by_order = match order {
+            TermOrder::DocFreq => b.doc_freq.cmp(&a.doc_freq),
+            TermOrder::Idf => b.idf.total_cmp(&a.idf),
+        };
+        by_order.then_with(|| a.term.cmp(&b.term))
+    });
+    terms.truncate(n);
+    terms
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchHit {
+    pub index: i32,
+    pub score: f32,
+}
+
+/// Post-scoring multiplier `(1 + x)^-weight`, where `x` is the document length
+/// relative to the average (`Linear`) or `ln(1 + length)` (`Log`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LengthAdjustment {
+    None,
+    Linear(f32),
+    Log(f32),
+}
+
+impl LengthAdjustment {
+    pub fn parse(value: &str) -> Option<LengthAdjustment> {
+        if value == "none" {
+            return Some(LengthAdjustment::None);
+        }
+        let (kind, weight) = value.split_once(':')?;
+        let weight: f32 = weight.parse().ok()?;
+        match kind {
+            "linear" => Some(LengthAdjustment::Linear(weight)),
+            "log" => Some(LengthAdjustment::Log(weight)),
+            _ => None,
+        }
+    }
+
+    pub fn factor(&self, doc_length: i32, avg_doc_length: i32) -> f32 {
+        match *self {
+            LengthAdjustment::None => 1.0,
+            LengthAdjustment::Linear(weight) => {
+                (1.0 + doc_length as f32 / avg_doc_length.max(1) as f32).powf(-weight)
+            }
+            LengthAdjustment::Log(weight) => (1.0 + (1.0 + doc_length as f32).ln()).powf(-weight),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedResults {
+    pub hits: Vec<(i32, f32)>,
+    pub all_tied: bool,
+    /// Whether the search passed its [`Deadline`] and stopped early, so these
+    /// are the best of the documents it got to rather than of all of them.
+    pub timed_out: bool,
+    /// The counts of the request's facets, over all the documents it
+    /// matches rather than only this page.
+    pub facets: Vec<Facet>,
+}
+
+impl RankedResults {
+    pub fn from_hits(hits: Vec<(i32, f32)>) -> RankedResults {
+        let all_tied = hits.len() > 1 && hits.first().map(|h| h.1) == hits.last().map(|h| h.1);
+        RankedResults {
+            hits,
+            all_tied,
+            timed_out: false,
+            facets: vec![],
+        }
+    }
+}
+
+struct Counter<T: Eq + std::hash::Hash> {
+    counts: HashMap<T, i32>,
+}
+
+impl<T: Eq + std::hash::Hash> Counter<T> {
+    fn new() -> Counter<T> {
+        Counter {
+            counts: HashMap::new(),
+        }
+    }
+
+    fn increment(&mut self, item: T) {
+        let count = self.counts.entry(item).or_insert(0);
+        *count += 1;
+    }
+
+    fn into_counts(self) -> HashMap<T, i32> {
+        self.counts
+    }
+}
+
+fn default_analyzer() -> Arc<dyn Analyzer> {
+    Arc::new(ChainAnalyzer::default())
+}
+
+fn default_scorer() -> Arc<dyn Scorer> {
+    Arc::new(scorer::Bm25)
+}
+
+fn default_cache() -> Mutex<QueryCache<RankedResults>> {
+    Mutex::new(QueryCache::new(cache::DEFAULT_CAPACITY))
+}
+
+type TermPositions = HashMap<String, Vec<i32>>;
+
+type CountedText = (HashMap<String, i32>, TermPositions, i32);
+
+/// Analyzes `text` into term counts, positions and its length.
+fn count_text(analyzer: &dyn Analyzer, text: &str) -> CountedText {
+    let tokens = analyzer.analyze(text);
+    let (counts, positions) = count_tokens(&tokens);
+    (counts, positions, tokens.len() as i32)
+}
+
+fn count_tokens(tokens: &[String]) -> (HashMap<String, i32>, TermPositions) {
+    let mut doc_counter = Counter::new();
+    let mut positions: TermPositions = HashMap::new();
+    tokens.iter().enumerate().for_each(|(position, word)| {
+        doc_counter.increment(word.clone());
+        positions
+            .entry(word.clone())
+            .or_default()
+            .push(position as i32);
+    });
+    (doc_counter.into_counts(), positions)
+}
+
+/// The Levenshtein distance between `a` and `b` if it is at most `max`,
+/// giving up as soon as a whole row of the table exceeds it.
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().all(|&d| d > max) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|&d| d <= max)
+}
+
+/// Reads a document from disk with the default [`Extractors`], so HTML,
+/// Markdown and (with the `pdf` feature) PDF files are reduced to plain text.
+pub fn read_document(path: &str) -> Result<String, Bm25Error> {
+    Extractors::default().read(path)
+}
+
+/// Copies the documents and settings; the analyzer and scorer are shared, and
+/// the copy starts with empty caches.
+impl Clone for BM25Index {
+    fn clone(&self) -> Self {
+        BM25Index {
+            k1: self.k1,
+            b: self.b,
+            idf_mode: self.idf_mode,
+            doc_lengths: self.doc_lengths.clone(),
+            total_doc_length: self.total_doc_length,
+            avg_doc_length: self.avg_doc_length,
+            doc_count: self.doc_count,
+            doc_terms: self.doc_terms.clone(),
+            live: self.live.clone(),
+            deleted: self.deleted,
+            terms: self.terms.clone(),
+            postings: self.postings.clone(),
+            dictionary: self.dictionary.clone(),
+            titles: self.titles.clone(),
+            title_bonus: self.title_bonus,
+            length_adjustment: self.length_adjustment,
+            fields: self.fields.clone(),
+            field_boosts: self.field_boosts.clone(),
+            field_b: self.field_b.clone(),
+            boost_key: self.boost_key.clone(),
+            doc_ids: self.doc_ids.clone(),
+            next_doc_id: self.next_doc_id,
+            metadata: self.metadata.clone(),
+            analyzer: Arc::clone(&self.analyzer),
+            scorer: Arc::clone(&self.scorer),
+            cache: Mutex::new(QueryCache::new(self.cache_stats().capacity)),
+            field_doc_freqs: Mutex::new(HashMap::new()),
+            synonyms: self.synonyms.clone(),
+            synonym_weight: self.synonym_weight,
+            proximity_weight: self.proximity_weight,
+            feedback: self.feedback,
+        }
+    }
+}
+
+impl Default for BM25Index {
+    fn default() -> Self {
+        BM25Index::new()
+    }
+}
+
+impl BM25Index {
+    pub fn new() -> BM25Index {
+        BM25Index {
+            k1: 1.5,
+            b: 0.75,
+            idf_mode: IdfMode::Classic,
+            doc_lengths: vec![],
+            total_doc_length: 0,
+            avg_doc_length: 0,
+            doc_count: 0,
+            doc_terms: vec![],
+            live: vec![],
+            deleted: 0,
+            terms: Terms::default(),
+            postings: vec![],
+            dictionary: BTreeSet::new(),
+            titles: vec![],
+            title_bonus: 0.0,
+            length_adjustment: LengthAdjustment::None,
+            fields: BTreeMap::new(),
+            field_boosts: HashMap::new(),
+            field_b: HashMap::new(),
+            boost_key: None,
+            doc_ids: vec![],
+            next_doc_id: 0,
+            metadata: vec![],
+            analyzer: default_analyzer(),
+            scorer: default_scorer(),
+            cache: default_cache(),
+            field_doc_freqs: Mutex::new(HashMap::new()),
+            synonyms: HashMap::new(),
+            synonym_weight: 0.0,
+            proximity_weight: 0.0,
+            feedback: None,
+        }
+    }
+
+    pub fn with_analyzer(analyzer: Box<dyn Analyzer>) -> BM25Index {
+        let mut index = BM25Index::new();
+        index.analyzer = Arc::from(analyzer);
+        index
+    }
+
+    /// Replaces the analyzer, e.g. after loading an index from disk. It should
+    /// match the analyzer the documents were indexed with.
+    pub fn set_analyzer(&mut self, analyzer: Box<dyn Analyzer>) {
+        self.analyzer = Arc::from(analyzer);
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn k1(&self) -> f32 {
+        self.k1
+    }
+
+    pub fn b(&self) -> f32 {
+        self.b
+    }
+
+    /// Sets the term frequency saturation `k1` and length normalization `b`,
+    /// which default to 1.5 and 0.75.
+    pub fn set_parameters(&mut self, k1: f32, b: f32) {
+        self.k1 = k1;
+        self.b = b;
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn idf_mode(&self) -> IdfMode {
+        self.idf_mode
+    }
+
+    /// Sets how BM25 and BM25F weight terms in more than half of the
+    /// documents, which is [`IdfMode::Classic`] by default. It is saved with
+    /// the index.
+    pub fn set_idf_mode(&mut self, idf_mode: IdfMode) {
+        self.idf_mode = idf_mode;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Replaces the per-term scoring function, which is BM25 by default. BM25F
+    /// scoring, once field boosts are set, takes precedence over it.
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = Arc::from(scorer);
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.analyzer.analyze(text)
+    }
+
+    /// Analyzes `text` and adds it as a document, returning its index.
+    pub fn add_text(&mut self, text: &str) -> usize {
+        let tokens = self.analyze(text);
+        self.add_document(&tokens)
+    }
+
+    pub fn from_corpus(corpus: &[Vec<String>]) -> BM25Index {
+        let mut index = BM25Index::new();
+        for doc in corpus {
+            index.add_document(doc);
+        }
+        index
+    }
+
+    /// Number of live documents, which is what IDF is computed over.
+    pub fn doc_count(&self) -> usize {
+        self.doc_count as usize
+    }
+
+    /// Bytes taken by the compressed postings.
+    pub fn postings_bytes(&self) -> usize {
+        self.postings
+            .iter()
+            .map(|postings| postings.as_slice().bytes().len())
+            .sum()
+    }
+
+    /// Number of documents deleted since the index was last compacted.
+    pub fn deleted_count(&self) -> usize {
+        self.deleted
+    }
+
+    /// Number of distinct terms in the live documents.
+    pub fn vocabulary_size(&self) -> usize {
+        self.dictionary.len()
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            deleted: self.deleted,
+            cache: Some(self.cache_stats()),
+            ..IndexStats::new(
+                self.doc_count,
+                self.total_doc_length,
+                self.vocabulary_size(),
+            )
+        }
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats()
+    }
+
+    /// Sets how many queries' results are cached, by default
+    /// [`cache::DEFAULT_CAPACITY`]. Zero turns the cache off.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// The `n` first terms of the vocabulary in the given order, for looking
+    /// into what a corpus is made of.
+    pub fn top_terms(&self, order: TermOrder, n: usize) -> Vec<TermInfo> {
+        let terms = self
+            .dictionary
+            .iter()
+            .filter_map(|term| {
+                let (doc_freq, collection_freq) = self.live_freqs(self.posting_list(term)?);
+                Some(TermInfo {
+                    term: term.clone(),
+                    doc_freq,
+                    collection_freq,
+                    idf: self.idf_for_doc_freq(doc_freq),
+                })
+            })
+            .collect();
+        top_terms(terms, order, n)
+    }
+
+    /// Number of document slots, including removed documents.
+    pub fn slot_count(&self) -> usize {
+        self.doc_terms.len()
+    }
+
+    pub fn is_live(&self, doc_index: usize) -> bool {
+        self.live.get(doc_index).copied().unwrap_or(false)
+    }
+
+    /// The stable id of the document at `doc_index`.
+    pub fn doc_id(&self, doc_index: usize) -> DocId {
+        self.doc_ids[doc_index]
+    }
+
+    /// The index of the live document with the given id.
+    pub fn doc_index(&self, id: DocId) -> Option<usize> {
+        self.doc_ids
+            .binary_search(&id)
+            .ok()
+            .filter(|&doc_index| self.is_live(doc_index))
+    }
+
+    pub fn metadata(&self, doc_index: usize) -> &Metadata {
+        &self.metadata[doc_index]
+    }
+
+    pub fn set_metadata(&mut self, doc_index: usize, metadata: Metadata) {
+        self.metadata[doc_index] = metadata;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Adds a tokenized document and returns its index.
+    pub fn add_document(&mut self, tokens: &[String]) -> usize {
+        let id = self.doc_terms.len();
+        let (counts, positions) = count_tokens(tokens);
+        self.insert_document(counts, positions, tokens.len() as i32);
+        id
+    }
+
+    /// Analyzes and counts `texts` in parallel, then adds them in order. Returns
+    /// the index range the documents were assigned.
+    pub fn add_texts(&mut self, texts: &[String]) -> Range<usize> {
+        let _span = tracing::info_span!("ingest", documents = texts.len()).entered();
+        let analyzer = &self.analyzer;
+        let documents: Vec<_> = tracing::debug_span!("tokenize").in_scope(|| {
+            texts
+                .par_iter()
+                .map(|text| count_text(analyzer.as_ref(), text))
+                .collect()
+        });
+        let start = self.doc_terms.len();
+        for (counts, positions, length) in documents {
+            self.insert_document(counts, positions, length);
+        }
+        start..self.doc_terms.len()
+    }
+
+    /// Adds documents as `documents` yields them, analyzing [`STREAM_BATCH`]
+    /// at a time in parallel, so that only one batch of text and tokens is in
+    /// memory rather than the whole corpus. `added` is called with the index
+    /// of each document and the document itself, e.g. to set its metadata.
+    /// Document frequencies and lengths are kept up to date as documents are
+    /// added, so the IDF needs no second pass. Returns the index range the
+    /// documents were assigned.
+    pub fn add_stream<T: Send + Sync>(
+        &mut self,
+        documents: impl IntoIterator<Item = T>,
+        text: impl Fn(&T) -> &str + Sync,
+        added: impl FnMut(&mut BM25Index, usize, T),
+    ) -> Range<usize> {
+        self.add_stream_with_progress(documents, text, added, |_| {})
+    }
+
+    /// Like [`BM25Index::add_stream`], calling `progress` after each batch.
+    pub fn add_stream_with_progress<T: Send + Sync>(
+        &mut self,
+        documents: impl IntoIterator<Item = T>,
+        text: impl Fn(&T) -> &str + Sync,
+        mut added: impl FnMut(&mut BM25Index, usize, T),
+        mut progress: impl FnMut(&Progress),
+    ) -> Range<usize> {
+        let _span = tracing::info_span!("ingest").entered();
+        let start = self.doc_terms.len();
+        let started = Instant::now();
+        let mut bytes = 0;
+        let mut documents = documents.into_iter();
+        loop {
+            let batch: Vec<T> = documents.by_ref().take(STREAM_BATCH).collect();
+            if batch.is_empty() {
+                break;
+            }
+            let analyzer = &self.analyzer;
+            let counted: Vec<_> = tracing::debug_span!("tokenize", documents = batch.len())
+                .in_scope(|| {
+                    batch
+                        .par_iter()
+                        .map(|document| count_text(analyzer.as_ref(), text(document)))
+                        .collect()
+                });
+            for (document, (counts, positions, length)) in batch.into_iter().zip(counted) {
+                bytes += text(&document).len() as u64;
+                let doc = self.doc_terms.len();
+                self.insert_document(counts, positions, length);
+                added(self, doc, document);
+            }
+            progress(&Progress {
+                documents: self.doc_terms.len() - start,
+                bytes,
+                elapsed: started.elapsed(),
+            });
+        }
+        tracing::info!(
+            documents = self.doc_terms.len() - start,
+            bytes,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "ingested"
+        );
+        start..self.doc_terms.len()
+    }
+
+    /// Adds a document from pre-aggregated term frequencies. `id` must be the
+    /// next free document index. Such documents have no positions, so they never
+    /// match phrase queries.
+    pub fn add_document_counts(
+        &mut self,
+        id: i32,
+        counts: HashMap<String, i32>,
+        length: i32,
+    ) -> Result<(), Bm25Error> {
+        let expected = self.doc_terms.len() as i32;
+        if id != expected {
+            return Err(Bm25Error::InvalidDocumentId {
+                expected,
+                found: id,
+            });
+        }
+        self.insert_document(counts, HashMap::new(), length);
+        Ok(())
+    }
+
+    fn insert_document(
+        &mut self,
+        counts: HashMap<String, i32>,
+        mut positions: HashMap<String, Vec<i32>>,
+        length: i32,
+    ) {
+        let id = self.doc_terms.len() as i32;
+        let mut doc_terms = vec![];
+        // New terms are numbered in order rather than in the hash map's, so
+        // the same documents always get the same term ids, and sums over a
+        // document's terms, as in `similarity`, the same rounding.
+        let mut counts: Vec<(String, i32)> = counts.into_iter().collect();
+        counts.sort_unstable();
+        for (term, count) in counts {
+            if count <= 0 {
+                continue;
+            }
+            let term_id = self.terms.intern(&term);
+            if self.postings.len() <= term_id as usize {
+                self.postings
+                    .resize_with(term_id as usize + 1, PostingList::default);
+            }
+            let postings = &mut self.postings[term_id as usize];
+            postings.push(&Posting {
+                doc: id,
+                tf: count,
+                positions: positions.remove(&term).unwrap_or_default(),
+            });
+            if postings.len() == 1 {
+                self.dictionary.insert(term);
+            }
+            doc_terms.push((term_id, count));
+        }
+        doc_terms.sort_unstable();
+        self.doc_terms.push(doc_terms);
+        self.live.push(true);
+        self.doc_ids.push(self.next_doc_id);
+        self.next_doc_id += 1;
+        self.metadata.push(Metadata::default());
+        self.doc_lengths.push(length);
+        self.doc_count += 1;
+        self.total_doc_length += length;
+        self.update_avg_doc_length();
+    }
+
+    /// Removes a document's terms and length from the index statistics. The slot
+    /// stays allocated so the indices of other documents do not change.
+    pub fn remove_document(&mut self, doc_id: usize) -> bool {
+        if !self.is_live(doc_id) {
+            return false;
+        }
+        let doc_terms = std::mem::take(&mut self.doc_terms[doc_id]);
+        self.metadata[doc_id] = Metadata::default();
+        for (term_id, _) in doc_terms {
+            let postings = &mut self.postings[term_id as usize];
+            postings.remove(doc_id as i32);
+            if postings.is_empty() {
+                self.dictionary.remove(self.terms.name(term_id));
+            }
+        }
+        if let Some(title) = self.titles.get_mut(doc_id) {
+            title.clear();
+        }
+        for field in self.fields.values_mut() {
+            field.clear(doc_id);
+        }
+        self.live[doc_id] = false;
+        self.total_doc_length -= self.doc_lengths[doc_id];
+        self.doc_lengths[doc_id] = 0;
+        self.doc_count -= 1;
+        self.update_avg_doc_length();
+        true
+    }
+
+    /// Marks a document deleted without touching its postings, so that it is
+    /// left out of results and of the statistics at once, and its postings are
+    /// only skipped until [`BM25Index::compact`] drops them. Cheaper than
+    /// [`BM25Index::remove_document`] when many documents are deleted at once.
+    pub fn delete_document(&mut self, doc_index: usize) -> bool {
+        if !self.is_live(doc_index) {
+            return false;
+        }
+        self.live[doc_index] = false;
+        self.deleted += 1;
+        for field in self.fields.values_mut() {
+            field.total_length -= field.lengths.get(doc_index).copied().unwrap_or(0);
+        }
+        self.total_doc_length -= self.doc_lengths[doc_index];
+        self.doc_count -= 1;
+        self.update_avg_doc_length();
+        true
+    }
+
+    /// Rewrites the index without the slots of removed and deleted documents
+    /// or the terms only they contained, and recomputes the statistics from
+    /// the documents that are left. Document indices change, and ids do not.
+    /// Returns how many slots were reclaimed.
+    pub fn compact(&mut self) -> usize {
+        let kept: Vec<usize> = (0..self.slot_count())
+            .filter(|&doc| self.is_live(doc))
+            .collect();
+        let reclaimed = self.slot_count() - kept.len();
+        let mut new_doc = vec![None; self.slot_count()];
+        for (new, &old) in kept.iter().enumerate() {
+            new_doc[old] = Some(new as i32);
+        }
+        // Terms keep their relative order, so each document's terms stay
+        // sorted by id.
+        let mut terms = Terms::default();
+        let mut new_term = vec![None; self.postings.len()];
+        let mut postings = vec![];
+        for (term_id, list) in self.postings.iter().enumerate() {
+            let mut compacted = PostingList::default();
+            for mut posting in list.iter() {
+                if let Some(doc) = new_doc[posting.doc as usize] {
+                    posting.doc = doc;
+                    compacted.push(&posting);
+                }
+            }
+            if !compacted.is_empty() {
+                new_term[term_id] = Some(terms.intern(self.terms.name(term_id as u32)));
+                postings.push(compacted);
+            }
+        }
+        let doc_terms = std::mem::take(&mut self.doc_terms);
+        self.doc_terms = kept
+            .iter()
+            .map(|&doc| {
+                doc_terms[doc]
+                    .iter()
+                    .map(|&(term_id, tf)| (new_term[term_id as usize].unwrap(), tf))
+                    .collect()
+            })
+            .collect();
+        self.dictionary = (0..terms.len() as u32)
+            .map(|term_id| terms.name(term_id).to_string())
+            .collect();
+        self.terms = terms;
+        self.postings = postings;
+        self.live = vec![true; kept.len()];
+        self.doc_ids = kept.iter().map(|&doc| self.doc_ids[doc]).collect();
+        self.metadata = kept
+            .iter()
+            .map(|&doc| std::mem::take(&mut self.metadata[doc]))
+            .collect();
+        self.doc_lengths = kept.iter().map(|&doc| self.doc_lengths[doc]).collect();
+        if !self.titles.is_empty() {
+            self.titles = kept
+                .iter()
+                .map(|&doc| {
+                    self.titles
+                        .get_mut(doc)
+                        .map(std::mem::take)
+                        .unwrap_or_default()
+                })
+                .collect();
+        }
+        for field in self.fields.values_mut() {
+            field.compact(&kept, &new_doc);
+        }
+        self.deleted = 0;
+        self.doc_count = kept.len() as i32;
+        self.total_doc_length = self.doc_lengths.iter().sum();
+        self.update_avg_doc_length();
+        reclaimed
+    }
+
+    fn update_avg_doc_length(&mut self) {
+        self.avg_doc_length = if self.doc_count > 0 {
+            self.total_doc_length / self.doc_count
+        } else {
+            0
+        };
+        self.cache.lock().unwrap().clear();
+        self.field_doc_freqs.lock().unwrap().clear();
+    }
+
+    /// The postings of `term`, if any live document contains it.
+    fn posting_list(&self, term: &str) -> Option<&PostingList> {
+        let postings = self.postings.get(self.terms.id(term)? as usize)?;
+        (!postings.is_empty()).then_some(postings)
+    }
+
+    /// How many times `term` occurs in the body of `doc_index`.
+    fn body_tf(&self, term: &str, doc_index: usize) -> i32 {
+        match self.terms.id(term) {
+            Some(term_id) => self.term_id_tf(term_id, doc_index),
+            None => 0,
+        }
+    }
+
+    fn term_id_tf(&self, term_id: u32, doc_index: usize) -> i32 {
+        let Some(doc_terms) = self.doc_terms.get(doc_index) else {
+            return 0;
+        };
+        doc_terms
+            .binary_search_by_key(&term_id, |&(id, _)| id)
+            .map_or(0, |found| doc_terms[found].1)
+    }
+
+    fn collection_freq(&self, term: &str) -> i32 {
+        self.posting_list(term)
+            .map_or(0, |postings| self.live_freqs(postings).1)
+    }
+
+    /// The document and collection frequencies of `postings`, leaving out
+    /// the deleted documents they still hold.
+    pub(crate) fn live_freqs(&self, postings: &PostingList) -> (i32, i32) {
+        if self.deleted == 0 {
+            return (postings.len() as i32, postings.collection_freq());
+        }
+        postings
+            .as_slice()
+            .frequencies()
+            .filter(|&(doc, _)| self.is_live(doc as usize))
+            .fold((0, 0), |(docs, total), (_, tf)| (docs + 1, total + tf))
+    }
+
+    /// Number of documents containing `term`. Once field boosts are set, a
+    /// document counts if the term occurs in its body or in any of its fields.
+    fn doc_freq(&self, term: &str) -> i32 {
+        let body = self
+            .posting_list(term)
+            .map_or(0, |postings| self.live_freqs(postings).0);
+        if !self.uses_fields() {
+            return body;
+        }
+        if let Some(&count) = self.field_doc_freqs.lock().unwrap().get(term) {
+            return count;
+        }
+        let count = body + self.field_only_docs(term).len() as i32;
+        self.field_doc_freqs
+            .lock()
+            .unwrap()
+            .insert(term.to_string(), count);
+        count
+    }
+
+    /// Documents that contain `term` in some field but not in their body.
+    fn field_only_docs(&self, term: &str) -> HashSet<i32> {
+        self.fields
+            .values()
+            .filter_map(|field| field.docs.get(term))
+            .flatten()
+            .copied()
+            .filter(|&doc| self.is_live(doc as usize) && self.body_tf(term, doc as usize) == 0)
+            .collect()
+    }
+
+    fn uses_fields(&self) -> bool {
+        !self.field_boosts.is_empty()
+    }
+
+    fn field_boost(&self, field: &str) -> f32 {
+        self.field_boosts.get(field).copied().unwrap_or(1.0)
+    }
+
+    fn field_b(&self, field: &str) -> f32 {
+        self.field_b.get(field).copied().unwrap_or(self.b)
+    }
+
+    /// BM25F: the body's and each field's term frequency is normalized by that
+    /// field's length relative to its average and boosted, and the sum is then
+    /// saturated once with `k1`, so repeating a term across fields does not
+    /// count as independent evidence.
+    fn field_term_score(&self, term: &str, idf: f32, doc_index: usize) -> f32 {
+        let normalized = |tf: i32, length: i32, avg_length: f32, b: f32| {
+            if tf == 0 {
+                0.0
+            } else {
+                tf as f32 / (1.0 - b + b * length as f32 / avg_length)
+            }
+        };
+        let body_tf = self.body_tf(term, doc_index);
+        let mut tf = self.field_boost("body")
+            * normalized(
+                body_tf,
+                self.doc_lengths[doc_index],
+                self.avg_doc_length.max(1) as f32,
+                self.field_b("body"),
+            );
+        for (name, field) in &self.fields {
+            let avg_length = field.total_length as f32 / self.doc_count as f32;
+            tf += self.field_boost(name)
+                * normalized(
+                    field.tf(term, doc_index),
+                    field.lengths.get(doc_index).copied().unwrap_or(0),
+                    avg_length,
+                    self.field_b(name),
+                );
+        }
+        idf * tf * (self.k1 + 1.0) / (self.k1 + tf)
+    }
+
+    fn idf(&self, term: &str) -> f32 {
+        self.idf_for_doc_freq(self.doc_freq(term))
+    }
+
+    fn idf_for_doc_freq(&self, count: i32) -> f32 {
+        self.idf_mode.idf(count, self.doc_count)
+    }
+
+    fn term_score(&self, tf: i32, doc_freq: i32, collection_freq: i32, doc_index: usize) -> f32 {
+        self.scorer
+            .score(&self.term_stats(tf, doc_freq, collection_freq, doc_index))
+    }
+
+    fn term_stats(
+        &self,
+        tf: i32,
+        doc_freq: i32,
+        collection_freq: i32,
+        doc_index: usize,
+    ) -> TermStats {
+        TermStats {
+            tf,
+            doc_length: *self.doc_lengths.get(doc_index).unwrap_or(&0),
+            avg_doc_length: self.avg_doc_length.max(1) as f32,
+            doc_freq,
+            doc_count: self.doc_count,
+            collection_freq,
+            collection_length: self.total_doc_length,
+            k1: self.k1,
+            b: self.b,
+            idf_mode: self.idf_mode,
+        }
+    }
+
+    pub fn score(&self, query: &[&str], doc_index: usize) -> f32 {
+        let query = unweighted(query);
+        let proximity = self.proximity(&query);
+        self.score_with_phrases(&query, &[], proximity.as_ref(), doc_index)
+    }
+
+    fn score_with_phrases(
+        &self,
+        query: &WeightedTerms,
+        phrases: &[PhraseStats],
+        proximity: Option<&Proximity>,
+        doc_index: usize,
+    ) -> f32 {
+        self.score_batch(query, phrases, proximity, &[doc_index as i32])[0]
+    }
+
+    /// The scores of `docs`, worked out a query term at a time over the
+    /// whole batch: each document's length normalization is computed once,
+    /// and a term's frequencies in the documents are gathered into one array
+    /// for [`Scorer::score_batch`]. BM25F looks terms up field by field, so
+    /// it scores each document on its own.
+    fn score_batch(
+        &self,
+        query: &WeightedTerms,
+        phrases: &[PhraseStats],
+        proximity: Option<&Proximity>,
+        docs: &[i32],
+    ) -> Vec<f32> {
+        let mut scores = vec![0.0; docs.len()];
+        if self.uses_fields() {
+            for (score, &doc) in scores.iter_mut().zip(docs) {
+                for (term, weight) in query {
+                    *score += weight * self.query_term_score(term, doc as usize);
+                }
+            }
+        } else {
+            let shared = self.term_stats(0, 0, 0, 0);
+            let doc_lengths: Vec<i32> = docs
+                .iter()
+                .map(|&doc| *self.doc_lengths.get(doc as usize).unwrap_or(&0))
+                .collect();
+            let norms: Vec<f32> = doc_lengths
+                .iter()
+                .map(|&doc_length| {
+                    let stats = TermStats {
+                        doc_length,
+                        ..shared
+                    };
+                    stats.k1 * stats.length_norm()
+                })
+                .collect();
+            let mut tfs = vec![0; docs.len()];
+            for (term, weight) in query {
+                let term_id = self.terms.id(term);
+                for (tf, &doc) in tfs.iter_mut().zip(docs) {
+                    *tf = term_id.map_or(0, |term_id| self.term_id_tf(term_id, doc as usize));
+                }
+                let stats = TermStats {
+                    doc_freq: self.doc_freq(term),
+                    collection_freq: self.collection_freq(term),
+                    ..shared
+                };
+                self.scorer
+                    .score_batch(&stats, &tfs, &doc_lengths, &norms, *weight, &mut scores);
+            }
+        }
+        for (score, &doc) in scores.iter_mut().zip(docs) {
+            let doc = doc as usize;
+            for phrase in phrases {
+                *score += phrase.weight * self.phrase_score(phrase, doc);
+            }
+            *score *= self.length_factor(doc) * self.doc_boost(doc);
+            *score =
+                *score + self.title_bonus_for(query, doc) + self.proximity_bonus(proximity, doc);
+        }
+        scores
+    }
+
+    /// The positions needed for the proximity bonus, if it is enabled and the
+    /// query has more than one distinct term.
+    fn proximity(&self, query: &WeightedTerms) -> Option<Proximity> {
+        if self.proximity_weight == 0.0 {
+            return None;
+        }
+        let terms: BTreeSet<&str> = query.iter().map(|(term, _)| *term).collect();
+        if terms.len() < 2 {
+            return None;
+        }
+        let positions = terms
+            .into_iter()
+            .map(|term| {
+                self.posting_list(term)
+                    .map(|postings| {
+                        postings
+                            .iter()
+                            .filter(|posting| !posting.positions.is_empty())
+                            .map(|posting| (posting.doc, posting.positions))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect();
+        Some(Proximity { positions })
+    }
+
+    /// `proximity_weight` times how tightly the query terms a document
+    /// contains are packed: the number of gaps between them over the length of
+    /// the shortest window containing them all, less one, so terms next to
+    /// each other get the whole weight.
+    fn proximity_bonus(&self, proximity: Option<&Proximity>, doc_index: usize) -> f32 {
+        let Some(proximity) = proximity else {
+            return 0.0;
+        };
+        let positions: Vec<&[i32]> = proximity
+            .positions
+            .iter()
+            .filter_map(|docs| docs.get(&(doc_index as i32)))
+            .map(|positions| positions.as_slice())
+            .collect();
+        if positions.len() < 2 {
+            return 0.0;
+        }
+        let span = min_window(&positions);
+        self.proximity_weight * (positions.len() - 1) as f32 / (span - 1) as f32
+    }
+
+    /// Adds a bonus of up to `weight` to documents in which the query terms
+    /// occur close together. Zero, the default, turns it off.
+    pub fn set_proximity_weight(&mut self, weight: f32) {
+        self.proximity_weight = weight;
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn query_term_score(&self, term: &str, doc_index: usize) -> f32 {
+        if self.uses_fields() && doc_index < self.doc_terms.len() {
+            self.field_term_score(term, self.idf(term), doc_index)
+        } else if doc_index < self.doc_terms.len() {
+            let tf = self.body_tf(term, doc_index);
+            self.term_score(
+                tf,
+                self.doc_freq(term),
+                self.collection_freq(term),
+                doc_index,
+            )
+        } else {
+            0.0
+        }
+    }
+
+    fn phrase_score(&self, phrase: &PhraseStats, doc_index: usize) -> f32 {
+        self.scorer
+            .score(&self.phrase_term_stats(phrase, doc_index))
+    }
+
+    /// The statistics a phrase is scored with, as if it were a single term.
+    /// One counted in a field is normalized by that field's length.
+    fn phrase_term_stats(&self, phrase: &PhraseStats, doc_index: usize) -> TermStats {
+        let tf = phrase.frequencies.get(&(doc_index as i32)).unwrap_or(&0);
+        let doc_freq = phrase.frequencies.len() as i32;
+        let stats = self.term_stats(*tf, doc_freq, phrase.collection_freq, doc_index);
+        let field = phrase
+            .field
+            .as_ref()
+            .and_then(|name| Some((name, self.fields.get(name)?)));
+        match field {
+            Some((name, field)) => TermStats {
+                doc_length: field.lengths.get(doc_index).copied().unwrap_or(0),
+                avg_doc_length: (field.total_length as f32 / self.doc_count.max(1) as f32).max(1.0),
+                collection_length: field.total_length,
+                b: self.field_b(name),
+                ..stats
+            },
+            None => stats,
+        }
+    }
+
+    /// The document's value of the boost key, or 1 without a key or a
+    /// non-negative number there.
+    fn doc_boost(&self, doc_index: usize) -> f32 {
+        self.boost_key
+            .as_ref()
+            .and_then(|key| self.metadata.get(doc_index)?.values.get(key)?.parse().ok())
+            .filter(|boost: &f32| boost.is_finite() && *boost >= 0.0)
+            .unwrap_or(1.0)
+    }
+
+    fn length_factor(&self, doc_index: usize) -> f32 {
+        self.length_adjustment.factor(
+            *self.doc_lengths.get(doc_index).unwrap_or(&0),
+            self.avg_doc_length,
+        )
+    }
+
+    fn title_bonus_for(&self, query: &WeightedTerms, doc_index: usize) -> f32 {
+        let terms = query.iter().map(|(term, _)| *term);
+        if self.title_bonus != 0.0 && self.title_matches(terms, doc_index) {
+            self.title_bonus
+        } else {
+            0.0
+        }
+    }
+
+    /// Breaks down the score of `doc_index` for a bag-of-words query, in the
+    /// same way [`BM25Index::score`] computes it.
+    pub fn explain(&self, query: &[&str], doc_index: usize) -> Explanation {
+        self.explain_with_phrases(&unweighted(query), &[], &[], doc_index)
+    }
+
+    /// Like [`BM25Index::explain`], for the scoring terms and phrases of a
+    /// structured query.
+    pub fn explain_query(&self, query: &Query, doc_index: usize) -> Explanation {
+        let terms = query.weighted_terms();
+        let terms: Vec<(&str, f32)> = terms.iter().map(|(t, w)| (t.as_str(), *w)).collect();
+        let (phrases, stats): (Vec<Vec<String>>, Vec<PhraseStats>) =
+            self.query_phrase_stats(query).into_iter().unzip();
+        self.explain_with_phrases(&terms, &phrases, &stats, doc_index)
+    }
+
+    fn explain_with_phrases(
+        &self,
+        query: &WeightedTerms,
+        phrases: &[Vec<String>],
+        stats: &[PhraseStats],
+        doc_index: usize,
+    ) -> Explanation {
+        let explain_term = |term: String, stats: TermStats| TermExplanation {
+            term,
+            weight: 1.0,
+            tf: stats.tf,
+            doc_freq: stats.doc_freq,
+            idf: self.scorer.idf(&stats),
+            length_norm: stats.length_norm(),
+            score: 0.0,
+        };
+        let mut terms: Vec<TermExplanation> = query
+            .iter()
+            .map(|&(term, weight)| {
+                let tf = self.body_tf(term, doc_index);
+                let stats = self.term_stats(
+                    tf,
+                    self.doc_freq(term),
+                    self.collection_freq(term),
+                    doc_index,
+                );
+                TermExplanation {
+                    weight,
+                    score: weight * self.query_term_score(term, doc_index),
+                    ..explain_term(term.to_string(), stats)
+                }
+            })
+            .collect();
+        for (phrase, stats) in phrases.iter().zip(stats) {
+            terms.push(TermExplanation {
+                weight: stats.weight,
+                score: stats.weight * self.phrase_score(stats, doc_index),
+                ..explain_term(phrase.join(" "), self.phrase_term_stats(stats, doc_index))
+            });
+        }
+        let length_factor = self.length_factor(doc_index);
+        let doc_boost = self.doc_boost(doc_index);
+        let title_bonus = self.title_bonus_for(query, doc_index);
+        let proximity_bonus = self.proximity_bonus(self.proximity(query).as_ref(), doc_index);
+        let sum: f32 = terms.iter().map(|term| term.score).sum();
+        Explanation {
+            doc: doc_index as i32,
+            score: sum * length_factor * doc_boost + title_bonus + proximity_bonus,
+            terms,
+            length_factor,
+            doc_boost,
+            title_bonus,
+            proximity_bonus,
+        }
+    }
+
+    fn title_matches<'q>(
+        &self,
+        query: impl ExactSizeIterator<Item = &'q str>,
+        doc_index: usize,
+    ) -> bool {
+        match self.titles.get(doc_index) {
+            Some(title) if !title.is_empty() => {
+                title.len() == query.len()
+                    && title
+                        .iter()
+                        .zip(query)
+                        .all(|(word, term)| *word == term.to_lowercase())
+            }
+            _ => false,
+        }
+    }
+
+    pub fn set_length_adjustment(&mut self, length_adjustment: LengthAdjustment) {
+        self.length_adjustment = length_adjustment;
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn set_title_bonus(&mut self, title_bonus: f32) {
+        self.title_bonus = title_bonus;
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn set_title(&mut self, doc_index: usize, title: Vec<String>) {
+        if self.titles.len() <= doc_index {
+            self.titles.resize(doc_index + 1, vec![]);
+        }
+        self.titles[doc_index] = title;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Sets per-document title tokens and the bonus added when a query equals a
+    /// document's whole title.
+    pub fn set_titles(&mut self, titles: Vec<Vec<String>>, title_bonus: f32) {
+        self.titles = titles;
+        self.title_bonus = title_bonus;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Sets the tokens of a named field of a document, e.g. a title or an
+    /// author. Fields only affect scoring once [`BM25Index::set_field_boosts`]
+    /// is called; the body is always the field named `body`.
+    pub fn set_field(&mut self, doc_index: usize, name: &str, tokens: &[String]) {
+        let field = self.fields.entry(name.to_string()).or_default();
+        if field.tf.len() <= doc_index {
+            field.tf.resize(doc_index + 1, HashMap::new());
+            field.lengths.resize(doc_index + 1, 0);
+        }
+        field.clear(doc_index);
+        let (counts, _) = count_tokens(tokens);
+        for term in counts.keys() {
+            field
+                .docs
+                .entry(term.clone())
+                .or_default()
+                .insert(doc_index as i32);
+        }
+        field.tf[doc_index] = counts;
+        field.lengths[doc_index] = tokens.len() as i32;
+        field.total_length += tokens.len() as i32;
+        self.cache.lock().unwrap().clear();
+        self.field_doc_freqs.lock().unwrap().clear();
+    }
+
+    pub fn field_names(&self) -> Vec<&str> {
+        self.fields.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Switches scoring to BM25F with the given per-field weights. Fields that
+    /// are not listed, including `body`, keep a weight of 1. An empty map goes
+    /// back to scoring the body alone.
+    pub fn set_field_boosts(&mut self, boosts: HashMap<String, f32>) {
+        self.field_boosts = boosts;
+        self.cache.lock().unwrap().clear();
+        self.field_doc_freqs.lock().unwrap().clear();
+    }
+
+    /// Replaces the length normalization `b` of the named fields, including
+    /// `body`, in BM25F scoring and field phrases, e.g. 0 for a field such
+    /// as a title whose length should not matter. Other fields use the
+    /// index's `b`.
+    pub fn set_field_length_normalization(&mut self, b: HashMap<String, f32>) {
+        self.field_b = b;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Multiplies each document's score by the number in its metadata value
+    /// `key`, a static prior such as recency or popularity that is set when
+    /// documents are added. Documents without the value, or whose value is
+    /// not a non-negative number, are not boosted. `None` turns it off.
+    pub fn set_boost_key(&mut self, key: Option<String>) {
+        self.boost_key = key;
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn term_weight(&self, term: &str, tf: i32) -> f32 {
+        let df = self.doc_freq(term) as f32;
+        let idf = (1.0 + (self.doc_count as f32 - df + 0.5) / (df + 0.5)).ln();
+        tf as f32 * idf
+    }
+
+    /// Cosine similarity of two documents' IDF-weighted term frequency vectors.
+    pub fn similarity(&self, doc_a: usize, doc_b: usize) -> f32 {
+        let (Some(a), Some(b)) = (self.doc_terms.get(doc_a), self.doc_terms.get(doc_b)) else {
+            return 0.0;
+        };
+        let weight = |term_id: u32, tf: i32| self.term_weight(self.terms.name(term_id), tf);
+        let norm = |doc: &[(u32, i32)]| {
+            doc.iter()
+                .map(|&(term_id, tf)| weight(term_id, tf).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        };
+        let dot: f32 = a
+            .iter()
+            .filter_map(|&(term_id, tf)| {
+                let other = b.binary_search_by_key(&term_id, |&(id, _)| id).ok()?;
+                Some(weight(term_id, tf) * weight(term_id, b[other].1))
+            })
+            .sum();
+        let denominator = norm(a) * norm(b);
+        if denominator == 0.0 {
+            0.0
+        } else {
+            dot / denominator
+        }
+    }
+
+    /// The `n` terms of the document at `doc_index` with the highest tf-idf
+    /// weight, highest first, with those weights. Terms no other document
+    /// has are left out, as they cannot find similar documents.
+    pub fn key_terms(&self, doc_index: usize, n: usize) -> Vec<(String, f32)> {
+        let Some(terms) = self.doc_terms.get(doc_index) else {
+            return vec![];
+        };
+        let mut weighted: Vec<(&str, f32)> = terms
+            .iter()
+            .map(|&(term_id, tf)| (self.terms.name(term_id), tf))
+            .filter(|&(term, _)| self.doc_freq(term) > 1)
+            .map(|(term, tf)| (term, self.term_weight(term, tf)))
+            .collect();
+        weighted.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        weighted
+            .into_iter()
+            .take(n)
+            .map(|(term, weight)| (term.to_string(), weight))
+            .collect()
+    }
+
+    /// The `k` documents most like the live document with id `id`, best
+    /// first: its [`MORE_LIKE_THIS_TERMS`] key terms are run as a query, each
+    /// weighted by its share of the best one's weight, and the document
+    /// itself is left out, as are documents scoring zero or below. `None` if
+    /// there is no such document.
+    pub fn more_like_this(&self, id: DocId, k: usize) -> Option<Vec<(i32, f32)>> {
+        let doc = self.doc_index(id)? as i32;
+        let terms = self.key_terms(doc as usize, MORE_LIKE_THIS_TERMS);
+        let best = terms.first().map_or(1.0, |(_, weight)| *weight);
+        let query: Vec<(&str, f32)> = terms
+            .iter()
+            .map(|(term, weight)| (term.as_str(), weight / best))
+            .collect();
+        let names: Vec<&str> = query.iter().map(|(term, _)| *term).collect();
+        let deadline = Deadline::default();
+        let candidates = self.candidates(&names, &deadline);
+        let candidates = candidates.into_iter().filter(|&other| other != doc);
+        let keep = |score| keeps_score(None, score);
+        Some(self.top_k(&query, candidates, k, keep, &deadline))
+    }
+
+    /// Documents containing at least one query term, plus any whose title
+    /// matches the query when the title bonus is enabled. Past the deadline
+    /// no more terms or titles are looked at.
+    fn candidates(&self, query: &[&str], deadline: &Deadline) -> Vec<i32> {
+        let mut docs: Vec<i32> = query
+            .iter()
+            .take_while(|_| !deadline.expired())
+            .filter_map(|term| self.posting_list(term))
+            .flat_map(|docs| docs.docs())
+            .collect();
+        if self.uses_fields() {
+            docs.extend(query.iter().flat_map(|term| self.field_only_docs(term)));
+        }
+        if self.title_bonus != 0.0 {
+            docs.extend(
+                (0..self.doc_terms.len() as i32)
+                    .take_while(|_| !deadline.expired())
+                    .filter(|&doc| self.title_matches(query.iter().copied(), doc as usize)),
+            );
+        }
+        if self.deleted > 0 {
+            docs.retain(|&doc| self.is_live(doc as usize));
+        }
+        docs.sort_unstable();
+        docs.dedup();
+        docs
+    }
+
+    fn score_candidates(&self, query: &[&str], candidates: Vec<i32>) -> Vec<(i32, f32)> {
+        self.score_candidates_with_phrases(
+            &unweighted(query),
+            &[],
+            candidates,
+            &Deadline::default(),
+        )
+    }
+
+    /// Scores and ranks the candidates, [`SCORE_BATCH`] at a time in
+    /// parallel, leaving out batches not scored yet once the deadline passes.
+    fn score_candidates_with_phrases(
+        &self,
+        query: &WeightedTerms,
+        phrases: &[PhraseStats],
+        candidates: Vec<i32>,
+        deadline: &Deadline,
+    ) -> Vec<(i32, f32)> {
+        let span = tracing::debug_span!("score", candidates = candidates.len());
+        let scored = span.in_scope(|| {
+            let proximity = self.proximity(query);
+            let proximity = proximity.as_ref();
+            candidates
+                .par_chunks(SCORE_BATCH)
+                .filter(|_| !deadline.expired())
+                .flat_map_iter(|docs| {
+                    let scores = self.score_batch(query, phrases, proximity, docs);
+                    docs.iter().copied().zip(scores)
+                })
+                .collect()
+        });
+        rank_hits(scored)
+    }
+
+    pub fn rank(&self, query: &[&str]) -> Vec<(i32, f32)> {
+        self.score_candidates(query, self.candidates(query, &Deadline::default()))
+    }
+
+    /// The `k` best hits in ranked order, kept in bounded heaps instead of
+    /// sorting every candidate, which are scored in parallel. Ties are broken
+    /// by document index exactly as in [`BM25Index::rank`], so this always
+    /// equals a prefix of it.
+    pub fn rank_top_k(&self, query: &[&str], k: usize) -> Vec<(i32, f32)> {
+        let deadline = Deadline::default();
+        let candidates = self.candidates(query, &deadline);
+        self.top_k(&unweighted(query), candidates, k, |_| true, &deadline)
+    }
+
+    /// Like [`BM25Index::rank_top_k`] over `candidates`, leaving out those
+    /// whose score is not kept, and batches not scored yet once the deadline
+    /// passes.
+    fn top_k(
+        &self,
+        query: &WeightedTerms,
+        candidates: impl IntoIterator<Item = i32>,
+        k: usize,
+        keep: impl Fn(f32) -> bool + Sync,
+        deadline: &Deadline,
+    ) -> Vec<(i32, f32)> {
+        let heap = self.top_k_heap(query, candidates, k, keep, deadline);
+        let _span = tracing::debug_span!("rank", hits = heap.len()).entered();
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| (ranked.doc, ranked.score))
+            .collect()
+    }
+
+    /// The `k` best of `candidates` for [`BM25Index::top_k`], worst on top.
+    fn top_k_heap(
+        &self,
+        query: &WeightedTerms,
+        candidates: impl IntoIterator<Item = i32>,
+        k: usize,
+        keep: impl Fn(f32) -> bool + Sync,
+        deadline: &Deadline,
+    ) -> BinaryHeap<Reverse<RankedDoc>> {
+        if k == 0 {
+            return BinaryHeap::new();
+        }
+        let push = |mut heap: BinaryHeap<Reverse<RankedDoc>>, candidate: RankedDoc| {
+            if heap.len() < k {
+                heap.push(Reverse(candidate));
+            } else if heap.peek().is_some_and(|worst| candidate > worst.0) {
+                heap.pop();
+                heap.push(Reverse(candidate));
+            }
+            heap
+        };
+        // Each thread keeps the best `k` of its share of the candidates, and the
+        // per-thread heaps are then merged into one.
+        let candidates: Vec<i32> = candidates.into_iter().collect();
+        let scoring = tracing::debug_span!("score", candidates = candidates.len()).entered();
+        let proximity = self.proximity(query);
+        let proximity = proximity.as_ref();
+        let heap = candidates
+            .par_chunks(SCORE_BATCH)
+            .filter(|_| !deadline.expired())
+            .flat_map_iter(|docs| {
+                let scores = self.score_batch(query, &[], proximity, docs);
+                docs.iter()
+                    .zip(scores)
+                    .map(|(&doc, score)| RankedDoc { score, doc })
+            })
+            .filter(|candidate| keep(candidate.score))
+            .fold(BinaryHeap::new, push)
+            .reduce(BinaryHeap::new, |heap, other| {
+                other
+                    .into_iter()
+                    .fold(heap, |heap, Reverse(candidate)| push(heap, candidate))
+            });
+        scoring.exit();
+        heap
+    }
+
+    pub fn rank_with_metadata(&self, query: &[&str]) -> RankedResults {
+        RankedResults::from_hits(self.rank(query))
+    }
+
+    /// Expands query terms to their synonyms, whose scores are multiplied by
+    /// `weight` so that documents with the exact term still rank higher.
+    pub fn set_synonyms(&mut self, synonyms: &Synonyms, weight: f32) {
+        self.synonyms = synonyms.analyzed(&|text| self.analyze(text));
+        self.synonym_weight = weight;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// The query a search for `text` runs: the parsed query if it uses any
+    /// query syntax, otherwise its analyzed terms OR-ed together. Either way
+    /// fuzzy terms, prefixes and synonyms are expanded.
+    pub fn text_query(&self, text: &str) -> Option<Query> {
+        self.text_query_until(text, &Deadline::default())
+    }
+
+    /// Like [`BM25Index::text_query`], with fuzzy terms expanded to the
+    /// terms found before the deadline.
+    fn text_query_until(&self, text: &str, deadline: &Deadline) -> Option<Query> {
+        if query::is_structured(text) {
+            return self.parse_query_until(text, deadline);
+        }
+        let terms = self.analyze(text);
+        if terms.is_empty() {
+            return None;
+        }
+        let clauses = terms
+            .into_iter()
+            .map(|term| (Occur::Should, Query::Term(term)))
+            .collect();
+        Some(self.expand(Query::Group(clauses), deadline))
+    }
+
+    /// Expands `query` with pseudo-relevance feedback from its best hits among
+    /// the documents `allowed` keeps, if feedback is enabled.
+    fn feedback_query(
+        &self,
+        query: Query,
+        allowed: impl Fn(&i32) -> bool,
+        deadline: &Deadline,
+    ) -> Query {
+        let Some(feedback) = &self.feedback else {
+            return query;
+        };
+        let candidates = self.matching_docs_until(&query, deadline);
+        let candidates = candidates.into_iter().filter(allowed);
+        let mut hits = self.rank_query_candidates(&query, candidates.collect(), deadline);
+        hits.truncate(feedback.docs);
+        let docs = hits.iter().map(|&(doc, score)| {
+            let terms = self.doc_terms[doc as usize]
+                .iter()
+                .map(|&(id, tf)| (self.terms.name(id), tf))
+                .collect();
+            (score, terms, self.doc_lengths[doc as usize])
+        });
+        let model = feedback.relevance_model(docs);
+        feedback.expand(query, &model)
+    }
+
+    /// `query` as [`BM25Index::search`] runs it without filters, i.e. expanded
+    /// with pseudo-relevance feedback if that is enabled.
+    pub fn with_feedback(&self, query: Query) -> Query {
+        self.feedback_query(query, |_| true, &Deadline::default())
+    }
+
+    /// Makes searches expand their query with the best terms of their first
+    /// results and run it again. `None`, the default, turns it off.
+    pub fn set_feedback(&mut self, feedback: Option<Feedback>) {
+        self.feedback = feedback;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Parses a boolean query using this index's analyzer, expanding fuzzy
+    /// terms, prefixes and synonyms against the term dictionary.
+    pub fn parse_query(&self, text: &str) -> Option<Query> {
+        self.parse_query_until(text, &Deadline::default())
+    }
+
+    fn parse_query_until(&self, text: &str, deadline: &Deadline) -> Option<Query> {
+        query::parse(text, &|word| self.analyze(word)).map(|query| self.expand(query, deadline))
+    }
+
+    fn expand(&self, query: Query, deadline: &Deadline) -> Query {
+        let any_of = |term: String, mut terms: Vec<String>| {
+            if terms.len() == 1 {
+                Query::Term(terms.remove(0))
+            } else if terms.is_empty() {
+                Query::Term(term)
+            } else {
+                Query::Group(
+                    terms
+                        .into_iter()
+                        .map(|term| (Occur::Should, Query::Term(term)))
+                        .collect(),
+                )
+            }
+        };
+        match query {
+            Query::Fuzzy(term, distance) => {
+                let terms = self.fuzzy_terms_until(&term, distance, deadline);
+                any_of(term, terms)
+            }
+            Query::Prefix(prefix) => {
+                let terms = self.suggest(&prefix, MAX_PREFIX_EXPANSIONS);
+                any_of(prefix, terms)
+            }
+            Query::Term(term) if self.synonyms.contains_key(&term) => {
+                let mut clauses = vec![(Occur::Should, Query::Term(term.clone()))];
+                for expansion in &self.synonyms[&term] {
+                    let expansion = match expansion.as_slice() {
+                        [term] => Query::Term(term.clone()),
+                        phrase => Query::Phrase(phrase.to_vec()),
+                    };
+                    clauses.push((
+                        Occur::Should,
+                        Query::Boost(Box::new(expansion), self.synonym_weight),
+                    ));
+                }
+                Query::Group(clauses)
+            }
+            Query::Group(clauses) => Query::Group(
+                clauses
+                    .into_iter()
+                    .map(|(occur, clause)| (occur, self.expand(clause, deadline)))
+                    .collect(),
+            ),
+            Query::Boost(query, boost) => {
+                Query::Boost(Box::new(self.expand(*query, deadline)), boost)
+            }
+            query => query,
+        }
+    }
+
+    /// Up to `n` indexed terms starting with `prefix`, most frequent (by
+    /// document frequency) first, for autocompletion.
+    pub fn suggest(&self, prefix: &str, n: usize) -> Vec<String> {
+        let mut terms: Vec<(&String, i32)> = self
+            .dictionary
+            .range(prefix.to_string()..)
+            .take_while(|term| term.starts_with(prefix))
+            .map(|term| {
+                (
+                    term,
+                    self.posting_list(term).map_or(0, |docs| docs.len() as i32),
+                )
+            })
+            .collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        terms
+            .into_iter()
+            .take(n)
+            .map(|(term, _)| term.clone())
+            .collect()
+    }
+
+    /// Indexed terms within `max_distance` edits (Levenshtein) of `term`, in
+    /// dictionary order.
+    pub fn fuzzy_terms(&self, term: &str, max_distance: u8) -> Vec<String> {
+        self.fuzzy_terms_until(term, max_distance, &Deadline::default())
+    }
+
+    /// Like [`BM25Index::fuzzy_terms`], among the terms looked at before the
+    /// deadline.
+    fn fuzzy_terms_until(&self, term: &str, max_distance: u8, deadline: &Deadline) -> Vec<String> {
+        let length = term.chars().count();
+        let max_distance = max_distance as usize;
+        self.dictionary
+            .iter()
+            .take_while(|_| !deadline.expired())
+            .filter(|candidate| candidate.chars().count().abs_diff(length) <= max_distance)
+            .filter(|candidate| edit_distance(term, candidate, max_distance).is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// The indexed term closest to an unknown `term`: the fewest edits wins,
+    /// then the highest document frequency. `None` if `term` is indexed or
+    /// nothing is within [`query::MAX_EDIT_DISTANCE`].
+    pub fn correct_term(&self, term: &str) -> Option<String> {
+        if self.posting_list(term).is_some() {
+            return None;
+        }
+        let length = term.chars().count();
+        let max_distance = query::MAX_EDIT_DISTANCE as usize;
+        self.dictionary
+            .iter()
+            .filter(|candidate| candidate.chars().count().abs_diff(length) <= max_distance)
+            .filter_map(|candidate| {
+                let distance = edit_distance(term, candidate, max_distance)?;
+                Some((distance, Reverse(self.doc_freq(candidate)), candidate))
+            })
+            .min()
+            .map(|(_, _, candidate)| candidate.clone())
+    }
+
+    /// Rewrites a query with every word that is not in the index replaced by
+    /// [`BM25Index::correct_term`], or `None` if no word changed. Operators,
+    /// fuzzy and prefix words are kept as typed.
+    pub fn did_you_mean(&self, text: &str) -> Option<String> {
+        let mut changed = false;
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|word| {
+                if matches!(word, "AND" | "OR" | "NOT") || word.contains('~') || word.ends_with('*')
+                {
+                    return word.to_string();
+                }
+                let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+                let mut terms = self.analyze(core);
+                match (terms.pop(), terms.is_empty()) {
+                    (Some(term), true) => match self.correct_term(&term) {
+                        Some(correction) => {
+                            changed = true;
+                            word.replacen(core, &correction, 1)
+                        }
+                        None => word.to_string(),
+                    },
+                    _ => word.to_string(),
+                }
+            })
+            .collect();
+        changed.then(|| words.join(" "))
+    }
+
+    fn term_docs(&self, term: &str) -> HashSet<i32> {
+        let mut docs: HashSet<i32> = self
+            .posting_list(term)
+            .map(|docs| docs.docs().collect())
+            .unwrap_or_default();
+        if self.uses_fields() {
+            docs.extend(self.field_only_docs(term));
+        }
+        docs
+    }
+
+    /// How many times `phrase` occurs, as adjacent tokens, in each document.
+    pub fn phrase_frequencies(&self, phrase: &[String]) -> HashMap<i32, i32> {
+        let mut frequencies = HashMap::new();
+        let Some(first) = phrase.first().and_then(|term| self.posting_list(term)) else {
+            return frequencies;
+        };
+        let mut rest: Vec<HashMap<i32, Posting>> = vec![];
+        for term in &phrase[1..] {
+            match self.posting_list(term) {
+                Some(docs) => rest.push(docs.iter().map(|p| (p.doc, p)).collect()),
+                None => return frequencies,
+            }
+        }
+        for posting in first.iter() {
+            let count = posting
+                .positions
+                .iter()
+                .filter(|&&start| {
+                    rest.iter().enumerate().all(|(offset, docs)| {
+                        docs.get(&posting.doc).is_some_and(|other| {
+                            other
+                                .positions
+                                .binary_search(&(start + offset as i32 + 1))
+                                .is_ok()
+                        })
+                    })
+                })
+                .count();
+            if count > 0 {
+                frequencies.insert(posting.doc, count as i32);
+            }
+        }
+        frequencies
+    }
+
+    fn phrase_stats(&self, phrase: &[String]) -> PhraseStats {
+        let frequencies = self.phrase_frequencies(phrase);
+        PhraseStats {
+            collection_freq: frequencies.values().sum(),
+            frequencies,
+            weight: 1.0,
+            field: None,
+        }
+    }
+
+    /// How many times `term` occurs in `field` of each document, `body` being
+    /// the document text.
+    fn field_term_stats(&self, field: &str, term: &str) -> PhraseStats {
+        let (frequencies, field): (HashMap<i32, i32>, _) = if field == "body" {
+            let frequencies = self.posting_list(term).map(|postings| {
+                postings
+                    .iter()
+                    .map(|posting| (posting.doc, posting.tf))
+                    .collect()
+            });
+            (frequencies.unwrap_or_default(), None)
+        } else {
+            let frequencies = self.fields.get(field).map(|stored| {
+                self.field_term_docs(field, term)
+                    .into_iter()
+                    .map(|doc| (doc, stored.tf(term, doc as usize)))
+                    .collect()
+            });
+            (frequencies.unwrap_or_default(), Some(field.to_string()))
+        };
+        PhraseStats {
+            collection_freq: frequencies.values().sum(),
+            frequencies,
+            weight: 1.0,
+            field,
+        }
+    }
+
+    /// Live documents with `term` in `field`, `body` being the document text.
+    fn field_term_docs(&self, field: &str, term: &str) -> HashSet<i32> {
+        if field == "body" {
+            return self
+                .posting_list(term)
+                .map(|docs| docs.docs().collect())
+                .unwrap_or_default();
+        }
+        self.fields
+            .get(field)
+            .and_then(|field| field.docs.get(term))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&doc| self.is_live(doc as usize))
+            .collect()
+    }
+
+    /// The scoring phrases and `field:term` terms of `query`, weighted, each
+    /// with the words it is explained as.
+    fn query_phrase_stats(&self, query: &Query) -> Vec<(Vec<String>, PhraseStats)> {
+        let phrases = query
+            .weighted_phrases()
+            .into_iter()
+            .map(|(phrase, weight)| {
+                let stats = self.phrase_stats(&phrase);
+                (phrase, PhraseStats { weight, ..stats })
+            });
+        let field_terms = query
+            .weighted_field_terms()
+            .into_iter()
+            .map(|(field, term, weight)| {
+                let stats = self.field_term_stats(&field, &term);
+                (
+                    vec![format!("{}:{}", field, term)],
+                    PhraseStats { weight, ..stats },
+                )
+            });
+        phrases.chain(field_terms).collect()
+    }
+
+    /// Documents matching the boolean structure of `query`.
+    pub fn matching_docs(&self, query: &Query) -> HashSet<i32> {
+        self.matching_docs_until(query, &Deadline::default())
+    }
+
+    /// Like [`BM25Index::matching_docs`]; past the deadline, some documents
+    /// that match may be missing, but none that do not match is included.
+    fn matching_docs_until(&self, query: &Query, deadline: &Deadline) -> HashSet<i32> {
+        let mut docs = self.query_docs(query, deadline);
+        if self.deleted > 0 {
+            docs.retain(|&doc| self.is_live(doc as usize));
+        }
+        docs
+    }
+
+    /// The documents matching `query`, including deleted ones that are yet to
+    /// be compacted away.
+    fn query_docs(&self, query: &Query, deadline: &Deadline) -> HashSet<i32> {
+        let clauses = match query {
+            Query::Term(term) => return self.term_docs(term),
+            Query::FieldTerm(field, term) => return self.field_term_docs(field, term),
+            Query::Boost(query, _) => return self.query_docs(query, deadline),
+            Query::Fuzzy(term, distance) => {
+                return self
+                    .fuzzy_terms_until(term, *distance, deadline)
+                    .iter()
+                    .flat_map(|term| self.term_docs(term))
+                    .collect();
+            }
+            Query::Prefix(prefix) => {
+                return self
+                    .suggest(prefix, MAX_PREFIX_EXPANSIONS)
+                    .iter()
+                    .flat_map(|term| self.term_docs(term))
+                    .collect();
+            }
+            Query::Phrase(phrase) => {
+                return self.phrase_frequencies(phrase).into_keys().collect();
+            }
+            Query::Group(clauses) => clauses,
+        };
+        let mut must: Option<HashSet<i32>> = None;
+        let mut should: Option<HashSet<i32>> = None;
+        let mut must_not = HashSet::new();
+        for (occur, clause) in clauses {
+            if deadline.expired() {
+                break;
+            }
+            let docs = self.query_docs(clause, deadline);
+            match occur {
+                Occur::Must => {
+                    must = Some(match must {
+                        Some(must) => must.intersection(&docs).copied().collect(),
+                        None => docs,
+                    })
+                }
+                Occur::Should => should.get_or_insert_with(HashSet::new).extend(docs),
+                Occur::MustNot => must_not.extend(docs),
+            }
+        }
+        let mut docs = match (must, should) {
+            (Some(must), _) => must,
+            (None, Some(should)) => should,
+            (None, None) => (0..self.doc_terms.len())
+                .filter(|&doc| self.is_live(doc))
+                .map(|doc| doc as i32)
+                .collect(),
+        };
+        // Cut short, a required clause may be missing from the intersection,
+        // or documents to leave out from the exclusions, so none is known to
+        // match. Optional clauses only add documents.
+        if deadline.expired() && clauses.iter().any(|(occur, _)| *occur != Occur::Should) {
+            return HashSet::new();
+        }
+        docs.retain(|doc| !must_not.contains(doc));
+        docs
+    }
+
+    /// Ranks the documents matching a structured query by the BM25 score of its
+    /// non-negated terms. Each phrase is scored like a single term whose
+    /// frequency is the number of times the phrase occurs.
+    pub fn rank_query(&self, query: &Query) -> Vec<(i32, f32)> {
+        self.rank_query_until(query, &Deadline::default())
+    }
+
+    fn rank_query_until(&self, query: &Query, deadline: &Deadline) -> Vec<(i32, f32)> {
+        let candidates = self.matching_docs_until(query, deadline);
+        self.rank_query_candidates(query, candidates.into_iter().collect(), deadline)
+    }
+
+    fn rank_query_candidates(
+        &self,
+        query: &Query,
+        candidates: Vec<i32>,
+        deadline: &Deadline,
+    ) -> Vec<(i32, f32)> {
+        let terms = query.weighted_terms();
+        let terms: Vec<(&str, f32)> = terms.iter().map(|(t, w)| (t.as_str(), *w)).collect();
+        let phrases: Vec<PhraseStats> = self
+            .query_phrase_stats(query)
+            .into_iter()
+            .map(|(_, stats)| stats)
+            .collect();
+        self.score_candidates_with_phrases(&terms, &phrases, candidates, deadline)
+    }
+
+    /// The `k` best hits of each of `queries`, ranked as by
+    /// [`BM25Index::rank_query`], in the order of the queries. Queries are
+    /// ranked in parallel and bypass the query cache, for scoring many at
+    /// once offline.
+    pub fn rank_batch(&self, queries: &[Query], k: usize) -> Vec<Vec<(i32, f32)>> {
+        let _span = tracing::info_span!("rank_batch", queries = queries.len()).entered();
+        queries
+            .par_iter()
+            .map(|query| {
+                let mut hits = self.rank_query(query);
+                hits.truncate(k);
+                hits
+            })
+            .collect()
+    }
+
+    pub fn rank_query_page(&self, query: &Query, offset: usize, limit: usize) -> RankedResults {
+        self.rank_query_page_above(query, offset, limit, |_| true, &Deadline::default())
+    }
+
+    fn rank_query_page_above(
+        &self,
+        query: &Query,
+        offset: usize,
+        limit: usize,
+        keep: impl Fn(f32) -> bool,
+        deadline: &Deadline,
+    ) -> RankedResults {
+        self.cached_page(
+            format!("{:?}", query),
+            offset,
+            limit,
+            keep,
+            deadline,
+            || RankedResults::from_hits(self.rank_query_until(query, deadline)),
+        )
+    }
+
+    /// The byte ranges of `text`, the text of a document, that a search for
+    /// `query` matches: the words of its terms, including those fuzzy terms,
+    /// prefixes and synonyms expand to, and its phrases. The index keeps no
+    /// text, so the caller passes it in. Lets a UI highlight results itself
+    /// without analyzing their text the way the index does.
+    pub fn highlight(&self, text: &str, query: &str) -> Vec<Span> {
+        let Some(query) = self.text_query(query) else {
+            return vec![];
+        };
+        let terms: HashSet<String> = query.scoring_terms().into_iter().collect();
+        let analyze = |word: &str| self.analyze(word);
+        snippet::highlight(text, &terms, &query.scoring_phrases(), &analyze)
+    }
+
+    /// Runs a query string, parsed as a structured query if it uses any query
+    /// syntax. Filters are applied to the candidates before any is scored, and
+    /// the minimum score before the page is taken.
+    pub fn search(&self, request: &SearchRequest) -> RankedResults {
+        self.search_until(request, &Deadline::default())
+    }
+
+    /// Like [`BM25Index::search`], but once `deadline` passes or is
+    /// cancelled, fuzzy terms stop expanding and no more candidates are
+    /// collected or scored; the best of those scored are returned, marked
+    /// `timed_out`. Results cut short are not cached.
+    pub fn search_until(&self, request: &SearchRequest, deadline: &Deadline) -> RankedResults {
+        let _span = tracing::info_span!("search", query = %request.query).entered();
+        let started = Instant::now();
+        let mut ranked = self.run_search(request, deadline);
+        ranked.timed_out |= deadline.reached();
+        tracing::info!(
+            elapsed_us = started.elapsed().as_micros() as u64,
+            hits = ranked.hits.len(),
+            timed_out = ranked.timed_out,
+            "searched"
+        );
+        ranked
+    }
+
+    fn run_search(&self, request: &SearchRequest, deadline: &Deadline) -> RankedResults {
+        let allowed = |doc: &i32| {
+            request
+                .filters
+                .iter()
+                .all(|filter| filter.matches(&self.metadata[*doc as usize]))
+        };
+        let structured = query::is_structured(&request.query);
+        if structured || !self.synonyms.is_empty() || self.feedback.is_some() {
+            let Some(query) = self.text_query_until(&request.query, deadline) else {
+                return RankedResults::from_hits(vec![]);
+            };
+            let rank = |query: &Query| {
+                let candidates = self.matching_docs_until(query, deadline);
+                let candidates = candidates.into_iter().filter(allowed);
+                let mut hits = self.rank_query_candidates(query, candidates.collect(), deadline);
+                hits.retain(|(_, score)| request.keeps(*score));
+                RankedResults::from_hits(page(hits, request.offset, request.limit))
+            };
+            // Facets count the matches of the query as given, not as
+            // expanded by feedback, which only reorders the ranking.
+            let facets = self.count_facets(&request.facets, || {
+                let matches = self.matching_docs_until(&query, deadline);
+                matches.into_iter().filter(allowed).collect()
+            });
+            let mut ranked = if self.feedback.is_some() {
+                let key = format!("feedback {}", request.cache_key(&query));
+                let ranked = self.cached(key, deadline, || {
+                    rank(&self.feedback_query(query, allowed, deadline))
+                });
+                (*ranked).clone()
+            } else if request.filters.is_empty() {
+                let keep = |score| request.keeps(score);
+                let (offset, limit) = (request.offset, request.limit);
+                self.rank_query_page_above(&query, offset, limit, keep, deadline)
+            } else {
+                let ranked = self.cached(request.cache_key(&query), deadline, || rank(&query));
+                (*ranked).clone()
+            };
+            ranked.facets = facets;
+            ranked
+        } else {
+            let terms = self.analyze(&request.query);
+            let terms: Vec<&str> = terms.iter().map(|term| term.as_str()).collect();
+            let ranked = self.cached(request.cache_key(&terms), deadline, || {
+                let candidates = self
+                    .candidates(&terms, deadline)
+                    .into_iter()
+                    .filter(allowed);
+                let k = request.offset.saturating_add(request.limit);
+                let keep = |score| request.keeps(score);
+                let hits = self.top_k(&unweighted(&terms), candidates, k, keep, deadline);
+                RankedResults::from_hits(page(hits, request.offset, request.limit))
+            });
+            let mut ranked = (*ranked).clone();
+            ranked.facets = self.count_facets(&request.facets, || {
+                let candidates = self.candidates(&terms, deadline);
+                candidates.into_iter().filter(allowed).collect()
+            });
+            ranked
+        }
+    }
+
+    /// Counts `keys` over the documents `matches` gives, which are only
+    /// collected if there are keys to count.
+    fn count_facets(&self, keys: &[String], matches: impl FnOnce() -> Vec<i32>) -> Vec<Facet> {
+        if keys.is_empty() {
+            return vec![];
+        }
+        let matches = matches();
+        facet::count(
+            keys,
+            matches.iter().map(|&doc| &self.metadata[doc as usize]),
+        )
+    }
+
+    /// Re-ranks the first `depth` of `hits`, which are in ranked order, with
+    /// `reranker`, and combines its scores with theirs by `fusion`. `text`
+    /// gives the text of a document. Only the re-ranked hits are returned.
+    pub fn rerank(
+        &self,
+        query: &str,
+        hits: &[(i32, f32)],
+        depth: usize,
+        text: impl Fn(usize) -> String,
+        reranker: &dyn Reranker,
+        fusion: Fusion,
+    ) -> Result<Vec<(i32, f32)>, Bm25Error> {
+        let hits = &hits[..depth.min(hits.len())];
+        let candidates: Vec<Candidate> = hits
+            .iter()
+            .map(|&(doc, score)| Candidate {
+                doc: doc as usize,
+                id: self.doc_id(doc as usize),
+                text: text(doc as usize),
+                score,
+            })
+            .collect();
+        let scores = reranker.score(query, &candidates)?;
+        if scores.len() != candidates.len() {
+            return Err(Bm25Error::Rerank {
+                message: format!(
+                    "got {} scores for {} candidates",
+                    scores.len(),
+                    candidates.len()
+                ),
+            });
+        }
+        Ok(fusion.fuse(hits, &scores))
+    }
+
+    /// Sends the hits of [`BM25Index::rank_top_k`] in ranked order, each as
+    /// soon as it is taken off the top-k heap rather than once all `k` are
+    /// sorted, stopping early if the receiver is dropped.
+    pub fn rank_streaming(&self, query: &[&str], k: usize, sender: Sender<SearchHit>) {
+        let deadline = Deadline::default();
+        let candidates = self.candidates(query, &deadline);
+        let heap = self.top_k_heap(&unweighted(query), candidates, k, |_| true, &deadline);
+        let mut heap: BinaryHeap<RankedDoc> = heap.into_iter().map(|Reverse(hit)| hit).collect();
+        while let Some(RankedDoc { score, doc }) = heap.pop() {
+            if sender.send(SearchHit { index: doc, score }).is_err() {
+                break;
+            }
+        }
+    }
+
+    pub fn rank_page(&self, query: &[&str], offset: usize, limit: usize) -> Vec<(i32, f32)> {
+        self.rank_page_with_metadata(query, offset, limit).hits
+    }
+
+    /// Returns one page of the ranking. The full rankings of recent queries
+    /// are cached so paging through them does not re-sort.
+    pub fn rank_page_with_metadata(
+        &self,
+        query: &[&str],
+        offset: usize,
+        limit: usize,
+    ) -> RankedResults {
+        self.cached_page(
+            format!("{:?}", query),
+            offset,
+            limit,
+            |_| true,
+            &Deadline::default(),
+            || self.rank_with_metadata(query),
+        )
+    }
+
+    fn cached_page(
+        &self,
+        key: String,
+        offset: usize,
+        limit: usize,
+        keep: impl Fn(f32) -> bool,
+        deadline: &Deadline,
+        rank: impl FnOnce() -> RankedResults,
+    ) -> RankedResults {
+        let ranked = self.cached(key, deadline, rank);
+        let hits = ranked
+            .hits
+            .iter()
+            .filter(|(_, score)| keep(*score))
+            .skip(offset)
+            .take(limit)
+            .copied()
+            .collect();
+        RankedResults {
+            hits,
+            all_tied: ranked.all_tied,
+            timed_out: ranked.timed_out,
+            facets: ranked.facets.clone(),
+        }
+    }
+
+    /// The cached results for `key`, computing and caching them if there are
+    /// none. The cache is not locked while `rank` runs. Results that `rank`
+    /// cut short at the deadline are not cached.
+    fn cached(
+        &self,
+        key: String,
+        deadline: &Deadline,
+        rank: impl FnOnce() -> RankedResults,
+    ) -> Arc<RankedResults> {
+        if let Some(ranked) = self.cache.lock().unwrap().get(&key) {
+            return ranked;
+        }
+        let mut ranked = rank();
+        if deadline.reached() {
+            ranked.timed_out = true;
+            return Arc::new(ranked);
+        }
+        let ranked = Arc::new(ranked);
+        self.cache.lock().unwrap().insert(key, Arc::clone(&ranked));
+        ranked
+    }
+}
@@ -1,191 +1,2391 @@
+mod config;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod input;
+mod metrics;
+mod output;
+mod progress;
+mod repl;
+mod reranker;
+mod server;
+mod watch;
+
+use config::Config;
+use encoding_rs::Encoding;
+use fulltext::{
+    analyzer::{AnalyzerConfig, Ngrams, Stemmer, TokenizerKind},
+    chunk::Chunking,
+    collections::Collections,
+    disk::DiskIndex,
+    eval::{self, Evaluation, Measure, ParameterRange, Qrels},
+    extract::Extractors,
+    feedback::Feedback,
+    format::{self, AnalyzerConfigV1, IndexHeader, FORMAT_VERSION},
+    query::{self, Query},
+    rerank::{self, Fusion},
+    scorer::{self, IdfMode},
+    shard::ShardedIndex,
+    snapshot, stopwords,
+    synonyms::Synonyms,
+    BM25Index, Bm25Error, DocId, Explanation, Filter, IndexStats, LengthAdjustment, Metadata,
+    RankedResults, SearchRequest, TermOrder,
+};
+#[cfg(feature = "sqlite")]
+use fulltext::{sqlite::SqliteStorage, storage::Storage};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use input::Columns;
+use output::{BatchHit, BatchResults, Correction, Display, OutputFormat};
+use progress::IndexProgress;
+use rayon::prelude::*;
+use repl::{LineReader, ReplCommand};
+use reranker::CommandReranker;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    collections::HashSet,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     env,
-    fs::{self},
-    io::{self, Write},
-    time::Instant,
+    error::Error,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, BufReader, IsTerminal, Write},
+    path::Path,
+    time::{Duration, Instant},
 };
+use tracing_subscriber::{filter::LevelFilter, fmt::format::FmtSpan};
+use walkdir::WalkDir;
+use watch::DirectoryWatcher;
 
-struct BM25<'a> {
-    corpus: &'a Vec<Vec<String>>,
-    k1: f32,
-    b: f32,
-    doc_lengths: Vec<i32>,
-    avg_doc_length: i32,
-    doc_count: i32,
-    tf_cache: Vec<HashMap<String, i32>>,
-    term_idf: HashMap<String, f32>,
+/// Recursively lists files under `directory_path` whose path relative to it
+/// matches `include` (or everything, if `include` is empty) and not `exclude`.
+/// Entries that cannot be read are skipped with a warning.
+fn list_files_with_full_paths(
+    directory_path: &str,
+    include: &GlobSet,
+    exclude: &GlobSet,
+) -> Vec<String> {
+    let mut file_paths = vec![];
+    for entry in WalkDir::new(directory_path).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("Warning: {}; skipping", err);
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(directory_path)
+            .unwrap_or(entry.path());
+        if !is_included(relative, include, exclude) {
+            continue;
+        }
+        if let Some(path) = entry.path().to_str() {
+            file_paths.push(path.to_string());
+        }
+    }
+    file_paths
 }
 
-struct Counter<T: Eq + std::hash::Hash> {
-    counts: HashMap<T, i32>,
+/// Whether a path relative to the indexed directory matches `include` (or
+/// `include` is empty) and does not match `exclude`.
+fn is_included(relative: &Path, include: &GlobSet, exclude: &GlobSet) -> bool {
+    (include.is_empty() || include.is_match(relative)) && !exclude.is_match(relative)
 }
 
-impl<T: Eq + std::hash::Hash> Counter<T> {
-    fn new() -> Counter<T> {
-        Counter {
-            counts: HashMap::new(),
-        }
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
     }
+    builder.build()
+}
 
-    fn increment(&mut self, item: T) {
-        let count = self.counts.entry(item).or_insert(0);
-        *count += 1;
+fn load_word_list(file_path: &str) -> std::io::Result<HashSet<String>> {
+    let file = std::fs::read_to_string(file_path)?;
+    let words: HashSet<String> = file
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .flat_map(|line| line.split_whitespace())
+        .map(|word| word.to_lowercase())
+        .collect();
+    Ok(words)
+}
+
+fn title_text(file_path: &str) -> String {
+    Path::new(file_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("")
+        .replace(|c: char| !c.is_alphanumeric(), " ")
+}
+
+/// Splits a leading block of `key: value` lines between `---` markers off a
+/// document, as used by Markdown front matter.
+fn split_front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return (vec![], text);
+    };
+    let Some(end) = rest.find("\n---\n") else {
+        return (vec![], text);
+    };
+    let fields = rest[..end]
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty() && key != "body")
+        .collect();
+    (fields, &rest[end + "\n---\n".len()..])
+}
+
+/// The fields and body of a file. Every document gets a `title` field, taken
+/// from its file name unless the front matter sets one.
+fn document_fields<'a>(file_path: &str, text: &'a str) -> (Vec<(String, String)>, &'a str) {
+    let (mut fields, body) = split_front_matter(text);
+    if !fields.iter().any(|(key, _)| key == "title") {
+        fields.push(("title".to_string(), title_text(file_path)));
     }
+    (fields, body)
+}
 
-    fn get_counts(&self) -> &HashMap<T, i32> {
-        &self.counts
+fn parse_boost(value: &str) -> Option<(String, f32)> {
+    let (field, boost) = value.split_once('=')?;
+    Some((field.to_string(), boost.parse().ok()?))
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {0} [options] <text_file_directory>\n       \
+         {0} index [options] <text_file_directory> [--output <index_file>] [--mmap]\n       \
+         {0} search [options] <text_file_directory|index_file> [query...] [--limit <n>] [--offset <n>] [--min-score <score>] [--watch]\n       \
+         {0} search [options] --input <corpus.jsonl|corpus.csv> [--column <id|body>=<name>]... [query...]\n       \
+         {0} add [options] <index_file> <file>...\n       \
+         {0} remove <index_file> <file>...\n       \
+         {0} compact <index_file>\n       \
+         {0} upgrade <index_file>\n       \
+         {0} backup <index_file> <backup_file>\n       \
+         {0} restore <backup_file> <index_file>\n       \
+         {0} serve [options] <text_file_directory|index_file> [--addr <host:port>] [--grpc] [--timeout <ms>]\n       \
+         {0} search [options] --collection <name>... [query...]\n       \
+         {0} stats [options] <text_file_directory|index_file>\n       \
+         {0} terms [options] <text_file_directory|index_file> [--by <df|idf>] [--limit <n>]\n       \
+         {0} eval [options] <text_file_directory|index_file> --queries <queries.tsv> --qrels <qrels>\n       \
+         {0} tune [options] <text_file_directory|index_file> --queries <queries.tsv> --qrels <qrels> [--k1-range <min..max[:step]>] [--b-range <min..max[:step]>] [--measure <map|ndcg|mrr|recall>]\n       \
+         {0} batch [options] <text_file_directory|index_file> --queries <queries.tsv> [--limit <n>]\n\n\
+         Options: [--include <glob>]... [--exclude <glob>]... [--title-bonus <score>] [--stopwords <path>] [--stop-language <language>] [--tokenizer <unicode|whitespace|cjk>] [--strip-punctuation] [--stemmer <language>] [--stem-exceptions <path>] [--bigrams] [--ngram <[word:|char:]min..max>]... [--format <text|json|tsv|feed>] [--snippets] [--explain] [--auto-correct] [--boost <field=weight>]... [--field-b <field=b>]... [--doc-boost <key>] [--filter <key(=|!=|<|<=|>|>=)value>]... [--facet <key>]... \
+         [--chunk <words[:overlap]|paragraphs>] [--aggregate <max|sum>] \
+         [--synonyms <path>] [--synonym-weight <weight>] [--proximity-weight <weight>] [--feedback <docs[:terms[:weight]]>] [--rerank <command>] [--rerank-depth <n>] [--fusion <replace|rrf[:k]>] [--threads <n>] [--log-level <error|warn|info|debug|trace>] [--cache-size <n>] [--shards <n>] [--quiet] [--duplicates <skip|alias|keep>] [--encoding <label>] \
+         [--scorer <bm25|bm25+[:DELTA]|bm25l[:DELTA]|tfidf|dirichlet[:MU]>] \
+         [--k1 <k1>] [--b <b>] [--idf <classic|clamp|floor[:WEIGHT]|lucene>] [--config <path>] \
+         [--length-adjustment <none|linear:WEIGHT|log:WEIGHT>]",
+        program
+    );
+    std::process::exit(1);
+}
+
+enum Command {
+    Interactive,
+    Index,
+    Search,
+    Add,
+    Remove,
+    Compact,
+    Upgrade,
+    Backup,
+    Restore,
+    Serve,
+    Stats,
+    Terms,
+    Eval,
+    Tune,
+    Batch,
+}
+
+struct Options {
+    command: Command,
+    path: String,
+    files: Vec<String>,
+    output: String,
+    addr: String,
+    /// Serves gRPC rather than HTTP.
+    grpc: bool,
+    /// How long the server lets a search run before returning what it has.
+    timeout: Option<Duration>,
+    include: GlobSet,
+    exclude: GlobSet,
+    title_bonus: f32,
+    analysis: AnalyzerConfig,
+    /// Which of `analysis` were given rather than left at their defaults.
+    given_analysis: GivenAnalysis,
+    length_adjustment: LengthAdjustment,
+    display: Display,
+    auto_correct: bool,
+    watch: bool,
+    limit: usize,
+    /// Number of best results skipped, to show a later page.
+    offset: usize,
+    /// Results scoring below it are left out, as are scores of zero or below
+    /// without it.
+    min_score: Option<f32>,
+    boosts: HashMap<String, f32>,
+    /// Without `--scorer` or a configured one, an index is searched with the
+    /// scorer it was built with.
+    scorer: Option<String>,
+    /// Fields' replacements of `b`.
+    field_b: HashMap<String, f32>,
+    /// The metadata value scores are multiplied by.
+    doc_boost: Option<String>,
+    k1: Option<f32>,
+    b: Option<f32>,
+    /// Saved with an index built with it, and otherwise the one it was built
+    /// with is kept.
+    idf_mode: Option<IdfMode>,
+    input: Option<String>,
+    columns: Columns,
+    filters: Vec<Filter>,
+    /// Metadata keys whose values are counted over the matching documents.
+    facets: Vec<String>,
+    chunking: Option<Chunking>,
+    aggregate: Option<Aggregate>,
+    synonyms: Synonyms,
+    synonym_weight: f32,
+    threads: Option<usize>,
+    /// The most detailed level of the spans and events that are logged to
+    /// stderr; nothing is logged without it.
+    log_level: Option<LevelFilter>,
+    /// How many queries' results the index caches.
+    cache_size: usize,
+    /// How many shards a searched directory or record file is indexed into.
+    shards: usize,
+    mmap: bool,
+    /// Leaves out the progress bar and summary while indexing.
+    quiet: bool,
+    proximity_weight: f32,
+    /// Pseudo-relevance feedback that queries are expanded with.
+    feedback: Option<Feedback>,
+    /// A program that re-ranks the best `rerank_depth` hits, and how its
+    /// scores are combined with theirs.
+    rerank: Option<String>,
+    rerank_depth: usize,
+    fusion: Fusion,
+    /// How files that are not UTF-8 are decoded.
+    encoding: &'static Encoding,
+    duplicates: Duplicates,
+    /// How `terms` orders the vocabulary.
+    term_order: TermOrder,
+    /// The `query_id<TAB>text` file and TREC qrels that `eval` measures.
+    queries: Option<String>,
+    qrels: Option<String>,
+    /// What `tune` tries and maximizes.
+    k1_range: ParameterRange,
+    b_range: ParameterRange,
+    measure: Measure,
+    /// Where `tune` writes the parameters it finds.
+    config_path: String,
+    /// The options of each collection, when several are searched at once.
+    collections: Vec<(String, Options)>,
+}
+
+impl Options {
+    /// The search for `query` with the options' filters, page and minimum
+    /// score.
+    fn request(&self, query: &str) -> SearchRequest {
+        SearchRequest {
+            query: query.to_string(),
+            filters: self.filters.clone(),
+            offset: self.offset,
+            limit: self.limit,
+            min_score: self.min_score,
+            facets: self.facets.clone(),
+        }
+    }
+
+    fn extractors(&self) -> Extractors {
+        Extractors::default().with_fallback_encoding(self.encoding)
     }
 }
 
-impl<'a> BM25<'a> {
-    fn calculate_idf(&mut self) {
-        let mut doc_freq: HashMap<&String, i32> = HashMap::new();
+/// What indexing does with a file whose content was already indexed from
+/// another file.
+#[derive(Clone, Copy, PartialEq)]
+enum Duplicates {
+    /// Leaves it out.
+    Skip,
+    /// Leaves it out, listing its path with the file it duplicates.
+    Alias,
+    /// Indexes it as a document of its own.
+    Keep,
+}
 
-        for doc in self.corpus {
-            let mut unique_terms = doc.iter().collect::<HashSet<_>>();
-            for term in unique_terms.drain() {
-                *doc_freq.entry(term).or_insert(0) += 1;
-            }
+impl Duplicates {
+    fn parse(value: &str) -> Option<Duplicates> {
+        match value {
+            "skip" => Some(Duplicates::Skip),
+            "alias" => Some(Duplicates::Alias),
+            "keep" => Some(Duplicates::Keep),
+            _ => None,
         }
+    }
+}
 
-        for (term, &count) in &doc_freq {
-            let idf = ((self.doc_count as f32 - count as f32 + 0.5) / (count as f32 + 0.5)).ln();
-            self.term_idf.insert(term.to_string(), idf);
+/// How the scores of a file's passages combine into one result per file.
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Max,
+    Sum,
+}
+
+impl Aggregate {
+    fn parse(value: &str) -> Option<Aggregate> {
+        match value {
+            "max" => Some(Aggregate::Max),
+            "sum" => Some(Aggregate::Sum),
+            _ => None,
         }
     }
 
-    fn calculate_bm25_score(&self, query: &Vec<&str>, doc_index: usize) -> f32 {
-        let mut score: f32 = 0.0;
-        for term in query {
-            if let Some(hash) = self.tf_cache.get(doc_index) {
-                let tf = hash.get(*term).unwrap_or(&0);
-                let idf = self.term_idf.get(*term).unwrap_or(&0.0);
-                let numerator = *tf as f32 * (self.k1 + 1.0);
-                let denominator = *tf as f32
-                    + self.k1
-                        * (1.0 - self.b
-                            + self.b
-                                * (*self.doc_lengths.get(doc_index).unwrap_or(&0) as f32
-                                    / self.avg_doc_length as f32));
-                score += idf * (numerator / denominator);
+    /// Merges ranked hits with the same name into the best-scoring one.
+    fn apply(self, hits: Vec<(i32, f32)>, name: impl Fn(i32) -> String) -> Vec<(i32, f32)> {
+        let mut merged: Vec<(i32, f32)> = vec![];
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (doc, score) in hits {
+            match positions.get(&name(doc)) {
+                Some(&position) => {
+                    if let Aggregate::Sum = self {
+                        merged[position].1 += score;
+                    }
+                }
+                None => {
+                    positions.insert(name(doc), merged.len());
+                    merged.push((doc, score));
+                }
             }
         }
-        score
+        merged.sort_by(|a, b| b.1.total_cmp(&a.1));
+        merged
     }
+}
 
-    fn rank_documents(&self, query: Vec<&str>) -> Vec<(i32, f32)> {
-        let mut ranks: Vec<(i32, f32)> = vec![];
-        for i in 0..self.doc_count as usize {
-            ranks.push((i as i32, self.calculate_bm25_score(&query, i)));
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    /// Text of the documents that have no file to read it back from.
+    texts: HashMap<DocId, String>,
+    chunking: Option<Chunking>,
+    analysis: AnalyzerConfig,
+    index: BM25Index,
+    /// The scorer it was built with, which is kept in the header.
+    #[serde(skip)]
+    scorer: String,
+    /// How files are read; set from the options of each run.
+    #[serde(skip)]
+    extractors: Extractors,
+}
+
+/// The analysis settings given on the command line or in the config file.
+/// An index searched or added to with some of them is checked against them
+/// only, keeping its own for the rest.
+#[derive(Debug, Clone, Copy, Default)]
+struct GivenAnalysis {
+    tokenizer: bool,
+    strip_punctuation: bool,
+    stopwords: bool,
+    stemmer: bool,
+    stem_exceptions: bool,
+    bigrams: bool,
+    ngrams: bool,
+}
+
+impl GivenAnalysis {
+    /// `built` with the settings of `given` that were given in their place.
+    fn merged(&self, built: &AnalyzerConfig, given: &AnalyzerConfig) -> AnalyzerConfig {
+        let mut merged = built.clone();
+        if self.tokenizer {
+            merged.tokenizer = given.tokenizer;
         }
-        ranks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        ranks
+        if self.strip_punctuation {
+            merged.strip_punctuation = given.strip_punctuation;
+        }
+        if self.stopwords {
+            merged.stopwords = given.stopwords.clone();
+        }
+        if self.stemmer {
+            merged.stemmer = given.stemmer.clone();
+        }
+        if self.stem_exceptions {
+            merged.stem_exceptions = given.stem_exceptions.clone();
+        }
+        if self.bigrams {
+            merged.bigrams = given.bigrams;
+        }
+        if self.ngrams {
+            merged.ngrams = given.ngrams.clone();
+        }
+        merged
+    }
+}
+
+fn add_stopword_file(analysis: &mut AnalyzerConfig, path: &str) {
+    let words = load_word_list(path).unwrap_or_else(|err| {
+        eprintln!("Could not read stop-word file {}: {}", path, err);
+        std::process::exit(1);
+    });
+    analysis.stopwords.extend(words);
+}
+
+fn add_stem_exception_file(analysis: &mut AnalyzerConfig, path: &str) {
+    let words = load_word_list(path).unwrap_or_else(|err| {
+        eprintln!("Could not read stem-exception file {}: {}", path, err);
+        std::process::exit(1);
+    });
+    analysis.stem_exceptions.extend(words);
+}
+
+fn add_stop_language(analysis: &mut AnalyzerConfig, language: &str) {
+    let words = stopwords::builtin(language).unwrap_or_else(|| {
+        eprintln!("No built-in stop words for language: {}", language);
+        std::process::exit(1);
+    });
+    analysis
+        .stopwords
+        .extend(words.iter().map(|word| word.to_string()));
+}
+
+fn checked_stemmer(language: &str) -> String {
+    if Stemmer::for_language(language).is_none() {
+        eprintln!("Unsupported stemmer language: {}", language);
+        std::process::exit(1);
     }
+    language.to_string()
+}
 
-    fn new(corpus: &Vec<Vec<String>>) -> BM25 {
-        let mut total_doc_length = 0;
-        let doc_count = corpus.len() as i32;
-        let doc_lengths: Vec<i32> = corpus
+fn checked_tokenizer(name: &str) -> TokenizerKind {
+    TokenizerKind::parse(name).unwrap_or_else(|| {
+        eprintln!("Unknown tokenizer: {}", name);
+        std::process::exit(1);
+    })
+}
+
+fn checked_ngrams(value: &str) -> Ngrams {
+    Ngrams::parse(value).unwrap_or_else(|| {
+        eprintln!("Invalid n-gram sizes: {}", value);
+        std::process::exit(1);
+    })
+}
+
+fn checked_scorer(name: &str) -> String {
+    if scorer::by_name(name).is_none() {
+        eprintln!("Unknown scorer: {}", name);
+        std::process::exit(1);
+    }
+    name.to_string()
+}
+
+/// k1 scales term frequency, so it cannot be negative (or NaN).
+fn valid_k1(k1: f32) -> bool {
+    k1 >= 0.0
+}
+
+/// b is the share of length normalization, from none to full.
+fn valid_b(b: f32) -> bool {
+    (0.0..=1.0).contains(&b)
+}
+
+fn checked_parameter(name: &str, value: f32, valid: fn(f32) -> bool) -> f32 {
+    if !valid(value) {
+        eprintln!("Invalid {} in config: {}", name, value);
+        std::process::exit(1);
+    }
+    value
+}
+
+fn checked_idf_mode(name: &str) -> IdfMode {
+    IdfMode::parse(name).unwrap_or_else(|| {
+        eprintln!("Unknown IDF mode: {}", name);
+        std::process::exit(1);
+    })
+}
+
+/// Parses the command line. With more than one `--collection`, the options
+/// are parsed once per collection, against its settings, into `collections`.
+fn parse_args(args: &[String]) -> Options {
+    let config_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .map(|i| args.get(i + 1).unwrap_or_else(|| usage(&args[0])).as_str());
+    // `tune` creates the config file it writes to if there is none yet.
+    let creating = args.get(1).is_some_and(|command| command == "tune")
+        && config_path.is_some_and(|path| !Path::new(path).exists());
+    let config = if creating {
+        Ok(Config::default())
+    } else {
+        config::find(config_path)
+    };
+    let config = config.unwrap_or_else(|err| {
+        eprintln!(
+            "Could not read config {}: {}",
+            config_path.unwrap_or(config::DEFAULT_PATH),
+            err
+        );
+        std::process::exit(1);
+    });
+    let names: Vec<&String> = args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| *arg == "--collection")
+        .map(|(i, _)| args.get(i + 1).unwrap_or_else(|| usage(&args[0])))
+        .collect();
+    let collection = |name: &str| {
+        config.collection(name).unwrap_or_else(|| {
+            eprintln!("Unknown collection: {}", name);
+            std::process::exit(1);
+        })
+    };
+    let Some((first, others)) = names.split_first() else {
+        return parse_with_config(args, &config, None);
+    };
+    let (path, first_config) = collection(first);
+    let mut options = parse_with_config(args, &first_config, path);
+    if !others.is_empty() {
+        options.collections = names
             .iter()
-            .map(|e| {
-                let a = e.len() as i32;
-                total_doc_length += a;
-                a
+            .map(|name| {
+                let (path, config) = collection(name);
+                (name.to_string(), parse_with_config(args, &config, path))
             })
             .collect();
-        let avg_doc_length = total_doc_length / doc_count;
-        let mut a = BM25 {
-            corpus,
-            k1: 1.5,
-            b: 0.75,
-            doc_lengths,
-            avg_doc_length,
-            doc_count,
-            tf_cache: corpus
+    }
+    options
+}
+
+/// Parses the command line on top of `config`. `collection_path` is the path
+/// of the collection being searched, which then need not be given.
+fn parse_with_config(args: &[String], config: &Config, collection_path: Option<String>) -> Options {
+    let mut rest = args.iter().skip(1).peekable();
+    let command = match rest.peek().map(|arg| arg.as_str()) {
+        Some("index") => Command::Index,
+        Some("search") => Command::Search,
+        Some("add") => Command::Add,
+        Some("remove") => Command::Remove,
+        Some("compact") => Command::Compact,
+        Some("upgrade") => Command::Upgrade,
+        Some("backup") => Command::Backup,
+        Some("restore") => Command::Restore,
+        Some("serve") => Command::Serve,
+        Some("stats") => Command::Stats,
+        Some("terms") => Command::Terms,
+        Some("eval") => Command::Eval,
+        Some("tune") => Command::Tune,
+        Some("batch") => Command::Batch,
+        _ => Command::Interactive,
+    };
+    if !matches!(command, Command::Interactive) {
+        rest.next();
+    }
+    let mut paths = vec![];
+    let mut include = vec![];
+    let mut exclude = vec![];
+    let mut options = Options {
+        command,
+        path: String::new(),
+        files: vec![],
+        output: "index.bm25".to_string(),
+        addr: "127.0.0.1:8080".to_string(),
+        grpc: false,
+        timeout: None,
+        include: GlobSet::empty(),
+        exclude: GlobSet::empty(),
+        title_bonus: 0.0,
+        analysis: AnalyzerConfig::default(),
+        given_analysis: GivenAnalysis::default(),
+        length_adjustment: LengthAdjustment::None,
+        display: Display {
+            format: OutputFormat::Text,
+            snippets: false,
+            explain: false,
+        },
+        auto_correct: false,
+        watch: false,
+        limit: config.limit.unwrap_or(5),
+        offset: 0,
+        min_score: None,
+        boosts: HashMap::new(),
+        field_b: HashMap::new(),
+        doc_boost: None,
+        scorer: config.scorer.as_deref().map(checked_scorer),
+        k1: config.k1.map(|k1| checked_parameter("k1", k1, valid_k1)),
+        b: config.b.map(|b| checked_parameter("b", b, valid_b)),
+        idf_mode: config.idf.as_deref().map(checked_idf_mode),
+        input: None,
+        columns: Columns::default(),
+        filters: vec![],
+        facets: vec![],
+        chunking: None,
+        aggregate: None,
+        synonyms: Synonyms::default(),
+        synonym_weight: 0.5,
+        threads: None,
+        log_level: None,
+        cache_size: config
+            .cache_size
+            .unwrap_or(fulltext::cache::DEFAULT_CAPACITY),
+        shards: config.shards.unwrap_or(1),
+        mmap: false,
+        quiet: false,
+        proximity_weight: 0.0,
+        feedback: None,
+        rerank: None,
+        rerank_depth: rerank::DEFAULT_DEPTH,
+        fusion: Fusion::default(),
+        encoding: encoding_rs::WINDOWS_1252,
+        duplicates: Duplicates::Skip,
+        term_order: TermOrder::DocFreq,
+        queries: None,
+        qrels: None,
+        k1_range: ParameterRange {
+            min: 0.5,
+            max: 2.0,
+            step: 0.1,
+        },
+        b_range: ParameterRange {
+            min: 0.0,
+            max: 1.0,
+            step: 0.1,
+        },
+        measure: Measure::Map,
+        config_path: config::DEFAULT_PATH.to_string(),
+        collections: vec![],
+    };
+    let analyzer = &config.analyzer;
+    if let Some(tokenizer) = &analyzer.tokenizer {
+        options.analysis.tokenizer = checked_tokenizer(tokenizer);
+    }
+    options.analysis.strip_punctuation = analyzer.strip_punctuation;
+    options.analysis.bigrams = analyzer.bigrams;
+    options.analysis.ngrams = analyzer
+        .ngrams
+        .iter()
+        .map(|value| checked_ngrams(value))
+        .collect();
+    options.analysis.stemmer = analyzer.stemmer.as_deref().map(checked_stemmer);
+    if let Some(language) = &analyzer.stop_language {
+        add_stop_language(&mut options.analysis, language);
+    }
+    for path in &analyzer.stopwords {
+        add_stopword_file(&mut options.analysis, path);
+    }
+    for path in &analyzer.stem_exceptions {
+        add_stem_exception_file(&mut options.analysis, path);
+    }
+    options.given_analysis = GivenAnalysis {
+        tokenizer: analyzer.tokenizer.is_some(),
+        strip_punctuation: analyzer.strip_punctuation,
+        stopwords: analyzer.stop_language.is_some() || !analyzer.stopwords.is_empty(),
+        stemmer: analyzer.stemmer.is_some(),
+        stem_exceptions: !analyzer.stem_exceptions.is_empty(),
+        bigrams: analyzer.bigrams,
+        ngrams: !analyzer.ngrams.is_empty(),
+    };
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--title-bonus" => {
+                options.title_bonus = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--stopwords" => {
+                options.given_analysis.stopwords = true;
+                add_stopword_file(
+                    &mut options.analysis,
+                    rest.next().unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--stop-language" => {
+                options.given_analysis.stopwords = true;
+                add_stop_language(
+                    &mut options.analysis,
+                    rest.next().unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--length-adjustment" => {
+                options.length_adjustment = rest
+                    .next()
+                    .and_then(|value| LengthAdjustment::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--stemmer" => {
+                let language = rest.next().unwrap_or_else(|| usage(&args[0]));
+                options.analysis.stemmer = Some(checked_stemmer(language));
+                options.given_analysis.stemmer = true;
+            }
+            "--stem-exceptions" => {
+                options.given_analysis.stem_exceptions = true;
+                add_stem_exception_file(
+                    &mut options.analysis,
+                    rest.next().unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--k1" => {
+                options.k1 = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&k1| valid_k1(k1))
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--b" => {
+                options.b = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&b| valid_b(b))
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            // Both were read before the config was loaded.
+            "--config" => options.config_path = rest.next().unwrap().clone(),
+            "--collection" => {
+                rest.next();
+            }
+            "--tokenizer" => {
+                options.analysis.tokenizer =
+                    checked_tokenizer(rest.next().unwrap_or_else(|| usage(&args[0])));
+                options.given_analysis.tokenizer = true;
+            }
+            "--strip-punctuation" => {
+                options.analysis.strip_punctuation = true;
+                options.given_analysis.strip_punctuation = true;
+            }
+            "--bigrams" => {
+                options.analysis.bigrams = true;
+                options.given_analysis.bigrams = true;
+            }
+            "--ngram" => {
+                let value = rest.next().unwrap_or_else(|| usage(&args[0]));
+                options.analysis.ngrams.push(checked_ngrams(value));
+                options.given_analysis.ngrams = true;
+            }
+            "--format" => {
+                options.display.format = rest
+                    .next()
+                    .and_then(|value| OutputFormat::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--snippets" => options.display.snippets = true,
+            "--auto-correct" => options.auto_correct = true,
+            "--explain" => options.display.explain = true,
+            "--watch" => options.watch = true,
+            "--mmap" => options.mmap = true,
+            "--quiet" => options.quiet = true,
+            "--encoding" => {
+                options.encoding = rest
+                    .next()
+                    .and_then(|label| Encoding::for_label(label.as_bytes()))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--duplicates" => {
+                options.duplicates = rest
+                    .next()
+                    .and_then(|value| Duplicates::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--by" => {
+                options.term_order = rest
+                    .next()
+                    .and_then(|value| TermOrder::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--queries" => {
+                options.queries = Some(rest.next().unwrap_or_else(|| usage(&args[0])).clone());
+            }
+            "--qrels" => {
+                options.qrels = Some(rest.next().unwrap_or_else(|| usage(&args[0])).clone());
+            }
+            "--k1-range" => {
+                options.k1_range = rest
+                    .next()
+                    .and_then(|value| ParameterRange::parse(value, 0.1))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--b-range" => {
+                options.b_range = rest
+                    .next()
+                    .and_then(|value| ParameterRange::parse(value, 0.1))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--measure" => {
+                options.measure = rest
+                    .next()
+                    .and_then(|value| Measure::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--scorer" => {
+                options.scorer = Some(checked_scorer(
+                    rest.next().unwrap_or_else(|| usage(&args[0])),
+                ));
+            }
+            "--idf" => {
+                options.idf_mode = Some(checked_idf_mode(
+                    rest.next().unwrap_or_else(|| usage(&args[0])),
+                ));
+            }
+            "--boost" => {
+                let (field, boost) = rest
+                    .next()
+                    .and_then(|value| parse_boost(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+                options.boosts.insert(field, boost);
+            }
+            "--field-b" => {
+                let (field, b) = rest
+                    .next()
+                    .and_then(|value| parse_boost(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+                options.field_b.insert(field, b);
+            }
+            "--doc-boost" => {
+                options.doc_boost = Some(rest.next().unwrap_or_else(|| usage(&args[0])).clone());
+            }
+            "--limit" => {
+                options.limit = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--offset" => {
+                options.offset = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--min-score" => {
+                options.min_score = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--input" => {
+                options.input = Some(rest.next().unwrap_or_else(|| usage(&args[0])).clone());
+            }
+            "--column" => {
+                rest.next()
+                    .and_then(|mapping| options.columns.set(mapping))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--facet" => {
+                let key = rest.next().unwrap_or_else(|| usage(&args[0]));
+                options.facets.push(key.to_string());
+            }
+            "--filter" => {
+                let filter = rest.next().unwrap_or_else(|| usage(&args[0]));
+                options
+                    .filters
+                    .push(Filter::parse(filter).unwrap_or_else(|| {
+                        eprintln!("Invalid filter: {}", filter);
+                        std::process::exit(1);
+                    }));
+            }
+            "--chunk" => {
+                options.chunking = Some(
+                    rest.next()
+                        .and_then(|value| Chunking::parse(value))
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--aggregate" => {
+                options.aggregate = Some(
+                    rest.next()
+                        .and_then(|value| Aggregate::parse(value))
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--synonyms" => {
+                let path = rest.next().unwrap_or_else(|| usage(&args[0]));
+                options.synonyms = Synonyms::load(path).unwrap_or_else(|err| {
+                    eprintln!("Could not read synonym file {}: {}", path, err);
+                    std::process::exit(1);
+                });
+            }
+            "--proximity-weight" => {
+                options.proximity_weight = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--feedback" => {
+                options.feedback = Some(
+                    rest.next()
+                        .and_then(|value| Feedback::parse(value))
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--rerank" => {
+                options.rerank = Some(rest.next().unwrap_or_else(|| usage(&args[0])).clone());
+            }
+            "--rerank-depth" => {
+                options.rerank_depth = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--fusion" => {
+                options.fusion = rest
+                    .next()
+                    .and_then(|value| Fusion::parse(value))
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--synonym-weight" => {
+                options.synonym_weight = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--cache-size" => {
+                options.cache_size = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--shards" => {
+                options.shards = rest
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&shards| shards > 0)
+                    .unwrap_or_else(|| usage(&args[0]));
+            }
+            "--threads" => {
+                options.threads = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&threads| threads > 0)
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--log-level" => {
+                options.log_level = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--include" => include.push(rest.next().unwrap_or_else(|| usage(&args[0])).clone()),
+            "--exclude" => exclude.push(rest.next().unwrap_or_else(|| usage(&args[0])).clone()),
+            "--addr" => options.addr = rest.next().unwrap_or_else(|| usage(&args[0])).clone(),
+            "--grpc" => options.grpc = true,
+            "--timeout" => {
+                options.timeout = Some(
+                    rest.next()
+                        .and_then(|value| value.parse().ok())
+                        .map(Duration::from_millis)
+                        .unwrap_or_else(|| usage(&args[0])),
+                );
+            }
+            "--output" => {
+                options.output = rest.next().unwrap_or_else(|| usage(&args[0])).clone();
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if let Some(input) = &options.input {
+        paths.insert(0, input.clone());
+    } else if let Some(path) = collection_path {
+        paths.insert(0, path);
+    }
+    if paths.is_empty() {
+        usage(&args[0]);
+    }
+    let glob_set = |patterns: &[String]| {
+        build_glob_set(patterns).unwrap_or_else(|err| {
+            eprintln!("Invalid glob pattern: {}", err);
+            std::process::exit(1);
+        })
+    };
+    options.include = glob_set(&include);
+    options.exclude = glob_set(&exclude);
+    options.path = paths.remove(0);
+    options.files = paths;
+    if matches!(options.command, Command::Add | Command::Remove) && options.files.is_empty() {
+        usage(&args[0]);
+    }
+    if matches!(options.command, Command::Backup | Command::Restore) && options.files.len() != 1 {
+        usage(&args[0]);
+    }
+    if let Command::Eval | Command::Tune = options.command {
+        if options.queries.is_none() || options.qrels.is_none() {
+            usage(&args[0]);
+        }
+    }
+    if let (Command::Batch, None) = (&options.command, &options.queries) {
+        usage(&args[0]);
+    }
+    options
+}
+
+/// The metadata, fields and body of a document to be indexed.
+type Document = (Metadata, Vec<(String, String)>, String);
+
+/// Fills in the title and user-defined values of a document from its fields.
+fn with_fields(mut metadata: Metadata, fields: &[(String, String)]) -> Metadata {
+    for (name, value) in fields {
+        if name == "title" {
+            metadata.title = Some(value.clone());
+        } else {
+            metadata.values.insert(name.clone(), value.clone());
+        }
+    }
+    metadata
+}
+
+fn file_document(extractors: &Extractors, file_path: &str) -> Result<Document, Bm25Error> {
+    let text = extractors.read(file_path)?;
+    let (fields, body) = document_fields(file_path, &text);
+    let metadata = Metadata::for_file(file_path).map_err(|source| Bm25Error::Io {
+        path: file_path.to_string(),
+        source,
+    })?;
+    Ok((with_fields(metadata, &fields), fields, body.to_string()))
+}
+
+fn record_document(record: input::Record) -> Document {
+    let mut metadata = with_fields(Metadata::default(), &record.fields);
+    metadata.values.insert("id".to_string(), record.id);
+    (metadata, record.fields, record.body)
+}
+
+/// Splits a document into one document per passage, each with the fields and
+/// metadata of the whole.
+fn split_document(document: Document, chunking: Option<Chunking>) -> Vec<Document> {
+    let Some(chunking) = chunking else {
+        return vec![document];
+    };
+    let (metadata, fields, body) = document;
+    chunking
+        .split(&body)
+        .into_iter()
+        .map(|passage| {
+            let metadata = Metadata {
+                passage: Some(passage),
+                ..metadata.clone()
+            };
+            (
+                metadata,
+                fields.clone(),
+                body[passage.start..passage.end].to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The documents of `files`, read in parallel a batch at a time as they are
+/// consumed.
+fn read_files(
+    extractors: &Extractors,
+    files: Vec<String>,
+) -> impl Iterator<Item = Result<Document, Bm25Error>> + '_ {
+    let batches: Vec<Vec<String>> = files
+        .chunks(fulltext::STREAM_BATCH)
+        .map(|batch| batch.to_vec())
+        .collect();
+    batches.into_iter().flat_map(move |batch| {
+        batch
+            .par_iter()
+            .map(|file_path| file_document(extractors, file_path))
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Indexes the files under `options.path`, skipping with a warning any that
+/// cannot be read and counting binary ones, or the records of the `--input`
+/// corpus. Files are read and indexed as a stream, so the corpus is never held
+/// in memory at once.
+fn build_index(options: &Options) -> Result<StoredIndex, Bm25Error> {
+    let start_time = Instant::now();
+    let extractors = options.extractors();
+    let (documents, progress): (Box<dyn Iterator<Item = _>>, _) = match &options.input {
+        Some(input) => {
+            let records = input::read_records(input, &options.columns).unwrap_or_else(|err| {
+                eprintln!("Could not read {}: {}", input, err);
+                std::process::exit(1);
+            });
+            let bytes = records.iter().map(|record| record.body.len() as u64).sum();
+            let progress = IndexProgress::new("records", records.len(), bytes);
+            let documents = records
+                .into_iter()
+                .map(|record| Ok(record_document(record)));
+            (Box::new(documents), progress)
+        }
+        None => {
+            let files =
+                list_files_with_full_paths(&options.path, &options.include, &options.exclude);
+            let bytes = files
                 .iter()
-                .map(|doc| {
-                    let mut doc_counter = Counter::new();
-                    doc.iter().for_each(|word| {
-                        doc_counter.increment(word.clone());
-                    });
-                    doc_counter.get_counts().to_owned()
-                })
-                .collect(),
-            term_idf: HashMap::new(),
+                .filter_map(|file| fs::metadata(file).ok())
+                .map(|file| file.len())
+                .sum();
+            let progress = IndexProgress::new("files", files.len(), bytes);
+            (Box::new(read_files(&extractors, files)), progress)
+        }
+    };
+    let progress = (!options.quiet).then_some(progress);
+    let documents = documents.filter_map(|document| match document {
+        Ok(document) => Some(document),
+        Err(Bm25Error::Binary { .. }) => {
+            if let Some(progress) = &progress {
+                progress.binary();
+            }
+            None
+        }
+        Err(err) => {
+            eprintln!("Warning: {}; skipping", err);
+            None
+        }
+    });
+    // The first paths with each hash of their content, and the paths that
+    // repeat one.
+    let mut first_paths: HashMap<u64, Vec<String>> = HashMap::new();
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    let documents = documents
+        .filter(|(metadata, _, body)| {
+            let Some(path) = &metadata.path else {
+                return true;
+            };
+            if options.duplicates == Duplicates::Keep {
+                return true;
+            }
+            let mut hasher = DefaultHasher::new();
+            body.hash(&mut hasher);
+            let firsts = first_paths.entry(hasher.finish()).or_default();
+            // Equal hashes are very likely, but not certain, to be equal
+            // content, so the earlier file is read again to compare them.
+            let first = firsts.iter().find(|first| {
+                file_document(&extractors, first).is_ok_and(|(_, _, text)| text == *body)
+            });
+            let Some(first) = first.cloned() else {
+                firsts.push(path.clone());
+                return true;
+            };
+            if let Some(progress) = &progress {
+                progress.duplicate(metadata, body);
+            }
+            if options.duplicates == Duplicates::Alias {
+                aliases.entry(first).or_default().push(path.clone());
+            }
+            false
+        })
+        .flat_map(|document| split_document(document, options.chunking));
+    let mut ins = BM25Index::with_analyzer(Box::new(options.analysis.build()));
+    ins.set_parameters(options.k1.unwrap_or(ins.k1()), options.b.unwrap_or(ins.b()));
+    ins.set_idf_mode(options.idf_mode.unwrap_or(ins.idf_mode()));
+    let mut titles = vec![];
+    let mut texts = HashMap::new();
+    let docs = ins.add_stream_with_progress(
+        documents,
+        |(_, _, body)| body,
+        |ins, doc, (metadata, fields, body)| {
+            if let Some(progress) = &progress {
+                progress.document(&metadata, &body);
+            }
+            let mut title = vec![];
+            for (name, value) in fields {
+                let tokens = ins.analyze(&value);
+                ins.set_field(doc, &name, &tokens);
+                if name == "title" {
+                    title = tokens;
+                }
+            }
+            titles.push(title);
+            if metadata.path.is_none() {
+                texts.insert(ins.doc_id(doc), body);
+            }
+            ins.set_metadata(doc, metadata);
+        },
+        |batch| {
+            if let Some(progress) = &progress {
+                progress.update(batch);
+            }
+        },
+    );
+    if let Some(progress) = &progress {
+        progress.finish(docs.len(), start_time.elapsed());
+    }
+    if docs.is_empty() {
+        return Err(Bm25Error::EmptyCorpus);
+    }
+    ins.set_titles(titles, options.title_bonus);
+    if !aliases.is_empty() {
+        for doc in docs {
+            let metadata = ins.metadata(doc);
+            if let Some(duplicates) = metadata.path.as_ref().and_then(|path| aliases.get(path)) {
+                let metadata = Metadata {
+                    duplicates: duplicates.clone(),
+                    ..metadata.clone()
+                };
+                ins.set_metadata(doc, metadata);
+            }
+        }
+    }
+    Ok(StoredIndex {
+        texts,
+        chunking: options.chunking,
+        analysis: options.analysis.clone(),
+        index: ins,
+        scorer: options.scorer.clone().unwrap_or("bm25".to_string()),
+        extractors,
+    })
+}
+
+impl StoredIndex {
+    /// Indexes a file, or each of its passages, and returns how many documents
+    /// were added.
+    fn add_file(&mut self, file_path: &str) -> Result<usize, Bm25Error> {
+        let document = file_document(&self.extractors, file_path)?;
+        let documents = split_document(document, self.chunking);
+        let added = documents.len();
+        for (metadata, fields, body) in documents {
+            self.add_document(metadata, fields, &body);
+        }
+        Ok(added)
+    }
+
+    /// Adds a document that is not backed by a file, keeping its text.
+    fn add_text(&mut self, path: &str, text: &str) -> usize {
+        let (fields, body) = document_fields(path, text);
+        let metadata = Metadata {
+            path: Some(path.to_string()),
+            ..with_fields(Metadata::default(), &fields)
         };
-        a.calculate_idf();
-        a
+        let doc = self.add_document(metadata, fields, body);
+        self.texts.insert(self.index.doc_id(doc), text.to_string());
+        doc
+    }
+
+    fn add_document(
+        &mut self,
+        metadata: Metadata,
+        fields: Vec<(String, String)>,
+        body: &str,
+    ) -> usize {
+        let doc = self.index.add_text(body);
+        for (name, value) in fields {
+            let tokens = self.index.analyze(&value);
+            self.index.set_field(doc, &name, &tokens);
+            if name == "title" {
+                self.index.set_title(doc, tokens);
+            }
+        }
+        self.index.set_metadata(doc, metadata);
+        doc
+    }
+
+    /// What a document is called in results: its path, or the id of the
+    /// record it came from.
+    fn name(&self, doc: usize) -> &str {
+        let metadata = self.index.metadata(doc);
+        metadata
+            .path
+            .as_deref()
+            .or(metadata.values.get("id").map(|id| id.as_str()))
+            .unwrap_or("")
+    }
+
+    /// The text of a document, for snippets: kept in the index for records and
+    /// documents added over HTTP, read from disk for files.
+    fn document_text(&self, doc: usize) -> String {
+        if let Some(text) = self.texts.get(&self.index.doc_id(doc)) {
+            return text.clone();
+        }
+        let path = self.name(doc);
+        let text = self.extractors.read(path).unwrap_or_default();
+        let (_, body) = document_fields(path, &text);
+        match self.index.metadata(doc).passage {
+            Some(passage) => body
+                .get(passage.start..passage.end)
+                .unwrap_or("")
+                .to_string(),
+            None => body.to_string(),
+        }
+    }
+    fn explain(&self, input: &str, doc: i32) -> Option<Explanation> {
+        let query = self.index.with_feedback(self.index.text_query(input)?);
+        Some(self.index.explain_query(&query, doc as usize))
+    }
+
+    /// Analyzed terms that a result for `input` is highlighted with.
+    fn query_terms(&self, input: &str) -> HashSet<String> {
+        let Some(query) = self.index.text_query(input) else {
+            return HashSet::new();
+        };
+        let mut terms: HashSet<String> = query.scoring_terms().into_iter().collect();
+        terms.extend(query.scoring_phrases().into_iter().flatten());
+        terms
+    }
+
+    /// Removes the documents with the given path or record id.
+    fn remove_file(&mut self, name: &str) -> usize {
+        let docs: Vec<usize> = (0..self.index.slot_count())
+            .filter(|&doc| self.index.is_live(doc) && self.name(doc) == name)
+            .collect();
+        for &doc in &docs {
+            self.texts.remove(&self.index.doc_id(doc));
+        }
+        docs.into_iter()
+            .filter(|&doc| self.index.remove_document(doc))
+            .count()
     }
 }
 
-fn list_files_with_full_paths(directory_path: &str) -> std::io::Result<Vec<String>> {
-    let entries = fs::read_dir(directory_path)?;
+/// Whether the index at `path` is kept in a SQLite database: an existing
+/// database, or a new file named `*.sqlite`, `*.sqlite3` or `*.db`.
+#[cfg(feature = "sqlite")]
+fn is_sqlite_path(path: &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str());
+    SqliteStorage::is_sqlite(path) || matches!(extension, Some("sqlite" | "sqlite3" | "db"))
+}
+
+/// What is stored next to the index in a database.
+#[cfg(feature = "sqlite")]
+type StoredExtra = (HashMap<DocId, String>, Option<Chunking>, AnalyzerConfig);
+
+/// [`StoredExtra`] as versions before 2 wrote it.
+#[cfg(feature = "sqlite")]
+type StoredExtraV1 = (HashMap<DocId, String>, Option<Chunking>, AnalyzerConfigV1);
+
+/// The fields of a [`StoredIndex`] as versions before 2 wrote it.
+type StoredIndexV1 = (
+    HashMap<DocId, String>,
+    Option<Chunking>,
+    AnalyzerConfigV1,
+    BM25Index,
+);
 
-    let file_paths: Vec<String> = entries
-        .filter_map(|entry| {
-            entry
-                .ok()
-                .and_then(|e| e.path().to_str().map(|s| s.to_string()))
+/// Writes the index to `path` in the current format: a header, then the
+/// index itself.
+fn save_index(stored: &StoredIndex, path: &str) -> Result<(), Box<dyn Error>> {
+    let header = IndexHeader::new(
+        stored.analysis.clone(),
+        &stored.scorer,
+        stored.index.k1(),
+        stored.index.b(),
+    );
+    #[cfg(feature = "sqlite")]
+    if is_sqlite_path(path) {
+        let extra = bincode::serialize(&(&stored.texts, &stored.chunking, &stored.analysis))?;
+        let mut storage = SqliteStorage::open(path)?;
+        storage.set_header(header);
+        storage.save(&stored.index, &extra)?;
+        return Ok(());
+    }
+    snapshot::write_atomic(path, |out| {
+        header.write(out, path)?;
+        bincode::serialize_into(out, stored).map_err(|err| Bm25Error::InvalidIndex {
+            path: path.to_string(),
+            message: err.to_string(),
         })
-        .collect();
+    })?;
+    Ok(())
+}
 
-    Ok(file_paths)
+/// Loads the index at `path`, which must be in the current format.
+fn load_index(path: &str) -> Result<StoredIndex, Box<dyn Error>> {
+    read_index(path, false).map(|(stored, _)| stored)
 }
 
-fn read_file_words(file_path: &str) -> std::io::Result<Vec<String>> {
-    let file = std::fs::read_to_string(file_path)?;
-    let words: Vec<String> = file
-        .split_whitespace()
-        .map(|s| s.to_lowercase().to_string())
-        .collect();
-    Ok(words)
+/// Loads the index at `path` and returns its format version. A file without
+/// a header may be in version 0, which holds the same data as version 1 and
+/// was built with the default scorer, but may as well be any other file, so
+/// it is only read if `headerless` allows it, as when upgrading.
+fn read_index(path: &str, headerless: bool) -> Result<(StoredIndex, u32), Box<dyn Error>> {
+    #[cfg(feature = "sqlite")]
+    if SqliteStorage::is_sqlite(path) {
+        let storage = SqliteStorage::open(path)?;
+        let header = storage.header()?;
+        let Some((index, extra)) = storage.load()? else {
+            return Err("the database holds no index".into());
+        };
+        let (texts, chunking, analysis) = if header.as_ref().is_some_and(|h| h.version >= 2) {
+            bincode::deserialize::<StoredExtra>(&extra)?
+        } else {
+            let (texts, chunking, analysis): StoredExtraV1 = bincode::deserialize(&extra)?;
+            (texts, chunking, analysis.into())
+        };
+        let stored = StoredIndex {
+            texts,
+            chunking,
+            analysis,
+            index,
+            scorer: String::new(),
+            extractors: Extractors::default(),
+        };
+        return loaded(stored, header);
+    }
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let header = IndexHeader::read(&mut reader, path)?;
+    if header.is_none() && !headerless {
+        return Err(Bm25Error::InvalidIndex {
+            path: path.to_string(),
+            message: format!(
+                "it has no index header; if an older release wrote it, run `upgrade {}` to \
+                 migrate it to format version {}",
+                path, FORMAT_VERSION
+            ),
+        }
+        .into());
+    }
+    let invalid = |err: bincode::Error| Bm25Error::InvalidIndex {
+        path: path.to_string(),
+        message: err.to_string(),
+    };
+    let stored = if header.as_ref().is_some_and(|h| h.version >= 2) {
+        format::deserialize_bounded(reader, length).map_err(invalid)?
+    } else {
+        let (texts, chunking, analysis, index): StoredIndexV1 =
+            format::deserialize_bounded(reader, length).map_err(invalid)?;
+        StoredIndex {
+            texts,
+            chunking,
+            analysis: analysis.into(),
+            index,
+            scorer: String::new(),
+            extractors: Extractors::default(),
+        }
+    };
+    loaded(stored, header)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <text_file_directory>", args[0]);
+/// Finishes loading an index with the header it had, if any.
+fn loaded(
+    mut stored: StoredIndex,
+    header: Option<IndexHeader>,
+) -> Result<(StoredIndex, u32), Box<dyn Error>> {
+    let (scorer, version) = header.map_or(("bm25".to_string(), 0), |header| {
+        (header.scorer, header.version)
+    });
+    if scorer::by_name(&scorer).is_none() {
+        return Err(format!("it was built with an unknown scorer {}", scorer).into());
+    }
+    stored.scorer = scorer;
+    stored.index.set_analyzer(Box::new(stored.analysis.build()));
+    Ok((stored, version))
+}
+
+/// Exits if analysis options were given, on the command line or in the
+/// config file, that differ from those the index at the options' path was
+/// built with. Settings not given are the index's own.
+fn check_analysis(options: &Options, built: &AnalyzerConfig) {
+    let given = options.given_analysis.merged(built, &options.analysis);
+    if let Err(err) = format::check_analysis(&options.path, built, &given) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn serve_grpc(
+    stored: StoredIndex,
+    addr: &str,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    grpc::serve(stored, addr, timeout)
+}
+
+/// Reports gRPC as unsupported when the `grpc` feature is off.
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(
+    _: StoredIndex,
+    _: &str,
+    _: Option<Duration>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    Err("gRPC support was not compiled in; build with --features grpc".into())
+}
+
+/// A line typed into the query loop that the index has to answer.
+enum Input<'a> {
+    Query(&'a str),
+    /// The `:stats` command.
+    Stats,
+    /// The `:similar` command, with the rank of the result to go by.
+    Similar(usize),
+}
+
+/// Reads one query per line until EOF, `:quit` or until `answer` fails to
+/// write, applying the `:` commands that change the options in between. The
+/// prompt is only shown for text output so that the other formats can be
+/// piped straight into a script.
+fn read_queries(
+    options: &mut Options,
+    mut answer: impl FnMut(&Options, Input) -> io::Result<bool>,
+) {
+    let mut reader = LineReader::new(options.display.format == OutputFormat::Text);
+    while let Some(line) = reader.read() {
+        let input = match ReplCommand::parse(&line) {
+            None => Input::Query(&line),
+            Some(Err(message)) => {
+                eprintln!("{}", message);
+                continue;
+            }
+            Some(Ok(command)) => {
+                match command {
+                    ReplCommand::Limit(limit) => options.limit = limit,
+                    ReplCommand::Explain(explain) => options.display.explain = explain,
+                    ReplCommand::Snippets(snippets) => options.display.snippets = snippets,
+                    ReplCommand::Stats => {
+                        if answer(options, Input::Stats).is_err() {
+                            break;
+                        }
+                    }
+                    ReplCommand::Similar(rank) => {
+                        if answer(options, Input::Similar(rank)).is_err() {
+                            break;
+                        }
+                    }
+                    ReplCommand::Help => println!("{}", repl::HELP),
+                    ReplCommand::Quit => break,
+                }
+                continue;
+            }
+        };
+        if answer(options, input).is_err() {
+            break;
+        }
+    }
+}
+
+/// Answers the query given on the command line of `search`, exiting with
+/// whether anything matched, or else reads queries from stdin.
+fn answer_queries(
+    options: &mut Options,
+    mut answer: impl FnMut(&Options, Input) -> io::Result<bool>,
+) {
+    if let Command::Search = options.command {
+        if !options.files.is_empty() {
+            let query = options.files.join(" ");
+            let matched = answer(options, Input::Query(&query)).unwrap_or(false);
+            std::process::exit(if matched { 0 } else { 1 });
+        }
+    }
+    read_queries(options, answer);
+}
+
+fn run_queries(stored: &mut StoredIndex, options: &mut Options, watcher: Option<DirectoryWatcher>) {
+    // The ranks and ids of the results last shown, for `:similar`. Ids,
+    // unlike document indexes, survive re-indexing by the watcher.
+    let mut shown: Vec<(usize, DocId)> = vec![];
+    read_queries(options, |options, input| {
+        if let Some(watcher) = &watcher {
+            let updated = watcher.apply(stored);
+            if updated > 0 {
+                eprintln!("Re-indexed {} changed file(s)", updated);
+            }
+        }
+        let (ranks, offset) = match input {
+            Input::Query(query) => (print_results(stored, options, query)?, options.offset),
+            Input::Stats => return print_stats(options, &stored.index.stats(), None),
+            Input::Similar(rank) => {
+                let Some(&(_, id)) = shown.iter().find(|(shown, _)| *shown == rank) else {
+                    eprintln!("No result {} to find documents like", rank);
+                    return Ok(false);
+                };
+                (print_similar(stored, options, id)?, 0)
+            }
+        };
+        shown = ranks
+            .hits
+            .iter()
+            .enumerate()
+            .map(|(rank, (doc, _))| (offset + rank + 1, stored.index.doc_id(*doc as usize)))
+            .collect();
+        Ok(!shown.is_empty())
+    });
+}
+
+/// Runs `request`, re-ranking the best hits when the options name a program
+/// for it. If that fails, the BM25 ranking is shown.
+fn search_stored(stored: &StoredIndex, options: &Options, request: SearchRequest) -> RankedResults {
+    let Some(command) = &options.rerank else {
+        return search_aggregated(stored, options, request);
+    };
+    let best = search_aggregated(
+        stored,
+        options,
+        SearchRequest {
+            offset: 0,
+            limit: options.rerank_depth,
+            ..request.clone()
+        },
+    );
+    let reranker = CommandReranker {
+        command: command.clone(),
+    };
+    let text = |doc| stored.document_text(doc);
+    let depth = options.rerank_depth;
+    match stored.index.rerank(
+        &request.query,
+        &best.hits,
+        depth,
+        text,
+        &reranker,
+        options.fusion,
+    ) {
+        Ok(hits) => {
+            let hits = hits.into_iter().skip(request.offset).take(request.limit);
+            RankedResults {
+                facets: best.facets,
+                ..RankedResults::from_hits(hits.collect())
+            }
+        }
+        Err(err) => {
+            eprintln!("Warning: {}; showing the BM25 ranking", err);
+            search_aggregated(stored, options, request)
+        }
+    }
+}
+
+/// Runs `request`, combining the passages of each file into one result when
+/// the options aggregate them.
+fn search_aggregated(
+    stored: &StoredIndex,
+    options: &Options,
+    request: SearchRequest,
+) -> RankedResults {
+    match options.aggregate {
+        Some(aggregate) => {
+            let ranks = stored.index.search(&SearchRequest {
+                offset: 0,
+                limit: stored.index.slot_count(),
+                ..request.clone()
+            });
+            let hits = aggregate.apply(ranks.hits, |doc| stored.name(doc as usize).to_string());
+            let hits = hits.into_iter().skip(request.offset).take(request.limit);
+            RankedResults {
+                facets: ranks.facets,
+                ..RankedResults::from_hits(hits.collect())
+            }
+        }
+        None => stored.index.search(&request),
+    }
+}
+
+/// Prints the results for one query and returns them. When nothing matched,
+/// a spelling correction is suggested, or searched for instead with
+/// `--auto-correct`.
+fn print_results(
+    stored: &StoredIndex,
+    options: &Options,
+    input: &str,
+) -> io::Result<RankedResults> {
+    let search = |input: &str| search_stored(stored, options, options.request(input));
+    let mut ranks = search(input);
+    let mut correction = None;
+    if ranks.hits.iter().all(|(_, score)| *score <= 0.0) {
+        if let Some(corrected) = stored.index.did_you_mean(input) {
+            if options.auto_correct {
+                ranks = search(&corrected);
+            }
+            correction = Some(Correction {
+                query: corrected,
+                applied: options.auto_correct,
+            });
+        }
+    }
+    let mut out = io::stdout().lock();
+    output::write_results(
+        &mut out,
+        options.display,
+        stored,
+        input,
+        &ranks,
+        options.offset,
+        correction.as_ref(),
+    )?;
+    out.flush()?;
+    Ok(ranks)
+}
+
+/// Prints the documents most like the one with `id` as the results of its
+/// key terms, and returns them.
+fn print_similar(stored: &StoredIndex, options: &Options, id: DocId) -> io::Result<RankedResults> {
+    let Some(hits) = stored.index.more_like_this(id, options.limit) else {
+        eprintln!("That result is no longer in the index");
+        return Ok(RankedResults::from_hits(vec![]));
+    };
+    let doc = stored.index.doc_index(id).unwrap();
+    let terms = stored.index.key_terms(doc, fulltext::MORE_LIKE_THIS_TERMS);
+    let terms: Vec<&str> = terms.iter().map(|(term, _)| term.as_str()).collect();
+    let ranks = RankedResults::from_hits(hits);
+    let mut out = io::stdout().lock();
+    output::write_results(
+        &mut out,
+        options.display,
+        stored,
+        &terms.join(" "),
+        &ranks,
+        0,
+        None,
+    )?;
+    out.flush()?;
+    Ok(ranks)
+}
+
+fn open_disk_index(path: &str) -> DiskIndex {
+    DiskIndex::open(path).unwrap_or_else(|err| {
+        eprintln!("Could not open index {}: {}", path, err);
+        std::process::exit(1);
+    })
+}
+
+/// Answers queries from an on-disk index, which only supports plain ranking:
+/// no query syntax, snippets, explanations, filters, facets or passages to
+/// aggregate.
+fn search_disk_index(options: &mut Options) {
+    let mut index = open_disk_index(&options.path);
+    check_analysis(options, index.analysis());
+    let display = options.display;
+    if display.snippets
+        || display.explain
+        || !options.filters.is_empty()
+        || !options.facets.is_empty()
+    {
+        eprintln!("--snippets, --explain, --filter and --facet need an index loaded into memory");
         std::process::exit(1);
     }
+    index.set_parameters(
+        options.k1.unwrap_or(index.k1()),
+        options.b.unwrap_or(index.b()),
+    );
+    index.set_idf_mode(options.idf_mode.unwrap_or(index.idf_mode()));
+    let name = options.scorer.as_deref().unwrap_or(index.scorer());
+    index.set_scorer(scorer::by_name(name).unwrap());
+    answer_queries(options, |options, input| {
+        let input = match input {
+            Input::Query(input) => input,
+            Input::Stats => return print_stats(options, &index.stats(), index_size(&options.path)),
+            Input::Similar(_) => {
+                eprintln!(":similar needs an index loaded into memory");
+                return Ok(false);
+            }
+        };
+        if query::is_structured(input) {
+            eprintln!(
+                "Query syntax (operators, phrases, prefixes, fuzzy terms, boosts and fields) \
+                 needs an index loaded into memory: {}",
+                input
+            );
+            return Ok(false);
+        }
+        let hits = index.search(input, options.offset.saturating_add(options.limit));
+        let hits: Vec<(i32, f32)> = hits
+            .into_iter()
+            .filter(|(_, score)| fulltext::keeps_score(options.min_score, *score))
+            .skip(options.offset)
+            .collect();
+        let mut out = io::stdout().lock();
+        let format = options.display.format;
+        output::write_disk_results(&mut out, format, &index, input, &hits, options.offset)?;
+        out.flush()?;
+        Ok(!hits.is_empty())
+    });
+}
+
+/// Indexes the options' files or records into `options.shards` shards, as
+/// [`build_index`] does but with only their bodies and metadata.
+fn build_sharded_index(options: &Options) -> Result<ShardedIndex, Bm25Error> {
+    let extractors = options.extractors();
+    let documents: Box<dyn Iterator<Item = _>> = match &options.input {
+        Some(input) => {
+            let records = input::read_records(input, &options.columns).unwrap_or_else(|err| {
+                eprintln!("Could not read {}: {}", input, err);
+                std::process::exit(1);
+            });
+            Box::new(
+                records
+                    .into_iter()
+                    .map(|record| Ok(record_document(record))),
+            )
+        }
+        None => {
+            let files =
+                list_files_with_full_paths(&options.path, &options.include, &options.exclude);
+            Box::new(read_files(&extractors, files))
+        }
+    };
+    let documents = documents
+        .filter_map(|document| match document {
+            Ok(document) => Some(document),
+            Err(Bm25Error::Binary { .. }) => None,
+            Err(err) => {
+                eprintln!("Warning: {}; skipping", err);
+                None
+            }
+        })
+        .flat_map(|document| split_document(document, options.chunking));
+    let mut index = ShardedIndex::new(options.analysis.clone(), options.shards);
+    let mut batch = vec![];
+    for (metadata, _, body) in documents {
+        batch.push((body, metadata));
+        if batch.len() == fulltext::STREAM_BATCH {
+            index.add_texts(std::mem::take(&mut batch));
+        }
+    }
+    index.add_texts(batch);
+    if index.doc_count() == 0 {
+        return Err(Bm25Error::EmptyCorpus);
+    }
+    Ok(index)
+}
 
-    let text_file_dir = &args[1];
-    let mut all_files = list_files_with_full_paths(text_file_dir).unwrap();
-    all_files.append(&mut all_files.clone());
-    let my: Vec<Vec<String>> = all_files
+/// Answers queries from a directory or record file indexed into several
+/// shards, which like an on-disk index only supports plain ranking.
+fn search_sharded(options: &mut Options) {
+    let display = options.display;
+    if display.snippets
+        || display.explain
+        || !options.filters.is_empty()
+        || !options.facets.is_empty()
+        || options.idf_mode.is_some()
+    {
+        eprintln!("--snippets, --explain, --filter, --facet and --idf need a single shard");
+        std::process::exit(1);
+    }
+    let mut index = build_sharded_index(options).unwrap_or_else(|err| {
+        eprintln!("Could not index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+    let defaults = BM25Index::new();
+    index.set_parameters(
+        options.k1.unwrap_or(defaults.k1()),
+        options.b.unwrap_or(defaults.b()),
+    );
+    index.set_scorer(scorer::by_name(options.scorer.as_deref().unwrap_or("bm25")).unwrap());
+    answer_queries(options, |options, input| {
+        let input = match input {
+            Input::Query(input) => input,
+            Input::Stats | Input::Similar(_) => {
+                eprintln!(":stats and :similar need a single shard");
+                return Ok(false);
+            }
+        };
+        if query::is_structured(input) {
+            eprintln!(
+                "Query syntax (operators, phrases, prefixes, fuzzy terms, boosts and fields) \
+                 needs a single shard: {}",
+                input
+            );
+            return Ok(false);
+        }
+        let hits = index.search(input, options.offset.saturating_add(options.limit));
+        let hits: Vec<(DocId, f32)> = hits
+            .into_iter()
+            .filter(|(_, score)| fulltext::keeps_score(options.min_score, *score))
+            .skip(options.offset)
+            .collect();
+        let mut out = io::stdout().lock();
+        let format = options.display.format;
+        output::write_sharded_results(&mut out, format, &index, input, &hits, options.offset)?;
+        out.flush()?;
+        Ok(!hits.is_empty())
+    });
+}
+
+/// Searches several collections, each indexed and scored with its own
+/// options, and prints their merged results.
+fn search_collections(options: &mut Options) {
+    if options.display.snippets || options.display.explain || !options.boosts.is_empty() {
+        eprintln!("--snippets, --explain and --boost need a single collection");
+        std::process::exit(1);
+    }
+    let mut collections = Collections::new();
+    for (name, collection_options) in &options.collections {
+        let stored = open_index(collection_options);
+        collections.insert(name, stored.index);
+    }
+    answer_queries(options, |options, input| {
+        let input = match input {
+            Input::Query(input) => input,
+            Input::Stats => {
+                for name in collections.names() {
+                    println!("{}:", name);
+                    print_stats(options, &collections.get(name).unwrap().stats(), None)?;
+                }
+                return Ok(true);
+            }
+            Input::Similar(_) => {
+                eprintln!(":similar needs a single collection");
+                return Ok(false);
+            }
+        };
+        let hits = collections.search(&options.request(input));
+        let mut out = io::stdout().lock();
+        output::write_collection_results(
+            &mut out,
+            options.display.format,
+            &collections,
+            input,
+            &hits,
+            options.offset,
+        )?;
+        out.flush()?;
+        Ok(!hits.is_empty())
+    });
+}
+
+/// The size of the index file at `path`, or `None` for a directory that is
+/// indexed on the fly.
+fn index_size(path: &str) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+fn print_stats(options: &Options, stats: &IndexStats, size: Option<u64>) -> io::Result<bool> {
+    let mut out = io::stdout().lock();
+    output::write_stats(&mut out, options.display.format, stats, size)?;
+    out.flush()?;
+    Ok(true)
+}
+
+/// Prints the `stats` or `terms` of the index at the options' path, which may
+/// be an index file, an on-disk index or a directory to index first.
+fn describe_index(options: &Options) -> io::Result<()> {
+    let disk = options.input.is_none() && DiskIndex::is_disk_index(&options.path);
+    let mut out = io::stdout().lock();
+    if let Command::Stats = options.command {
+        let stats = if disk {
+            open_disk_index(&options.path).stats()
+        } else {
+            open_index(options).index.stats()
+        };
+        let size = options.input.is_none().then(|| index_size(&options.path));
+        output::write_stats(&mut out, options.display.format, &stats, size.flatten())?;
+    } else {
+        let (order, limit) = (options.term_order, options.limit);
+        let terms = if disk {
+            open_disk_index(&options.path).top_terms(order, limit)
+        } else {
+            open_index(options).index.top_terms(order, limit)
+        };
+        output::write_terms(&mut out, options.display.format, &terms)?;
+    }
+    out.flush()
+}
+
+/// The queries and relevance judgements named by `--queries` and `--qrels`.
+fn load_judgements(options: &Options) -> (Vec<(String, String)>, Qrels) {
+    let (queries, qrels) = (options.queries.as_ref(), options.qrels.as_ref());
+    let queries = eval::load_queries(queries.unwrap()).unwrap_or_else(|err| {
+        eprintln!("Could not read queries {}: {}", queries.unwrap(), err);
+        std::process::exit(1);
+    });
+    let qrels = Qrels::load(qrels.unwrap()).unwrap_or_else(|err| {
+        eprintln!("Could not read qrels {}: {}", qrels.unwrap(), err);
+        std::process::exit(1);
+    });
+    (queries, qrels)
+}
+
+/// Ranks every query and measures the rankings against `qrels`. Documents are
+/// identified as in the TSV output: by record id, or else by path.
+fn evaluate_queries(
+    stored: &StoredIndex,
+    options: &Options,
+    queries: &[(String, String)],
+    qrels: &Qrels,
+) -> Evaluation {
+    eval::evaluate(queries, qrels, |query| {
+        let request = SearchRequest {
+            offset: 0,
+            limit: eval::DEPTH,
+            ..options.request(query)
+        };
+        let ranks = search_stored(stored, options, request);
+        ranks
+            .hits
+            .iter()
+            .map(|(doc, _)| stored.name(*doc as usize).to_string())
+            .collect()
+    })
+}
+
+/// Prints how well the rankings of the options' queries agree with the qrels.
+fn evaluate_index(options: &Options) -> io::Result<()> {
+    let (queries, qrels) = load_judgements(options);
+    let stored = open_index(options);
+    let evaluation = evaluate_queries(&stored, options, &queries, &qrels);
+    for id in &evaluation.unjudged {
+        eprintln!("No relevance judgements for query {}", id);
+    }
+    let mut out = io::stdout().lock();
+    output::write_evaluation(&mut out, options.display.format, &evaluation)?;
+    out.flush()
+}
+
+/// Ranks every query of the options' queries file and prints the best
+/// `--limit` results of each.
+fn search_batch(options: &Options) -> io::Result<()> {
+    let path = options.queries.as_ref().unwrap();
+    let queries = eval::load_queries(path).unwrap_or_else(|err| {
+        eprintln!("Could not read queries {}: {}", path, err);
+        std::process::exit(1);
+    });
+    let stored = open_index(options);
+    let index = &stored.index;
+    let parsed: Vec<Option<Query>> = queries
+        .par_iter()
+        .map(|(_, text)| Some(index.with_feedback(index.text_query(text)?)))
+        .collect();
+    let valid: Vec<Query> = parsed.iter().flatten().cloned().collect();
+    let mut ranked = index.rank_batch(&valid, options.limit).into_iter();
+    let batch: Vec<BatchResults> = queries
         .iter()
-        .map(|file_path| read_file_words(file_path).unwrap())
+        .zip(&parsed)
+        .map(|((id, query), parsed)| BatchResults {
+            query_id: id,
+            query,
+            results: parsed
+                .as_ref()
+                .and_then(|_| ranked.next())
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(rank, (doc, score))| BatchHit {
+                    rank: rank + 1,
+                    id: index.doc_id(doc as usize),
+                    path: stored.name(doc as usize),
+                    score,
+                })
+                .collect(),
+        })
         .collect();
-    let start_time = Instant::now();
-    let ins = BM25::new(&my);
-    let end_time = Instant::now();
-    let duration = end_time.duration_since(start_time);
+    let mut out = io::stdout().lock();
+    output::write_batch(&mut out, options.display.format, &batch)?;
+    out.flush()
+}
+
+/// Evaluates the options' queries for every k1 and b in the ranges and writes
+/// the best pair into the config file.
+fn tune_parameters(options: &Options) -> io::Result<()> {
+    let (queries, qrels) = load_judgements(options);
+    let mut stored = open_index(options);
+    let mut out = io::stdout().lock();
+    let format = options.display.format;
+    output::write_tuning_header(&mut out, format)?;
+    let mut written = Ok(());
+    let best = eval::grid_search(
+        &options.k1_range,
+        &options.b_range,
+        options.measure,
+        |k1, b| {
+            stored.index.set_parameters(k1, b);
+            evaluate_queries(&stored, options, &queries, &qrels).all
+        },
+        |tuning| {
+            if written.is_ok() {
+                written = output::write_tuning(&mut out, format, tuning);
+            }
+        },
+    );
+    written?;
+    let Some(best) = best else {
+        return Ok(());
+    };
+    output::write_best_tuning(&mut out, format, &best, options.measure)?;
+    out.flush()?;
+    config::write_parameters(&options.config_path, best.k1, best.b).unwrap_or_else(|err| {
+        eprintln!("Could not write config {}: {}", options.config_path, err);
+        std::process::exit(1);
+    });
+    eprintln!("Wrote k1 and b to {}", options.config_path);
+    Ok(())
+}
+
+/// Loads the index file at the options' path, or builds an index of it, and
+/// applies the scoring options.
+fn open_index(options: &Options) -> StoredIndex {
+    let mut stored = if options.input.is_none() && Path::new(&options.path).is_file() {
+        let stored = load_index(&options.path).unwrap_or_else(|err| {
+            eprintln!("Could not load index {}: {}", options.path, err);
+            std::process::exit(1);
+        });
+        check_analysis(options, &stored.analysis);
+        stored
+    } else {
+        build_index(options).unwrap_or_else(|err| {
+            eprintln!("Could not index {}: {}", options.path, err);
+            std::process::exit(1);
+        })
+    };
+    stored.extractors = options.extractors();
+    if let Command::Index = options.command {
+        return stored;
+    }
+    stored.index.set_title_bonus(options.title_bonus);
+    stored
+        .index
+        .set_length_adjustment(options.length_adjustment);
+    stored.index.set_field_boosts(options.boosts.clone());
+    stored
+        .index
+        .set_field_length_normalization(options.field_b.clone());
+    stored.index.set_boost_key(options.doc_boost.clone());
+    stored.index.set_parameters(
+        options.k1.unwrap_or(stored.index.k1()),
+        options.b.unwrap_or(stored.index.b()),
+    );
+    stored
+        .index
+        .set_idf_mode(options.idf_mode.unwrap_or(stored.index.idf_mode()));
+    stored
+        .index
+        .set_scorer(scorer::by_name(options.scorer.as_deref().unwrap_or(&stored.scorer)).unwrap());
+    stored
+        .index
+        .set_synonyms(&options.synonyms, options.synonym_weight);
+    stored.index.set_proximity_weight(options.proximity_weight);
+    stored.index.set_feedback(options.feedback);
+    stored.index.set_cache_capacity(options.cache_size);
+    stored
+}
+
+/// Exits if the index at `path` is memory-mapped, as those are written once
+/// and can only be built again with `index --mmap`, not changed.
+fn refuse_disk_index(path: &str) {
+    if DiskIndex::is_disk_index(path) {
+        eprintln!(
+            "{} is a memory-mapped index; build it again with `index --mmap` instead",
+            path
+        );
+        std::process::exit(1);
+    }
+}
+
+fn update_index(options: &Options) {
+    refuse_disk_index(&options.path);
+    let mut stored = load_index(&options.path).unwrap_or_else(|err| {
+        eprintln!("Could not load index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+    check_analysis(options, &stored.analysis);
+    stored.extractors = options.extractors();
+    for file_path in &options.files {
+        if let Command::Add = options.command {
+            match stored.add_file(file_path) {
+                Ok(_) => println!("Added {}", file_path),
+                Err(err) => eprintln!("Could not add {}: {}", file_path, err),
+            }
+        } else {
+            let removed = stored.remove_file(file_path);
+            println!("Removed {} document(s) for {}", removed, file_path);
+        }
+    }
+    save_index(&stored, &options.path).unwrap_or_else(|err| {
+        eprintln!("Could not write index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+}
+
+/// Rewrites the index file at the options' path without the slots of removed
+/// documents.
+fn compact_index(options: &Options) {
+    refuse_disk_index(&options.path);
+    let mut stored = load_index(&options.path).unwrap_or_else(|err| {
+        eprintln!("Could not load index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+    let reclaimed = stored.index.compact();
+    let index = &stored.index;
+    stored.texts.retain(|&id, _| index.doc_index(id).is_some());
+    save_index(&stored, &options.path).unwrap_or_else(|err| {
+        eprintln!("Could not write index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
     println!(
-        "Time taken to create index: {}.{:03} seconds",
-        duration.as_secs(),
-        duration.subsec_millis()
+        "Compacted {}: reclaimed {} document slot(s)",
+        options.path, reclaimed
     );
-    loop {
-        print!("Enter a search query: ");
-        io::stdout().flush().unwrap();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let query: Vec<&str> = input.split_whitespace().collect();
-        let ranks = ins.rank_documents(query);
-        println!("Results:");
-        for (index, score) in ranks.iter().take(5) {
-            println!(
-                "{}: BM25 Score - {}",
-                all_files
-                    .get(*index as usize)
-                    .unwrap()
-                    .split("/")
-                    .last()
-                    .unwrap(),
-                score
-            );
+}
+
+/// Rewrites the index file at the options' path, written in an older format
+/// version, in the current one.
+fn upgrade_index(options: &Options) {
+    refuse_disk_index(&options.path);
+    let (stored, version) = read_index(&options.path, true).unwrap_or_else(|err| {
+        eprintln!("Could not load index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+    if version == FORMAT_VERSION {
+        println!(
+            "{} is already in format version {}",
+            options.path, FORMAT_VERSION
+        );
+        return;
+    }
+    save_index(&stored, &options.path).unwrap_or_else(|err| {
+        eprintln!("Could not write index {}: {}", options.path, err);
+        std::process::exit(1);
+    });
+    println!(
+        "Upgraded {} from format version {} to {}",
+        options.path, version, FORMAT_VERSION
+    );
+}
+
+/// Copies the index at the options' path to the file given after it. With
+/// `restore`, the options' path is a backup, which is checked to load before
+/// it replaces the index.
+fn back_up_index(options: &Options) {
+    let (from, to) = (&options.path, &options.files[0]);
+    let restore = matches!(options.command, Command::Restore);
+    let copied: Result<(), Box<dyn Error>> = if restore {
+        let loads: Result<(), Box<dyn Error>> = if DiskIndex::is_disk_index(from) {
+            DiskIndex::open(from).map(|_| ()).map_err(Into::into)
+        } else {
+            load_index(from).map(|_| ())
+        };
+        loads.and_then(|_| Ok(snapshot::restore(from, to)?))
+    } else {
+        snapshot::snapshot(from, to).map_err(Into::into)
+    };
+    if let Err(err) = copied {
+        eprintln!("Could not copy {} to {}: {}", from, to, err);
+        std::process::exit(1);
+    }
+    if restore {
+        println!("Restored {} from {}", to, from);
+    } else {
+        println!("Backed up {} to {}", from, to);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut options = parse_args(&args);
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("the thread pool is only configured once");
+    }
+    if let Some(level) = options.log_level {
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(io::stderr().is_terminal())
+            .with_writer(io::stderr)
+            .init();
+    }
+    let mut stored = match options.command {
+        Command::Search | Command::Interactive if !options.collections.is_empty() => {
+            search_collections(&mut options);
+            return;
+        }
+        _ if !options.collections.is_empty() => {
+            eprintln!("Only search can use more than one --collection");
+            std::process::exit(1);
+        }
+        Command::Search if options.input.is_none() && DiskIndex::is_disk_index(&options.path) => {
+            search_disk_index(&mut options);
+            return;
+        }
+        Command::Search | Command::Interactive
+            if options.shards > 1
+                && (options.input.is_some() || !Path::new(&options.path).is_file()) =>
+        {
+            search_sharded(&mut options);
+            return;
+        }
+        Command::Search | Command::Serve | Command::Interactive | Command::Index => {
+            open_index(&options)
+        }
+        Command::Add | Command::Remove => {
+            update_index(&options);
+            return;
+        }
+        Command::Compact => {
+            compact_index(&options);
+            return;
+        }
+        Command::Upgrade => {
+            upgrade_index(&options);
+            return;
         }
-        println!("---------------------")
+        Command::Backup | Command::Restore => {
+            back_up_index(&options);
+            return;
+        }
+        Command::Stats | Command::Terms => {
+            // A closed pipe, e.g. into `head`, is not an error.
+            let _ = describe_index(&options);
+            return;
+        }
+        Command::Eval => {
+            let _ = evaluate_index(&options);
+            return;
+        }
+        Command::Tune => {
+            let _ = tune_parameters(&options);
+            return;
+        }
+        Command::Batch => {
+            let _ = search_batch(&options);
+            return;
+        }
+    };
+    if let Command::Index = options.command {
+        let saved = if options.mmap {
+            DiskIndex::write(
+                &stored.index,
+                &stored.analysis,
+                &stored.scorer,
+                &options.output,
+            )
+            .map_err(Into::into)
+        } else {
+            save_index(&stored, &options.output)
+        };
+        saved.unwrap_or_else(|err| {
+            eprintln!("Could not write index {}: {}", options.output, err);
+            std::process::exit(1);
+        });
+        println!("Index written to {}", options.output);
+        return;
     }
+    if let Command::Serve = options.command {
+        let served = if options.grpc {
+            serve_grpc(stored, &options.addr, options.timeout)
+        } else {
+            server::serve(&mut stored, &options.addr, options.timeout)
+        };
+        if let Err(err) = served {
+            eprintln!("Could not start server on {}: {}", options.addr, err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Command::Search = options.command {
+        if !options.files.is_empty() {
+            let matched = print_results(&stored, &options, &options.files.join(" "))
+                .is_ok_and(|ranks| !ranks.hits.is_empty());
+            std::process::exit(if matched { 0 } else { 1 });
+        }
+    }
+    let watcher = options.watch.then(|| {
+        if !Path::new(&options.path).is_dir() {
+            eprintln!("--watch needs a directory, not an index file");
+            std::process::exit(1);
+        }
+        DirectoryWatcher::new(
+            &options.path,
+            options.include.clone(),
+            options.exclude.clone(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("Could not watch {}: {}", options.path, err);
+            std::process::exit(1);
+        })
+    });
+    run_queries(&mut stored, &mut options, watcher);
 }
@@ -0,0 +1,70 @@
+use crate::chunk::Passage;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io, path::Path, time::UNIX_EPOCH};
+
+/// A document id that stays the same while other documents are added and
+/// removed, unlike a document's index.
+pub type DocId = u64;
+
+/// What is known about a document besides its text. Stored in the index and
+/// returned with search results.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub path: Option<String>,
+    pub title: Option<String>,
+    /// Size in bytes of the file the document was read from.
+    pub size: Option<u64>,
+    /// Modification time of the file, in seconds since the Unix epoch.
+    pub mtime: Option<u64>,
+    /// User-defined values, e.g. front matter keys or record columns.
+    pub values: BTreeMap<String, String>,
+    /// The part of the document this one covers, if it was split into passages.
+    pub passage: Option<Passage>,
+    /// Paths of other files with the same content, which were indexed as this
+    /// document rather than on their own.
+    pub duplicates: Vec<String>,
+}
+
+impl Metadata {
+    /// The path, size and modification time of a file.
+    pub fn for_file(path: &str) -> io::Result<Metadata> {
+        let file = std::fs::metadata(path)?;
+        Ok(Metadata {
+            path: Some(path.to_string()),
+            size: Some(file.len()),
+            mtime: file
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            ..Metadata::default()
+        })
+    }
+
+    /// Looks up a value by name: `path`, `title`, `size`, `mtime`, `ext` (the
+    /// lowercase file extension), `dir` (the directory of the path, `.` for
+    /// a bare file name) or a user-defined key.
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "path" => self.path.clone(),
+            "title" => self.title.clone(),
+            "size" => self.size.map(|size| size.to_string()),
+            "mtime" => self.mtime.map(|mtime| mtime.to_string()),
+            "ext" => self
+                .path
+                .as_deref()
+                .and_then(|path| Path::new(path).extension())
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_lowercase()),
+            "dir" => self
+                .path
+                .as_deref()
+                .and_then(|path| Path::new(path).parent())
+                .map(|dir| match dir.to_string_lossy() {
+                    dir if dir.is_empty() => ".".to_string(),
+                    dir => dir.into_owned(),
+                }),
+            key => self.values.get(key).cloned(),
+        }
+    }
+}
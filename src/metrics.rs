@@ -0,0 +1,196 @@
+use fulltext::BM25Index;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{Display, Write},
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the query latency histogram's buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// How far back the queries per second are averaged over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// What the HTTP server has done since it started, for `GET /metrics`.
+pub struct Metrics {
+    started: Instant,
+    /// Queries that fell into each latency bucket, and above the last one.
+    latency_counts: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+    /// Queries by the second since starting they were answered in, over the
+    /// rate window.
+    recent: VecDeque<(u64, u64)>,
+    /// Searches that ran out of time and returned partial results.
+    timeouts: u64,
+    responses: BTreeMap<u16, u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            started: Instant::now(),
+            latency_counts: [0; LATENCY_BUCKETS.len() + 1],
+            latency_sum: 0.0,
+            recent: VecDeque::new(),
+            timeouts: 0,
+            responses: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_query(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_counts[bucket] += 1;
+        self.latency_sum += seconds;
+        let now = self.started.elapsed().as_secs();
+        match self.recent.back_mut() {
+            Some((second, count)) if *second == now => *count += 1,
+            _ => self.recent.push_back((now, 1)),
+        }
+        self.forget_before(now);
+    }
+
+    pub fn record_timeout(&mut self) {
+        self.timeouts += 1;
+    }
+
+    pub fn record_response(&mut self, status: u16) {
+        *self.responses.entry(status).or_default() += 1;
+    }
+
+    fn forget_before(&mut self, now: u64) {
+        let window = RATE_WINDOW.as_secs();
+        while self
+            .recent
+            .front()
+            .is_some_and(|&(second, _)| second + window <= now)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Queries per second over the rate window, or since starting if that
+    /// was more recent.
+    fn query_rate(&mut self) -> f64 {
+        let elapsed = self.started.elapsed();
+        self.forget_before(elapsed.as_secs());
+        let queries: u64 = self.recent.iter().map(|&(_, count)| count).sum();
+        queries as f64 / elapsed.min(RATE_WINDOW).as_secs_f64().max(1.0)
+    }
+
+    /// The metrics, and statistics of `index`, in the Prometheus text format.
+    pub fn render(&mut self, index: &BM25Index) -> String {
+        let mut out = String::new();
+        let queries: u64 = self.latency_counts.iter().sum();
+        let rate = self.query_rate();
+        let counter = "counter";
+        let gauge = "gauge";
+        single(
+            &mut out,
+            "fulltext_queries_total",
+            counter,
+            "Searches answered.",
+            queries,
+        );
+        let help = "Searches per second over the last minute.";
+        single(&mut out, "fulltext_queries_per_second", gauge, help, rate);
+        let help = "Searches that ran out of time and returned partial results.";
+        let timeouts = self.timeouts;
+        single(
+            &mut out,
+            "fulltext_query_timeouts_total",
+            counter,
+            help,
+            timeouts,
+        );
+
+        let name = "fulltext_query_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time taken to answer a search.",
+        );
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, queries);
+        let _ = writeln!(out, "{}_sum {}", name, self.latency_sum);
+        let _ = writeln!(out, "{}_count {}", name, queries);
+
+        let name = "fulltext_http_responses_total";
+        header(
+            &mut out,
+            name,
+            counter,
+            "HTTP responses sent, by status code.",
+        );
+        for (status, count) in &self.responses {
+            let _ = writeln!(out, "{}{{status=\"{}\"}} {}", name, status, count);
+        }
+
+        let stats = index.stats();
+        let help = "Live documents in the index.";
+        single(
+            &mut out,
+            "fulltext_documents",
+            gauge,
+            help,
+            index.doc_count(),
+        );
+        let help = "Distinct terms in the index.";
+        single(
+            &mut out,
+            "fulltext_terms",
+            gauge,
+            help,
+            stats.vocabulary_size,
+        );
+        let help = "Bytes of compressed postings held in memory.";
+        let size = index.postings_bytes();
+        single(&mut out, "fulltext_index_size_bytes", gauge, help, size);
+        if let Some(cache) = stats.cache {
+            let help = "Searches answered from the query cache.";
+            single(
+                &mut out,
+                "fulltext_cache_hits_total",
+                counter,
+                help,
+                cache.hits,
+            );
+            let help = "Searches the query cache did not have.";
+            single(
+                &mut out,
+                "fulltext_cache_misses_total",
+                counter,
+                help,
+                cache.misses,
+            );
+            let ratio = match cache.hits + cache.misses {
+                0 => 0.0,
+                lookups => cache.hits as f64 / lookups as f64,
+            };
+            let help = "Share of searches answered from the query cache.";
+            single(&mut out, "fulltext_cache_hit_ratio", gauge, help, ratio);
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// A metric with one value and no labels.
+fn single(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -0,0 +1,757 @@
+use crate::StoredIndex;
+use fulltext::{
+    collections::{CollectionHit, Collections},
+    disk::DiskIndex,
+    eval::{Evaluation, Measure, Metrics, Tuning},
+    facet::Facet,
+    shard::ShardedIndex,
+    snippet, DocId, Explanation, IndexStats, Metadata, RankedResults, TermInfo,
+};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    io::{self, IsTerminal, Write},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+    Feed,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<OutputFormat> {
+        match value {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "tsv" => Some(OutputFormat::Tsv),
+            "feed" => Some(OutputFormat::Feed),
+            _ => None,
+        }
+    }
+}
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy)]
+pub struct Display {
+    pub format: OutputFormat,
+    pub snippets: bool,
+    pub explain: bool,
+}
+
+/// A spelling correction offered for a query that found nothing. `applied`
+/// means the results shown are those of the corrected query.
+pub struct Correction {
+    pub query: String,
+    pub applied: bool,
+}
+
+#[derive(Serialize)]
+struct JsonResults<'a> {
+    query: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    did_you_mean: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    corrected: bool,
+    results: Vec<JsonResult<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    facets: &'a [Facet],
+}
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    rank: usize,
+    id: DocId,
+    path: &'a str,
+    score: f32,
+    metadata: &'a Metadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+}
+
+#[derive(Serialize)]
+struct JsonCollectionResults<'a> {
+    query: &'a str,
+    results: Vec<JsonCollectionResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonCollectionResult<'a> {
+    rank: usize,
+    collection: &'a str,
+    id: DocId,
+    path: &'a str,
+    score: f32,
+    metadata: &'a Metadata,
+}
+
+#[derive(Serialize)]
+struct Feed {
+    title: String,
+    query: String,
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Serialize)]
+struct FeedEntry {
+    id: String,
+    title: String,
+    score: f32,
+    snippet: String,
+}
+
+const SNIPPET_WORDS: usize = 20;
+
+/// Builds snippets around the query terms for the results of one query.
+struct Snippets<'a> {
+    stored: &'a StoredIndex,
+    terms: HashSet<String>,
+}
+
+impl Snippets<'_> {
+    fn render(&self, doc: i32, open: &str, close: &str) -> String {
+        let text = self.stored.document_text(doc as usize);
+        let analyze = |word: &str| self.stored.index.analyze(word);
+        snippet::best_window(&text, &self.terms, &analyze, SNIPPET_WORDS).render(open, close)
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.split("/").last().unwrap()
+}
+
+/// What the text format says instead of listing results when there are none.
+fn write_no_results(out: &mut impl Write, query: &str) -> io::Result<()> {
+    writeln!(out, "No results for \"{}\"", query)?;
+    writeln!(out, "---------------------")
+}
+
+fn write_facets(out: &mut impl Write, facets: &[Facet]) -> io::Result<()> {
+    if facets.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "Facets:")?;
+    for facet in facets {
+        let counts: Vec<String> = facet
+            .counts
+            .iter()
+            .map(|count| format!("{} ({})", count.value, count.count))
+            .collect();
+        writeln!(out, "  {}: {}", facet.key, counts.join(", "))?;
+    }
+    Ok(())
+}
+
+fn write_explanation(out: &mut impl Write, explanation: &Explanation) -> io::Result<()> {
+    for term in &explanation.terms {
+        let idf = term.idf.map_or("-".to_string(), |idf| idf.to_string());
+        let weight = match term.weight {
+            1.0 => String::new(),
+            weight => format!(" weight={}", weight),
+        };
+        writeln!(
+            out,
+            "    {}: tf={} df={} idf={} length_norm={}{} score={}",
+            term.term, term.tf, term.doc_freq, idf, term.length_norm, weight, term.score
+        )?;
+    }
+    write!(
+        out,
+        "    length factor {}, title bonus {}",
+        explanation.length_factor, explanation.title_bonus
+    )?;
+    if explanation.doc_boost != 1.0 {
+        write!(out, ", document boost {}", explanation.doc_boost)?;
+    }
+    if explanation.proximity_bonus != 0.0 {
+        write!(out, ", proximity bonus {}", explanation.proximity_bonus)?;
+    }
+    writeln!(out)
+}
+
+/// Writes one query's results, ranked from `offset + 1` when they are a later
+/// page. JSON is written as a single line per query so that a stream of queries
+/// produces JSON Lines.
+pub fn write_results(
+    out: &mut impl Write,
+    display: Display,
+    stored: &StoredIndex,
+    query: &str,
+    ranks: &RankedResults,
+    offset: usize,
+    correction: Option<&Correction>,
+) -> io::Result<()> {
+    let Display {
+        format,
+        snippets,
+        explain,
+    } = display;
+    let original = query;
+    let query = match correction {
+        Some(correction) if correction.applied => correction.query.as_str(),
+        _ => query,
+    };
+    let highlighter = Snippets {
+        stored,
+        terms: stored.query_terms(query),
+    };
+    let hits = ranks.hits.iter().enumerate().map(|(rank, (index, score))| {
+        let rank = offset + rank + 1;
+        (rank, *index, stored.name(*index as usize), *score)
+    });
+    let explanation = |doc: i32| explain.then(|| stored.explain(query, doc)).flatten();
+    match format {
+        OutputFormat::Text => {
+            match correction {
+                Some(Correction {
+                    query,
+                    applied: true,
+                }) => writeln!(
+                    out,
+                    "No results for \"{}\", showing results for \"{}\"",
+                    original, query
+                )?,
+                Some(Correction { query, .. }) => writeln!(out, "Did you mean: {}?", query)?,
+                None => {}
+            }
+            if ranks.hits.is_empty() {
+                return write_no_results(out, query);
+            }
+            writeln!(out, "Results:")?;
+            if ranks.all_tied {
+                writeln!(out, "(all documents are equally relevant)")?;
+            }
+            for (_, doc, path, score) in hits {
+                match stored.index.metadata(doc as usize).passage {
+                    Some(passage) => writeln!(
+                        out,
+                        "{} (lines {}-{}): BM25 Score - {}",
+                        file_name(path),
+                        passage.start_line,
+                        passage.end_line,
+                        score
+                    )?,
+                    None => writeln!(out, "{}: BM25 Score - {}", file_name(path), score)?,
+                }
+                let duplicates = &stored.index.metadata(doc as usize).duplicates;
+                if !duplicates.is_empty() {
+                    let names: Vec<&str> = duplicates.iter().map(|path| file_name(path)).collect();
+                    writeln!(out, "    Same content as: {}", names.join(", "))?;
+                }
+                if let Some(explanation) = explanation(doc) {
+                    write_explanation(out, &explanation)?;
+                }
+                if snippets {
+                    let (open, close) = if io::stdout().is_terminal() {
+                        ("\x1b[1;31m", "\x1b[0m")
+                    } else {
+                        ("", "")
+                    };
+                    writeln!(out, "    {}", highlighter.render(doc, open, close))?;
+                }
+            }
+            write_facets(out, &ranks.facets)?;
+            writeln!(out, "---------------------")
+        }
+        OutputFormat::Json => {
+            let results = JsonResults {
+                query: original,
+                did_you_mean: correction.map(|correction| correction.query.as_str()),
+                corrected: correction.is_some_and(|correction| correction.applied),
+                results: hits
+                    .map(|(rank, doc, path, score)| JsonResult {
+                        rank,
+                        id: stored.index.doc_id(doc as usize),
+                        path,
+                        score,
+                        metadata: stored.index.metadata(doc as usize),
+                        snippet: snippets.then(|| highlighter.render(doc, "<em>", "</em>")),
+                        explanation: explanation(doc),
+                    })
+                    .collect(),
+                facets: &ranks.facets,
+            };
+            writeln!(out, "{}", serde_json::to_string(&results).unwrap())
+        }
+        OutputFormat::Tsv => {
+            if let Some(correction) = correction {
+                eprintln!("Did you mean: {}?", correction.query);
+            }
+            for (rank, doc, path, score) in hits {
+                if snippets {
+                    let snippet = highlighter.render(doc, "", "");
+                    writeln!(out, "{}\t{}\t{}\t{}", rank, path, score, snippet)?;
+                } else {
+                    writeln!(out, "{}\t{}\t{}", rank, path, score)?;
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Feed => {
+            let feed = Feed {
+                title: format!("Search results for \"{}\"", query),
+                query: query.to_string(),
+                entries: hits
+                    .map(|(_, doc, path, score)| FeedEntry {
+                        id: path.to_string(),
+                        title: stored
+                            .index
+                            .metadata(doc as usize)
+                            .title
+                            .clone()
+                            .unwrap_or_else(|| file_name(path).to_string()),
+                        score,
+                        snippet: highlighter.render(doc, "<em>", "</em>"),
+                    })
+                    .collect(),
+            };
+            writeln!(out, "{}", serde_json::to_string_pretty(&feed).unwrap())
+        }
+    }
+}
+
+/// Writes one query's results from an on-disk index, in the same formats as
+/// [`write_results`] but without snippets or explanations.
+pub fn write_disk_results(
+    out: &mut impl Write,
+    format: OutputFormat,
+    index: &DiskIndex,
+    query: &str,
+    hits: &[(i32, f32)],
+    offset: usize,
+) -> io::Result<()> {
+    let documents: Vec<(DocId, Metadata)> = hits
+        .iter()
+        .map(|(doc, _)| (index.doc_id(*doc as usize), index.metadata(*doc as usize)))
+        .collect();
+    write_plain_results(out, format, query, &documents, hits, offset)
+}
+
+/// Writes one query's results from a sharded index, as
+/// [`write_disk_results`] does.
+pub fn write_sharded_results(
+    out: &mut impl Write,
+    format: OutputFormat,
+    index: &ShardedIndex,
+    query: &str,
+    hits: &[(DocId, f32)],
+    offset: usize,
+) -> io::Result<()> {
+    let documents: Vec<(DocId, Metadata)> = hits
+        .iter()
+        .map(|(id, _)| (*id, index.metadata(*id).unwrap_or_default()))
+        .collect();
+    write_plain_results(out, format, query, &documents, hits, offset)
+}
+
+/// Writes the `documents` that `hits` scored, without snippets or
+/// explanations.
+fn write_plain_results<D>(
+    out: &mut impl Write,
+    format: OutputFormat,
+    query: &str,
+    documents: &[(DocId, Metadata)],
+    hits: &[(D, f32)],
+    offset: usize,
+) -> io::Result<()> {
+    let name = |metadata: &Metadata| {
+        metadata
+            .path
+            .clone()
+            .or_else(|| metadata.values.get("id").cloned())
+            .unwrap_or_default()
+    };
+    let names: Vec<String> = documents
+        .iter()
+        .map(|(_, metadata)| name(metadata))
+        .collect();
+    match format {
+        OutputFormat::Text => {
+            if hits.is_empty() {
+                return write_no_results(out, query);
+            }
+            writeln!(out, "Results:")?;
+            for (((_, metadata), path), (_, score)) in documents.iter().zip(&names).zip(hits) {
+                match metadata.passage {
+                    Some(passage) => writeln!(
+                        out,
+                        "{} (lines {}-{}): BM25 Score - {}",
+                        file_name(path),
+                        passage.start_line,
+                        passage.end_line,
+                        score
+                    )?,
+                    None => writeln!(out, "{}: BM25 Score - {}", file_name(path), score)?,
+                }
+            }
+            writeln!(out, "---------------------")
+        }
+        OutputFormat::Json | OutputFormat::Feed => {
+            let results = JsonResults {
+                query,
+                did_you_mean: None,
+                corrected: false,
+                results: documents
+                    .iter()
+                    .zip(&names)
+                    .zip(hits)
+                    .enumerate()
+                    .map(|(rank, (((id, metadata), path), (_, score)))| JsonResult {
+                        rank: offset + rank + 1,
+                        id: *id,
+                        path,
+                        score: *score,
+                        metadata,
+                        snippet: None,
+                        explanation: None,
+                    })
+                    .collect(),
+                facets: &[],
+            };
+            writeln!(out, "{}", serde_json::to_string(&results).unwrap())
+        }
+        OutputFormat::Tsv => {
+            for (rank, (path, (_, score))) in names.iter().zip(hits).enumerate() {
+                writeln!(out, "{}\t{}\t{}", offset + rank + 1, path, score)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes the merged results of several collections, each labelled with the
+/// collection it came from.
+pub fn write_collection_results(
+    out: &mut impl Write,
+    format: OutputFormat,
+    collections: &Collections,
+    query: &str,
+    hits: &[CollectionHit],
+    offset: usize,
+) -> io::Result<()> {
+    let documents: Vec<(&Metadata, DocId)> = hits
+        .iter()
+        .map(|hit| {
+            let index = collections.get(&hit.collection).unwrap();
+            let doc = hit.doc as usize;
+            (index.metadata(doc), index.doc_id(doc))
+        })
+        .collect();
+    let name = |metadata: &'_ Metadata| -> String {
+        metadata
+            .path
+            .clone()
+            .or_else(|| metadata.values.get("id").cloned())
+            .unwrap_or_default()
+    };
+    let names: Vec<String> = documents
+        .iter()
+        .map(|(metadata, _)| name(metadata))
+        .collect();
+    match format {
+        OutputFormat::Text => {
+            if hits.is_empty() {
+                return write_no_results(out, query);
+            }
+            writeln!(out, "Results:")?;
+            for (hit, path) in hits.iter().zip(&names) {
+                writeln!(
+                    out,
+                    "[{}] {}: BM25 Score - {}",
+                    hit.collection,
+                    file_name(path),
+                    hit.score
+                )?;
+            }
+            writeln!(out, "---------------------")
+        }
+        OutputFormat::Json | OutputFormat::Feed => {
+            let results = JsonCollectionResults {
+                query,
+                results: hits
+                    .iter()
+                    .zip(&documents)
+                    .zip(&names)
+                    .enumerate()
+                    .map(
+                        |(rank, ((hit, (metadata, id)), path))| JsonCollectionResult {
+                            rank: offset + rank + 1,
+                            collection: &hit.collection,
+                            id: *id,
+                            path,
+                            score: hit.score,
+                            metadata,
+                        },
+                    )
+                    .collect(),
+            };
+            writeln!(out, "{}", serde_json::to_string(&results).unwrap())
+        }
+        OutputFormat::Tsv => {
+            for (rank, (hit, path)) in hits.iter().zip(&names).enumerate() {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    offset + rank + 1,
+                    hit.collection,
+                    path,
+                    hit.score
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonStats<'a> {
+    #[serde(flatten)]
+    stats: &'a IndexStats,
+    index_bytes: Option<u64>,
+}
+
+/// Writes the statistics of an index, with the size of its file if it was
+/// loaded from one.
+pub fn write_stats(
+    out: &mut impl Write,
+    format: OutputFormat,
+    stats: &IndexStats,
+    size: Option<u64>,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            writeln!(out, "Documents: {}", stats.doc_count)?;
+            writeln!(out, "Total length: {} tokens", stats.total_doc_length)?;
+            writeln!(out, "Average length: {:.2} tokens", stats.avg_doc_length)?;
+            writeln!(out, "Vocabulary: {} terms", stats.vocabulary_size)?;
+            if stats.deleted > 0 {
+                writeln!(out, "Deleted: {} documents, until compacted", stats.deleted)?;
+            }
+            if let Some(size) = size {
+                writeln!(out, "Index size: {} bytes", size)?;
+            }
+            if let Some(cache) = &stats.cache {
+                writeln!(
+                    out,
+                    "Query cache: {} hits, {} misses, {} of {} entries",
+                    cache.hits, cache.misses, cache.entries, cache.capacity
+                )?;
+            }
+            Ok(())
+        }
+        OutputFormat::Json | OutputFormat::Feed => {
+            let stats = JsonStats {
+                stats,
+                index_bytes: size,
+            };
+            writeln!(out, "{}", serde_json::to_string(&stats).unwrap())
+        }
+        OutputFormat::Tsv => {
+            writeln!(out, "doc_count\t{}", stats.doc_count)?;
+            writeln!(out, "total_doc_length\t{}", stats.total_doc_length)?;
+            writeln!(out, "avg_doc_length\t{}", stats.avg_doc_length)?;
+            writeln!(out, "vocabulary_size\t{}", stats.vocabulary_size)?;
+            if stats.deleted > 0 {
+                writeln!(out, "deleted\t{}", stats.deleted)?;
+            }
+            if let Some(size) = size {
+                writeln!(out, "index_bytes\t{}", size)?;
+            }
+            if let Some(cache) = &stats.cache {
+                writeln!(out, "cache_hits\t{}", cache.hits)?;
+                writeln!(out, "cache_misses\t{}", cache.misses)?;
+                writeln!(out, "cache_entries\t{}", cache.entries)?;
+                writeln!(out, "cache_capacity\t{}", cache.capacity)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes terms with their document frequency, collection frequency and IDF.
+pub fn write_terms(
+    out: &mut impl Write,
+    format: OutputFormat,
+    terms: &[TermInfo],
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json | OutputFormat::Feed => {
+            writeln!(out, "{}", serde_json::to_string(terms).unwrap())
+        }
+        OutputFormat::Text | OutputFormat::Tsv => {
+            if format == OutputFormat::Text {
+                writeln!(out, "term\tdf\tcf\tidf")?;
+            }
+            for term in terms {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    term.term, term.doc_freq, term.collection_freq, term.idf
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The results of one query of a `batch`.
+#[derive(Serialize)]
+pub struct BatchResults<'a> {
+    pub query_id: &'a str,
+    pub query: &'a str,
+    pub results: Vec<BatchHit<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct BatchHit<'a> {
+    pub rank: usize,
+    pub id: DocId,
+    pub path: &'a str,
+    pub score: f32,
+}
+
+/// Writes the results of a batch of queries: as a run in the TREC format,
+/// which `trec_eval` reads, as a table with one row per result, or as one
+/// JSON object per query and line.
+pub fn write_batch(
+    out: &mut impl Write,
+    format: OutputFormat,
+    batch: &[BatchResults],
+) -> io::Result<()> {
+    if format == OutputFormat::Tsv {
+        writeln!(out, "query\trank\tid\tpath\tscore")?;
+    }
+    for results in batch {
+        match format {
+            OutputFormat::Text => {
+                for hit in &results.results {
+                    writeln!(
+                        out,
+                        "{} Q0 {} {} {} fulltext",
+                        results.query_id, hit.path, hit.rank, hit.score
+                    )?;
+                }
+            }
+            OutputFormat::Tsv => {
+                for hit in &results.results {
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{}",
+                        results.query_id, hit.rank, hit.id, hit.path, hit.score
+                    )?;
+                }
+            }
+            OutputFormat::Json | OutputFormat::Feed => {
+                writeln!(out, "{}", serde_json::to_string(results).unwrap())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the mean of each measure, and in TSV also each query's, as
+/// `trec_eval -q` does.
+pub fn write_evaluation(
+    out: &mut impl Write,
+    format: OutputFormat,
+    evaluation: &Evaluation,
+) -> io::Result<()> {
+    let row = |out: &mut dyn Write, id: &str, metrics: &Metrics| {
+        writeln!(
+            out,
+            "{}\t{:.4}\t{:.4}\t{:.4}\t{:.4}",
+            id, metrics.map, metrics.ndcg, metrics.mrr, metrics.recall
+        )
+    };
+    match format {
+        OutputFormat::Text => {
+            let all = &evaluation.all;
+            writeln!(out, "Queries: {}", evaluation.queries.len())?;
+            writeln!(out, "MAP: {:.4}", all.map)?;
+            writeln!(out, "nDCG@10: {:.4}", all.ndcg)?;
+            writeln!(out, "MRR: {:.4}", all.mrr)?;
+            writeln!(out, "Recall: {:.4}", all.recall)
+        }
+        OutputFormat::Json | OutputFormat::Feed => {
+            writeln!(out, "{}", serde_json::to_string(evaluation).unwrap())
+        }
+        OutputFormat::Tsv => {
+            writeln!(out, "query\tmap\tndcg@10\tmrr\trecall")?;
+            for (id, metrics) in &evaluation.queries {
+                row(out, id, metrics)?;
+            }
+            row(out, "all", &evaluation.all)
+        }
+    }
+}
+
+/// Starts the table that [`write_tuning`] adds to.
+pub fn write_tuning_header(out: &mut impl Write, format: OutputFormat) -> io::Result<()> {
+    match format {
+        OutputFormat::Tsv => writeln!(out, "k1\tb\tmap\tndcg@10\tmrr\trecall"),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the measures of one pair of parameters tried by `tune`, which only
+/// the TSV format lists.
+pub fn write_tuning(out: &mut impl Write, format: OutputFormat, tuning: &Tuning) -> io::Result<()> {
+    let Tuning { k1, b, metrics } = tuning;
+    match format {
+        OutputFormat::Tsv => writeln!(
+            out,
+            "{}\t{}\t{:.4}\t{:.4}\t{:.4}\t{:.4}",
+            k1, b, metrics.map, metrics.ndcg, metrics.mrr, metrics.recall
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonTuning<'a> {
+    k1: f32,
+    b: f32,
+    #[serde(flatten)]
+    metrics: &'a Metrics,
+}
+
+/// Writes the best parameters found by `tune`.
+pub fn write_best_tuning(
+    out: &mut impl Write,
+    format: OutputFormat,
+    best: &Tuning,
+    measure: Measure,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            let name = match measure {
+                Measure::Map => "MAP",
+                Measure::Ndcg => "nDCG@10",
+                Measure::Mrr => "MRR",
+                Measure::Recall => "Recall",
+            };
+            writeln!(
+                out,
+                "Best: k1 = {}, b = {} ({} {:.4})",
+                best.k1,
+                best.b,
+                name,
+                measure.of(&best.metrics)
+            )
+        }
+        OutputFormat::Json | OutputFormat::Feed => {
+            let best = JsonTuning {
+                k1: best.k1,
+                b: best.b,
+                metrics: &best.metrics,
+            };
+            writeln!(out, "{}", serde_json::to_string(&best).unwrap())
+        }
+        // The table already has the best row among the others.
+        OutputFormat::Tsv => Ok(()),
+    }
+}
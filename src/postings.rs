@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One document's occurrences of a term. `positions` are token offsets and are
+/// empty for documents added from pre-aggregated counts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Posting {
+    pub doc: i32,
+    pub tf: i32,
+    pub positions: Vec<i32>,
+}
+
+/// A shared term dictionary, so every term string is stored once and the
+/// postings and per-document counts refer to it by id. Ids are never reused,
+/// even once no document contains the term any more.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Terms {
+    ids: HashMap<String, u32>,
+    names: Vec<String>,
+}
+
+impl Terms {
+    pub fn id(&self, term: &str) -> Option<u32> {
+        self.ids.get(term).copied()
+    }
+
+    /// The id of `term`, assigning the next one if it is new.
+    pub fn intern(&mut self, term: &str) -> u32 {
+        if let Some(id) = self.id(term) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.ids.insert(term.to_string(), id);
+        self.names.push(term.to_string());
+        id
+    }
+
+    pub fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// The varint at `offset`, or `None` if the bytes end first or it does not
+/// fit in a `u32`, as in a damaged file.
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Option<u32> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+        if shift == 28 && byte > 0x0f {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+/// The postings of one term in increasing document order, stored as
+/// varint-encoded gaps: each posting is the document's distance from the
+/// previous one, its term frequency, its number of positions and the gaps
+/// between its positions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostingList {
+    bytes: Vec<u8>,
+    len: u32,
+    last_doc: i32,
+    collection_freq: i32,
+}
+
+impl PostingList {
+    /// Appends a posting, whose document must come after every one already in
+    /// the list.
+    pub fn push(&mut self, posting: &Posting) {
+        debug_assert!(self.len == 0 || posting.doc > self.last_doc);
+        let previous = if self.len == 0 { 0 } else { self.last_doc };
+        write_varint(&mut self.bytes, (posting.doc - previous) as u32);
+        write_varint(&mut self.bytes, posting.tf as u32);
+        write_varint(&mut self.bytes, posting.positions.len() as u32);
+        let mut previous = 0;
+        for &position in &posting.positions {
+            write_varint(&mut self.bytes, (position - previous) as u32);
+            previous = position;
+        }
+        self.len += 1;
+        self.last_doc = posting.doc;
+        self.collection_freq += posting.tf;
+    }
+
+    /// Number of documents in the list.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Total term frequency over the documents in the list.
+    pub fn collection_freq(&self) -> i32 {
+        self.collection_freq
+    }
+
+    pub fn as_slice(&self) -> PostingSlice<'_> {
+        PostingSlice {
+            bytes: &self.bytes,
+            len: self.len,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Posting> + '_ {
+        self.as_slice().iter()
+    }
+
+    pub fn docs(&self) -> impl Iterator<Item = i32> + '_ {
+        self.as_slice().docs()
+    }
+
+    /// Drops the posting of `doc`, re-encoding the rest of the list.
+    pub fn remove(&mut self, doc: i32) {
+        let mut list = PostingList::default();
+        for posting in self.iter().filter(|posting| posting.doc != doc) {
+            list.push(&posting);
+        }
+        *self = list;
+    }
+}
+
+/// Encoded postings borrowed from a [`PostingList`] or from a file.
+#[derive(Debug, Clone, Copy)]
+pub struct PostingSlice<'a> {
+    bytes: &'a [u8],
+    len: u32,
+}
+
+impl<'a> PostingSlice<'a> {
+    /// `len` postings encoded as by [`PostingList::push`].
+    pub fn new(bytes: &'a [u8], len: u32) -> PostingSlice<'a> {
+        PostingSlice { bytes, len }
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decodes the postings in document order. Decoding stops at the first
+    /// posting that is cut short or malformed.
+    pub fn iter(self) -> impl Iterator<Item = Posting> + 'a {
+        let mut offset = 0;
+        let mut doc = 0i32;
+        (0..self.len).map_while(move |_| {
+            doc = doc.wrapping_add(read_varint(self.bytes, &mut offset)? as i32);
+            let tf = read_varint(self.bytes, &mut offset)? as i32;
+            let count = read_varint(self.bytes, &mut offset)?;
+            let mut position = 0i32;
+            let mut positions = vec![];
+            for _ in 0..count {
+                position = position.wrapping_add(read_varint(self.bytes, &mut offset)? as i32);
+                positions.push(position);
+            }
+            Some(Posting { doc, tf, positions })
+        })
+    }
+
+    /// The documents in the list, skipping over their positions.
+    pub fn docs(self) -> impl Iterator<Item = i32> + 'a {
+        self.frequencies().map(|(doc, _)| doc)
+    }
+
+    /// Each document with its term frequency, skipping over their positions.
+    /// Like [`PostingSlice::iter`], it stops at a malformed posting.
+    pub fn frequencies(self) -> impl Iterator<Item = (i32, i32)> + 'a {
+        let mut offset = 0;
+        let mut doc = 0i32;
+        (0..self.len).map_while(move |_| {
+            doc = doc.wrapping_add(read_varint(self.bytes, &mut offset)? as i32);
+            let tf = read_varint(self.bytes, &mut offset)? as i32;
+            for _ in 0..read_varint(self.bytes, &mut offset)? {
+                read_varint(self.bytes, &mut offset)?;
+            }
+            Some((doc, tf))
+        })
+    }
+}
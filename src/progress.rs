@@ -0,0 +1,130 @@
+use fulltext::{Metadata, Progress};
+use std::{
+    cell::Cell,
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
+const BAR_WIDTH: usize = 30;
+
+/// Shows how far indexing has got on stderr: a progress bar while it runs, if
+/// stderr is a terminal, and a summary line when it is done.
+pub struct IndexProgress {
+    /// What is counted, e.g. "files".
+    unit: &'static str,
+    total: usize,
+    total_bytes: u64,
+    done: Cell<usize>,
+    bytes: Cell<u64>,
+    /// Files left out because their content was already indexed.
+    duplicates: Cell<usize>,
+    /// Files left out because they are not text.
+    binary: Cell<usize>,
+    bar: bool,
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+impl IndexProgress {
+    /// Counts `total` files or records of `total_bytes` altogether.
+    pub fn new(unit: &'static str, total: usize, total_bytes: u64) -> IndexProgress {
+        IndexProgress {
+            unit,
+            total,
+            total_bytes,
+            done: Cell::new(0),
+            bytes: Cell::new(0),
+            duplicates: Cell::new(0),
+            binary: Cell::new(0),
+            bar: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Counts an added document; passages after a file's first are part of
+    /// a file that was already counted.
+    pub fn document(&self, metadata: &Metadata, text: &str) {
+        if metadata.passage.is_some_and(|passage| passage.start > 0) {
+            return;
+        }
+        self.done.set(self.done.get() + 1);
+        let bytes = metadata.size.unwrap_or(text.len() as u64);
+        self.bytes.set(self.bytes.get() + bytes);
+    }
+
+    /// Counts a file that was left out as a duplicate.
+    pub fn duplicate(&self, metadata: &Metadata, text: &str) {
+        self.duplicates.set(self.duplicates.get() + 1);
+        self.document(metadata, text);
+    }
+
+    /// Counts a file that was left out as binary.
+    pub fn binary(&self) {
+        self.binary.set(self.binary.get() + 1);
+        self.done.set(self.done.get() + 1);
+    }
+
+    /// Redraws the bar after a batch was added.
+    pub fn update(&self, progress: &Progress) {
+        if !self.bar {
+            return;
+        }
+        let fraction = match self.total_bytes {
+            0 => 1.0,
+            total => (self.bytes.get() as f64 / total as f64).min(1.0),
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let eta = if fraction > 0.0 {
+            let left = progress.elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+            format!("ETA {}s", left.ceil() as u64)
+        } else {
+            "ETA -".to_string()
+        };
+        let mut err = io::stderr().lock();
+        let _ = write!(
+            err,
+            "\r[{}{}] {}/{} {}, {}, {}\x1b[K",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done.get(),
+            self.total,
+            self.unit,
+            format_bytes(self.bytes.get()),
+            eta
+        );
+        let _ = err.flush();
+    }
+
+    /// Clears the bar and prints what was indexed.
+    pub fn finish(&self, documents: usize, elapsed: Duration) {
+        if self.bar {
+            eprint!("\r\x1b[K");
+        }
+        eprintln!(
+            "Indexed {} {} ({}) as {} documents in {}.{:03} seconds",
+            self.done.get() - self.duplicates.get() - self.binary.get(),
+            self.unit,
+            format_bytes(self.bytes.get()),
+            documents,
+            elapsed.as_secs(),
+            elapsed.subsec_millis()
+        );
+        if self.duplicates.get() > 0 {
+            eprintln!("Left out {} duplicate {}", self.duplicates.get(), self.unit);
+        }
+        if self.binary.get() > 0 {
+            eprintln!("Left out {} binary {}", self.binary.get(), self.unit);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Python bindings, compiled in with the `python` feature and built into the
+//! `fulltext` extension module by maturin:
+//!
+//! ```python
+//! from fulltext import BM25Index
+//!
+//! index = BM25Index.index(["the quick brown fox", "a lazy dog"], stemmer="english")
+//! index.search("foxes", k=10)  # [(0, 0.98...)]
+//! ```
+//!
+//! Documents and queries are either text, which is analyzed, or lists of
+//! tokens, which are used as they are, as with `rank_bm25`.
+use crate::{
+    analyzer::{AnalyzerConfig, Stemmer},
+    stopwords, BM25Index,
+};
+use pyo3::{
+    exceptions::{PyIOError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use std::fs;
+
+#[derive(FromPyObject)]
+enum Text {
+    Text(String),
+    Tokens(Vec<String>),
+}
+
+#[pyclass(name = "BM25Index", module = "fulltext")]
+struct PyBM25Index {
+    index: BM25Index,
+    analysis: AnalyzerConfig,
+}
+
+impl PyBM25Index {
+    fn tokens(&self, text: Text) -> Vec<String> {
+        match text {
+            Text::Text(text) => self.index.analyze(&text),
+            Text::Tokens(tokens) => tokens,
+        }
+    }
+
+    fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        bincode::serialize(&(&self.analysis, &self.index))
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> PyResult<PyBM25Index> {
+        let (analysis, mut index): (AnalyzerConfig, BM25Index) = bincode::deserialize(bytes)
+            .map_err(|err| PyValueError::new_err(format!("not a saved index: {}", err)))?;
+        index.set_analyzer(Box::new(analysis.build()));
+        Ok(PyBM25Index { index, analysis })
+    }
+}
+
+#[pymethods]
+impl PyBM25Index {
+    /// An empty index. `stemmer` and `stopwords` name a language, e.g.
+    /// "english", to stem text with and to drop the stop words of.
+    #[new]
+    #[pyo3(signature = (k1 = 1.5, b = 0.75, stemmer = None, stopwords = None))]
+    fn new(
+        k1: f32,
+        b: f32,
+        stemmer: Option<String>,
+        stopwords: Option<&str>,
+    ) -> PyResult<PyBM25Index> {
+        let mut analysis = AnalyzerConfig::default();
+        if let Some(language) = &stemmer {
+            if Stemmer::for_language(language).is_none() {
+                return Err(PyValueError::new_err(format!("unknown stemmer: {}", language)));
+            }
+        }
+        analysis.stemmer = stemmer;
+        if let Some(language) = stopwords {
+            let words = stopwords::builtin(language).ok_or_else(|| {
+                PyValueError::new_err(format!("no stop words for {}", language))
+            })?;
+            analysis.stopwords = words.iter().map(|word| word.to_string()).collect();
+        }
+        let mut index = BM25Index::with_analyzer(Box::new(analysis.build()));
+        index.set_parameters(k1, b);
+        Ok(PyBM25Index { index, analysis })
+    }
+
+    /// Indexes `corpus`, a list of texts or of token lists, in parallel.
+    #[staticmethod]
+    #[pyo3(signature = (corpus, k1 = 1.5, b = 0.75, stemmer = None, stopwords = None))]
+    fn index(
+        py: Python<'_>,
+        corpus: Vec<Text>,
+        k1: f32,
+        b: f32,
+        stemmer: Option<String>,
+        stopwords: Option<&str>,
+    ) -> PyResult<PyBM25Index> {
+        let mut index = PyBM25Index::new(k1, b, stemmer, stopwords)?;
+        py.detach(|| {
+            let mut texts = vec![];
+            for document in corpus {
+                // Runs of texts are analyzed in parallel, keeping the order.
+                match document {
+                    Text::Text(text) => texts.push(text),
+                    Text::Tokens(tokens) => {
+                        index.index.add_texts(&std::mem::take(&mut texts));
+                        index.index.add_document(&tokens);
+                    }
+                }
+            }
+            index.index.add_texts(&texts);
+        });
+        Ok(index)
+    }
+
+    /// Adds a text or token list and returns its index.
+    fn add(&mut self, document: Text) -> usize {
+        let tokens = self.tokens(document);
+        self.index.add_document(&tokens)
+    }
+
+    /// The `k` best documents for `query` as `(index, score)` pairs.
+    #[pyo3(signature = (query, k = 10))]
+    fn search(&self, py: Python<'_>, query: Text, k: usize) -> Vec<(i32, f32)> {
+        let tokens = self.tokens(query);
+        py.detach(|| {
+            let terms: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
+            self.index.rank_top_k(&terms, k)
+        })
+    }
+
+    /// The score of every document for `query`, in index order, as
+    /// `rank_bm25`'s `get_scores`.
+    fn get_scores(&self, py: Python<'_>, query: Text) -> Vec<f32> {
+        let tokens = self.tokens(query);
+        py.detach(|| {
+            let terms: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
+            (0..self.index.slot_count())
+                .map(|doc| self.index.score(&terms, doc))
+                .collect()
+        })
+    }
+
+    /// The `n` best of `documents`, which are in index order, as `rank_bm25`'s
+    /// `get_top_n`.
+    #[pyo3(signature = (query, documents, n = 5))]
+    fn get_top_n(
+        &self,
+        py: Python<'_>,
+        query: Text,
+        documents: Vec<Py<PyAny>>,
+        n: usize,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        if documents.len() != self.index.slot_count() {
+            return Err(PyValueError::new_err(format!(
+                "expected {} documents, got {}",
+                self.index.slot_count(),
+                documents.len()
+            )));
+        }
+        Ok(self
+            .search(py, query, n)
+            .into_iter()
+            .map(|(doc, _)| documents[doc as usize].clone_ref(py))
+            .collect())
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.doc_count()
+    }
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        fs::write(path, self.to_bytes()?).map_err(|err| PyIOError::new_err(err.to_string()))
+    }
+
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<PyBM25Index> {
+        let bytes = fs::read(path).map_err(|err| PyIOError::new_err(err.to_string()))?;
+        PyBM25Index::from_bytes(&bytes)
+    }
+
+    fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.to_bytes()?))
+    }
+
+    fn __setstate__(&mut self, state: &[u8]) -> PyResult<()> {
+        *self = PyBM25Index::from_bytes(state)?;
+        Ok(())
+    }
+}
+
+#[pymodule]
+fn fulltext(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBM25Index>()
+}
@@ -0,0 +1,298 @@
+/// How a clause of a boolean group constrains the matching documents, with the
+/// same meaning as Lucene's SHOULD / MUST / MUST_NOT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Occur {
+    Should,
+    Must,
+    MustNot,
+}
+
+/// A parsed `rust AND async NOT tokio` style query. Terms are already analyzed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    Phrase(Vec<String>),
+    /// A term to be matched within an edit distance. The index replaces it with
+    /// the matching terms of its dictionary before the query is used.
+    Fuzzy(String, u8),
+    /// A `rust*` prefix, likewise replaced with the indexed terms it starts.
+    Prefix(String),
+    Group(Vec<(Occur, Query)>),
+    /// A query whose term and phrase scores are multiplied by a weight, e.g. a
+    /// synonym that should count for less than the word it was expanded from,
+    /// or a `rust^2` word.
+    Boost(Box<Query>, f32),
+    /// A `title:rust` term, matched and scored in one field only. `body` is
+    /// the document text itself.
+    FieldTerm(String, String),
+}
+
+impl Query {
+    /// Single terms that contribute to the score, i.e. those not under `NOT`
+    /// and not part of a phrase.
+    pub fn scoring_terms(&self) -> Vec<String> {
+        self.weighted_terms()
+            .into_iter()
+            .map(|(term, _)| term)
+            .collect()
+    }
+
+    /// Phrases that contribute to the score, i.e. those not under `NOT`.
+    pub fn scoring_phrases(&self) -> Vec<Vec<String>> {
+        self.weighted_phrases()
+            .into_iter()
+            .map(|(phrase, _)| phrase)
+            .collect()
+    }
+
+    /// The scoring terms with the product of the boosts above each.
+    pub fn weighted_terms(&self) -> Vec<(String, f32)> {
+        self.scoring().terms
+    }
+
+    /// The scoring phrases with the product of the boosts above each.
+    pub fn weighted_phrases(&self) -> Vec<(Vec<String>, f32)> {
+        self.scoring().phrases
+    }
+
+    /// The scoring `field:term` terms as field, term and the product of the
+    /// boosts above each.
+    pub fn weighted_field_terms(&self) -> Vec<(String, String, f32)> {
+        self.scoring().field_terms
+    }
+
+    fn scoring(&self) -> Scoring {
+        let mut scoring = Scoring::default();
+        self.collect_scoring(1.0, &mut scoring);
+        scoring
+    }
+
+    fn collect_scoring(&self, weight: f32, scoring: &mut Scoring) {
+        match self {
+            Query::Term(term) | Query::Fuzzy(term, _) | Query::Prefix(term) => {
+                scoring.terms.push((term.clone(), weight))
+            }
+            Query::Phrase(phrase) => scoring.phrases.push((phrase.clone(), weight)),
+            Query::FieldTerm(field, term) => {
+                scoring
+                    .field_terms
+                    .push((field.clone(), term.clone(), weight))
+            }
+            Query::Group(clauses) => {
+                for (occur, clause) in clauses {
+                    if *occur != Occur::MustNot {
+                        clause.collect_scoring(weight, scoring);
+                    }
+                }
+            }
+            Query::Boost(query, boost) => query.collect_scoring(weight * boost, scoring),
+        }
+    }
+}
+
+/// The scoring parts of a query, each with its weight.
+#[derive(Default)]
+struct Scoring {
+    terms: Vec<(String, f32)>,
+    phrases: Vec<(Vec<String>, f32)>,
+    field_terms: Vec<(String, String, f32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Open,
+    Close,
+}
+
+/// Splits a query string into words, quoted phrases and parentheses.
+fn lex(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut chars = text.chars();
+    let flush = |word: &mut String, tokens: &mut Vec<Token>| {
+        if !word.is_empty() {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                flush(&mut word, &mut tokens);
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Phrase(phrase));
+            }
+            '(' if word.is_empty() => tokens.push(Token::Open),
+            ')' => {
+                flush(&mut word, &mut tokens);
+                tokens.push(Token::Close);
+            }
+            c if c.is_whitespace() => flush(&mut word, &mut tokens),
+            c => word.push(c),
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+/// The largest edit distance a fuzzy term may ask for.
+pub const MAX_EDIT_DISTANCE: u8 = 2;
+
+/// Splits `serach~1` into the word and its edit distance. A bare `~` means the
+/// maximum distance.
+fn fuzzy_suffix(word: &str) -> Option<(&str, u8)> {
+    let (word, distance) = word.rsplit_once('~')?;
+    let distance = match distance {
+        "" => MAX_EDIT_DISTANCE,
+        distance => distance.parse().ok().filter(|&d| d <= MAX_EDIT_DISTANCE)?,
+    };
+    (!word.is_empty()).then_some((word, distance))
+}
+
+fn prefix_word(word: &str) -> Option<&str> {
+    word.strip_suffix('*').filter(|word| !word.is_empty())
+}
+
+/// Splits `rust^2` into the word and its boost. The word is empty for a boost
+/// that follows a phrase or a group, as in `"async rust"^2`.
+fn boost_suffix(word: &str) -> Option<(&str, f32)> {
+    let (word, boost) = word.rsplit_once('^')?;
+    let boost: f32 = boost.parse().ok()?;
+    (boost.is_finite() && boost >= 0.0).then_some((word, boost))
+}
+
+/// Splits `title:rust` into the field name and the word. A field name is a
+/// letter followed by letters, digits or underscores, and the word may not
+/// start with `:` or `/`, so `std::vec` or `https://example.com` is left
+/// alone.
+fn field_prefix(word: &str) -> Option<(&str, &str)> {
+    let (field, word) = word.split_once(':')?;
+    let mut chars = field.chars();
+    let name = chars.next().is_some_and(|c| c.is_alphabetic())
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    (name && !word.is_empty() && !word.starts_with([':', '/'])).then_some((field, word))
+}
+
+/// Whether the text uses a boolean operator, a quoted phrase, a fuzzy term, a
+/// prefix, a boost or a field and should be parsed as a structured query
+/// rather than a bag of words.
+pub fn is_structured(text: &str) -> bool {
+    text.contains('"')
+        || text.split_whitespace().any(|word| {
+            matches!(word, "AND" | "OR" | "NOT")
+                || fuzzy_suffix(word).is_some()
+                || prefix_word(word).is_some()
+                || boost_suffix(word.trim_end_matches(')')).is_some()
+                || field_prefix(word.trim_start_matches('(')).is_some()
+        })
+}
+
+/// Parses a boolean query with optional quoted phrases, fuzzy `term~N` words,
+/// `prefix*` words, `field:word` terms and `^N` boosts after a word, a phrase
+/// or a group. Operators must be uppercase; the default operator between
+/// clauses is OR. Each word is passed through `analyze`, and words that
+/// analyze to nothing (e.g. stop words) are dropped. Returns `None` for a query
+/// with no usable clauses.
+pub fn parse(text: &str, analyze: &dyn Fn(&str) -> Vec<String>) -> Option<Query> {
+    let tokens = lex(text);
+    let mut position = 0;
+    parse_group(&tokens, &mut position, analyze)
+}
+
+fn parse_group(
+    tokens: &[Token],
+    position: &mut usize,
+    analyze: &dyn Fn(&str) -> Vec<String>,
+) -> Option<Query> {
+    let mut clauses: Vec<(Occur, Query)> = vec![];
+    let mut next_occur = Occur::Should;
+    let mut after_clause = false;
+    while let Some(token) = tokens.get(*position) {
+        *position += 1;
+        let follows_clause = std::mem::take(&mut after_clause);
+        let clause = match token {
+            Token::Close => break,
+            Token::Word(word) if word == "AND" => {
+                if let Some(last) = clauses.last_mut() {
+                    if last.0 == Occur::Should {
+                        last.0 = Occur::Must;
+                    }
+                }
+                if next_occur != Occur::MustNot {
+                    next_occur = Occur::Must;
+                }
+                continue;
+            }
+            Token::Word(word) if word == "OR" => {
+                if next_occur != Occur::MustNot {
+                    next_occur = Occur::Should;
+                }
+                continue;
+            }
+            Token::Word(word) if word == "NOT" => {
+                next_occur = Occur::MustNot;
+                continue;
+            }
+            Token::Open => parse_group(tokens, position, analyze),
+            Token::Phrase(phrase) => {
+                let mut terms = analyze(phrase);
+                match terms.len() {
+                    0 => None,
+                    1 => terms.pop().map(Query::Term),
+                    _ => Some(Query::Phrase(terms)),
+                }
+            }
+            Token::Word(word) => match boost_suffix(word) {
+                Some(("", boost)) => {
+                    if let Some(last) = clauses.last_mut().filter(|_| follows_clause) {
+                        last.1 = Query::Boost(Box::new(last.1.clone()), boost);
+                    }
+                    continue;
+                }
+                Some((word, boost)) => {
+                    parse_word(word, analyze).map(|query| Query::Boost(Box::new(query), boost))
+                }
+                None => parse_word(word, analyze),
+            },
+        };
+        if let Some(clause) = clause {
+            clauses.push((next_occur, clause));
+            after_clause = true;
+        }
+        next_occur = Occur::Should;
+    }
+    if clauses.is_empty() {
+        None
+    } else {
+        Some(Query::Group(clauses))
+    }
+}
+
+/// A clause made by `query` from each of the terms a word analyzes to, all of
+/// which must match.
+fn all_of(terms: Vec<String>, query: impl Fn(String) -> Query) -> Option<Query> {
+    let mut terms: Vec<Query> = terms.into_iter().map(query).collect();
+    match terms.len() {
+        0 => None,
+        1 => terms.pop(),
+        _ => Some(Query::Group(
+            terms.into_iter().map(|term| (Occur::Must, term)).collect(),
+        )),
+    }
+}
+
+fn parse_word(word: &str, analyze: &dyn Fn(&str) -> Vec<String>) -> Option<Query> {
+    if let Some((field, word)) = field_prefix(word) {
+        return all_of(analyze(word), |term| {
+            Query::FieldTerm(field.to_lowercase(), term)
+        });
+    }
+    if let Some((word, distance)) = fuzzy_suffix(word) {
+        return all_of(analyze(word), |term| Query::Fuzzy(term, distance));
+    }
+    if let Some(word) = prefix_word(word) {
+        return all_of(analyze(word), Query::Prefix);
+    }
+    all_of(analyze(word), Query::Term)
+}
@@ -0,0 +1,141 @@
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+};
+
+const PROMPT: &str = "Enter a search query: ";
+
+/// A `:` command typed at the prompt instead of a query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplCommand {
+    Limit(usize),
+    Explain(bool),
+    Snippets(bool),
+    Stats,
+    /// Documents like the one at this rank of the last results shown.
+    Similar(usize),
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = ":limit <n>         show at most n results\n\
+                        :explain on|off    break down each score\n\
+                        :snippets on|off   show a snippet of each result\n\
+                        :stats             describe the index\n\
+                        :similar <n>       show documents like result n\n\
+                        :quit              leave (as does Ctrl-D)";
+
+fn switch(value: Option<&str>) -> Option<bool> {
+    match value? {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl ReplCommand {
+    /// Parses a line starting with `:`, or returns `None` for a query. An
+    /// unknown or malformed command is an error.
+    pub fn parse(line: &str) -> Option<Result<ReplCommand, String>> {
+        let command = line.strip_prefix(':')?;
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let argument = words.next();
+        let parsed = match name {
+            "limit" => argument
+                .and_then(|limit| limit.parse().ok())
+                .map(ReplCommand::Limit),
+            "explain" => switch(argument).map(ReplCommand::Explain),
+            "snippets" => switch(argument).map(ReplCommand::Snippets),
+            "stats" => Some(ReplCommand::Stats),
+            "similar" => argument
+                .and_then(|rank| rank.parse().ok())
+                .filter(|&rank| rank > 0)
+                .map(ReplCommand::Similar),
+            "help" => Some(ReplCommand::Help),
+            "quit" | "q" | "exit" => Some(ReplCommand::Quit),
+            _ => None,
+        };
+        Some(parsed.ok_or_else(|| format!("Unknown command: {} (try :help)", line)))
+    }
+}
+
+/// Reads queries from stdin: with line editing and a history kept in
+/// `~/.fulltext_history` when it is a terminal, or plain lines otherwise so
+/// that queries can be piped in.
+pub struct LineReader {
+    editor: Option<DefaultEditor>,
+    history: Option<PathBuf>,
+    prompt: bool,
+}
+
+impl LineReader {
+    /// `prompt` is whether to prompt for each query.
+    pub fn new(prompt: bool) -> LineReader {
+        let editor = io::stdin()
+            .is_terminal()
+            .then(|| DefaultEditor::new().ok())
+            .flatten();
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(".fulltext_history"));
+        let mut reader = LineReader {
+            editor,
+            history,
+            prompt,
+        };
+        if let (Some(editor), Some(history)) = (&mut reader.editor, &reader.history) {
+            // There is no history yet on the first run.
+            let _ = editor.load_history(history);
+        }
+        reader
+    }
+
+    /// The next line, trimmed, or `None` at the end of input.
+    pub fn read(&mut self) -> Option<String> {
+        let prompt = if self.prompt { PROMPT } else { "" };
+        let Some(editor) = &mut self.editor else {
+            if self.prompt {
+                print!("{}", prompt);
+                io::stdout().flush().unwrap();
+            }
+            let mut input = String::new();
+            return match io::stdin().read_line(&mut input) {
+                Ok(0) => None,
+                Ok(_) => Some(input.trim().to_string()),
+                Err(err) => {
+                    eprintln!("Could not read query: {}", err);
+                    None
+                }
+            };
+        };
+        loop {
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    let line = line.trim().to_string();
+                    if !line.is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    return Some(line);
+                }
+                // Ctrl-C discards the line being typed rather than quitting.
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return None,
+                Err(err) => {
+                    eprintln!("Could not read query: {}", err);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LineReader {
+    fn drop(&mut self) {
+        if let (Some(editor), Some(history)) = (&mut self.editor, &self.history) {
+            if let Err(err) = editor.save_history(history) {
+                eprintln!("Could not save history to {}: {}", history.display(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Re-ranking of the best BM25 hits by another model, e.g. the similarity of
+//! document and query embeddings, without the index knowing about it. A
+//! [`Reranker`] scores the candidates it is given, and a [`Fusion`] combines
+//! those scores with the BM25 ranking.
+use crate::{Bm25Error, DocId};
+
+/// How many of the best hits are re-ranked unless told otherwise.
+pub const DEFAULT_DEPTH: usize = 100;
+
+/// The `k` of reciprocal rank fusion unless told otherwise, as in the paper
+/// by Cormack et al.
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
+/// A hit to be re-ranked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// Index of the document.
+    pub doc: usize,
+    /// Its stable id, see [`crate::BM25Index::doc_id`].
+    pub id: DocId,
+    pub text: String,
+    /// Its BM25 score.
+    pub score: f32,
+}
+
+/// Scores candidates for a query, a higher score meaning more relevant.
+pub trait Reranker: Send + Sync {
+    /// One score per candidate, in the order given.
+    fn score(&self, query: &str, candidates: &[Candidate]) -> Result<Vec<f32>, Bm25Error>;
+}
+
+/// How re-ranker scores are combined with the BM25 ranking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// The re-ranker's scores are used as they are.
+    Replace,
+    /// Each ranking contributes `1 / (k + rank)`, so neither's scale matters.
+    ReciprocalRank { k: f32 },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::ReciprocalRank { k: DEFAULT_RRF_K }
+    }
+}
+
+impl Fusion {
+    /// Parses `replace`, `rrf` or `rrf:<k>`.
+    pub fn parse(value: &str) -> Option<Fusion> {
+        match value.split_once(':') {
+            None if value == "replace" => Some(Fusion::Replace),
+            None if value == "rrf" => Some(Fusion::default()),
+            Some(("rrf", k)) => {
+                let k: f32 = k.parse().ok()?;
+                (k >= 0.0).then_some(Fusion::ReciprocalRank { k })
+            }
+            _ => None,
+        }
+    }
+
+    /// Combines `hits`, in BM25 order, with one re-ranker score each, and
+    /// sorts them by the result. Ties keep the BM25 order.
+    pub fn fuse(&self, hits: &[(i32, f32)], scores: &[f32]) -> Vec<(i32, f32)> {
+        let mut fused: Vec<(i32, f32)> = match self {
+            Fusion::Replace => hits
+                .iter()
+                .zip(scores)
+                .map(|(&(doc, _), &score)| (doc, score))
+                .collect(),
+            Fusion::ReciprocalRank { k } => {
+                let mut by_score: Vec<usize> = (0..hits.len().min(scores.len())).collect();
+                by_score.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+                let mut fused: Vec<(i32, f32)> = hits[..by_score.len()]
+                    .iter()
+                    .enumerate()
+                    .map(|(rank, &(doc, _))| (doc, 1.0 / (k + rank as f32 + 1.0)))
+                    .collect();
+                for (rank, hit) in by_score.into_iter().enumerate() {
+                    fused[hit].1 += 1.0 / (k + rank as f32 + 1.0);
+                }
+                fused
+            }
+        };
+        // A stable sort, so equal scores stay in BM25 order.
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+        fused
+    }
+}
@@ -0,0 +1,74 @@
+use fulltext::{
+    rerank::{Candidate, Reranker},
+    Bm25Error,
+};
+use serde::Serialize;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Re-ranks with an external program, run through the shell for each query.
+/// It is given `{"query": ..., "documents": [{"id": ..., "text": ...,
+/// "score": ...}, ...]}` on stdin and prints a JSON array with one score per
+/// document, e.g. the cosine similarity of their embeddings.
+pub struct CommandReranker {
+    pub command: String,
+}
+
+#[derive(Serialize)]
+struct RerankInput<'a> {
+    query: &'a str,
+    documents: Vec<RerankDocument<'a>>,
+}
+
+#[derive(Serialize)]
+struct RerankDocument<'a> {
+    id: String,
+    text: &'a str,
+    score: f32,
+}
+
+fn failed(message: String) -> Bm25Error {
+    Bm25Error::Rerank { message }
+}
+
+impl Reranker for CommandReranker {
+    fn score(&self, query: &str, candidates: &[Candidate]) -> Result<Vec<f32>, Bm25Error> {
+        let input = RerankInput {
+            query,
+            documents: candidates
+                .iter()
+                .map(|candidate| RerankDocument {
+                    id: candidate.id.to_string(),
+                    text: &candidate.text,
+                    score: candidate.score,
+                })
+                .collect(),
+        };
+        let input = serde_json::to_vec(&input).unwrap();
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| failed(format!("could not run {}: {}", self.command, err)))?;
+        // Written from another thread so a program that prints before it has
+        // read everything cannot deadlock with us.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child
+            .wait_with_output()
+            .map_err(|err| failed(format!("{} failed: {}", self.command, err)))?;
+        let _ = writer.join();
+        if !output.status.success() {
+            return Err(failed(format!(
+                "{} exited with {}",
+                self.command, output.status
+            )));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|err| failed(format!("{} printed no scores: {}", self.command, err)))
+    }
+}
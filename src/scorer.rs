@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+
+/// What a [`Scorer`] knows about one query term (or phrase) in one document.
+/// `k1`, `b` and `idf_mode` are the index's settings; scorers that do not
+/// saturate term frequency, normalize length or use the BM25 IDF ignore them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermStats {
+    pub tf: i32,
+    pub doc_length: i32,
+    pub avg_doc_length: f32,
+    pub doc_freq: i32,
+    pub doc_count: i32,
+    pub collection_freq: i32,
+    pub collection_length: i32,
+    pub k1: f32,
+    pub b: f32,
+    pub idf_mode: IdfMode,
+}
+
+impl TermStats {
+    /// `1 - b + b * doc_length / avg_doc_length`, the BM25 length normalization.
+    pub fn length_norm(&self) -> f32 {
+        1.0 - self.b + self.b * (self.doc_length as f32 / self.avg_doc_length)
+    }
+}
+
+/// Scores one query term in one document; a document's score is the sum over
+/// the query's terms and phrases.
+pub trait Scorer: Send + Sync {
+    fn score(&self, stats: &TermStats) -> f32;
+
+    /// Adds `weight` times the score of one term in each document of a batch
+    /// to `scores`, given the term's frequency in each, each one's length and,
+    /// in `norms`, each one's `k1 * length_norm()`. `stats` holds what the
+    /// documents share; its `tf` and `doc_length` are ignored. By default
+    /// each document is scored on its own with [`Scorer::score`].
+    fn score_batch(
+        &self,
+        stats: &TermStats,
+        tfs: &[i32],
+        doc_lengths: &[i32],
+        norms: &[f32],
+        weight: f32,
+        scores: &mut [f32],
+    ) {
+        let _ = norms;
+        for ((score, &tf), &doc_length) in scores.iter_mut().zip(tfs).zip(doc_lengths) {
+            *score += weight
+                * self.score(&TermStats {
+                    tf,
+                    doc_length,
+                    ..*stats
+                });
+        }
+    }
+
+    /// The IDF this scorer weights the term with, if it has one. Only used to
+    /// explain scores.
+    fn idf(&self, _stats: &TermStats) -> Option<f32> {
+        None
+    }
+}
+
+/// The classic Robertson/Spärck Jones IDF. It is negative for terms in more
+/// than half of the documents.
+pub fn bm25_idf(doc_freq: i32, doc_count: i32) -> f32 {
+    match doc_freq {
+        0 => 0.0,
+        df => ((doc_count as f32 - df as f32 + 0.5) / (df as f32 + 0.5)).ln(),
+    }
+}
+
+/// How BM25 treats terms in more than half of the documents, whose classic
+/// IDF is negative, so that matching them lowers a document's score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum IdfMode {
+    /// The classic IDF, negative or not.
+    #[default]
+    Classic,
+    /// The classic IDF, but never below zero, so such terms do not count.
+    Clamp,
+    /// The classic IDF, but never below the given weight, so such terms
+    /// still count a little.
+    Floor(f32),
+    /// Lucene's `ln(1 + (N - df + 0.5) / (df + 0.5))`, which is always
+    /// positive and falls smoothly as a term gets more common.
+    Lucene,
+}
+
+impl IdfMode {
+    /// Parses `classic`, `clamp`, `floor[:WEIGHT]` (0.01 by default) or
+    /// `lucene`.
+    pub fn parse(value: &str) -> Option<IdfMode> {
+        match value.split_once(':') {
+            Some(("floor", weight)) => Some(IdfMode::Floor(weight.parse().ok()?)),
+            Some(_) => None,
+            None => match value {
+                "classic" => Some(IdfMode::Classic),
+                "clamp" => Some(IdfMode::Clamp),
+                "floor" => Some(IdfMode::Floor(0.01)),
+                "lucene" => Some(IdfMode::Lucene),
+                _ => None,
+            },
+        }
+    }
+
+    /// The IDF of a term in `doc_freq` of `doc_count` documents.
+    pub fn idf(&self, doc_freq: i32, doc_count: i32) -> f32 {
+        if doc_freq == 0 {
+            return 0.0;
+        }
+        match *self {
+            IdfMode::Classic => bm25_idf(doc_freq, doc_count),
+            IdfMode::Clamp => bm25_idf(doc_freq, doc_count).max(0.0),
+            IdfMode::Floor(weight) => bm25_idf(doc_freq, doc_count).max(weight),
+            IdfMode::Lucene => {
+                let (df, n) = (doc_freq as f32, doc_count as f32);
+                (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+            }
+        }
+    }
+}
+
+/// BM25, with the IDF the index's [`IdfMode`] says.
+pub struct Bm25;
+
+impl Scorer for Bm25 {
+    fn score(&self, stats: &TermStats) -> f32 {
+        let tf = stats.tf as f32;
+        let numerator = tf * (stats.k1 + 1.0);
+        let denominator = tf + stats.k1 * stats.length_norm();
+        self.idf(stats).unwrap() * (numerator / denominator)
+    }
+
+    /// The same arithmetic as [`Bm25::score`], as one loop over plain
+    /// arrays with nothing to look up, which the compiler vectorizes.
+    fn score_batch(
+        &self,
+        stats: &TermStats,
+        tfs: &[i32],
+        _doc_lengths: &[i32],
+        norms: &[f32],
+        weight: f32,
+        scores: &mut [f32],
+    ) {
+        let idf = self.idf(stats).unwrap();
+        let saturation = stats.k1 + 1.0;
+        for ((score, &tf), &norm) in scores.iter_mut().zip(tfs).zip(norms) {
+            let tf = tf as f32;
+            *score += weight * (idf * (tf * saturation / (tf + norm)));
+        }
+    }
+
+    fn idf(&self, stats: &TermStats) -> Option<f32> {
+        Some(stats.idf_mode.idf(stats.doc_freq, stats.doc_count))
+    }
+}
+
+/// BM25+ (Lv & Zhai, 2011): adds `delta` to every matching term so that very
+/// long documents are not scored below ones that lack the term, and uses an
+/// IDF that is never negative.
+pub struct Bm25Plus {
+    pub delta: f32,
+}
+
+impl Scorer for Bm25Plus {
+    fn score(&self, stats: &TermStats) -> f32 {
+        if stats.tf == 0 {
+            return 0.0;
+        }
+        let tf = stats.tf as f32;
+        let idf = self.idf(stats).unwrap();
+        idf * (tf * (stats.k1 + 1.0) / (tf + stats.k1 * stats.length_norm()) + self.delta)
+    }
+
+    fn idf(&self, stats: &TermStats) -> Option<f32> {
+        Some(((stats.doc_count as f32 + 1.0) / stats.doc_freq as f32).ln())
+    }
+}
+
+/// BM25L (Lv & Zhai, 2011): shifts the length-normalized term frequency by
+/// `delta` before saturation, which favours long documents less harshly.
+pub struct Bm25L {
+    pub delta: f32,
+}
+
+impl Scorer for Bm25L {
+    fn score(&self, stats: &TermStats) -> f32 {
+        if stats.tf == 0 {
+            return 0.0;
+        }
+        let c = stats.tf as f32 / stats.length_norm() + self.delta;
+        let idf = self.idf(stats).unwrap();
+        idf * (stats.k1 + 1.0) * c / (stats.k1 + c)
+    }
+
+    fn idf(&self, stats: &TermStats) -> Option<f32> {
+        Some(((stats.doc_count as f32 + 1.0) / (stats.doc_freq as f32 + 0.5)).ln())
+    }
+}
+
+/// Raw term frequency times `ln(N / df)`, with no length normalization.
+pub struct TfIdf;
+
+impl Scorer for TfIdf {
+    fn score(&self, stats: &TermStats) -> f32 {
+        if stats.tf == 0 {
+            return 0.0;
+        }
+        stats.tf as f32 * self.idf(stats).unwrap()
+    }
+
+    fn idf(&self, stats: &TermStats) -> Option<f32> {
+        Some((stats.doc_count as f32 / stats.doc_freq as f32).ln())
+    }
+}
+
+/// Query likelihood with Dirichlet prior smoothing (Zhai & Lafferty, 2001),
+/// in its rank-equivalent form. Scores are log probabilities, so they are
+/// usually negative, and every query term contributes to every document.
+pub struct Dirichlet {
+    pub mu: f32,
+}
+
+impl Scorer for Dirichlet {
+    fn score(&self, stats: &TermStats) -> f32 {
+        if stats.collection_freq == 0 {
+            return 0.0;
+        }
+        let p = stats.collection_freq as f32 / stats.collection_length as f32;
+        (1.0 + stats.tf as f32 / (self.mu * p)).ln()
+            + (self.mu / (stats.doc_length as f32 + self.mu)).ln()
+    }
+}
+
+/// Looks up a scorer by name, with an optional parameter after a colon:
+/// `bm25`, `bm25+[:delta]`, `bm25l[:delta]`, `tfidf` or `dirichlet[:mu]`.
+/// `None` for an unknown name, a negative delta or a mu that is not positive.
+pub fn by_name(value: &str) -> Option<Box<dyn Scorer>> {
+    let (name, parameter) = match value.split_once(':') {
+        Some((name, parameter)) => (name, Some(parameter.parse::<f32>().ok()?)),
+        None => (value, None),
+    };
+    let valid = match name {
+        "dirichlet" => parameter.is_none_or(|mu| mu > 0.0),
+        _ => parameter.is_none_or(|delta| delta >= 0.0),
+    };
+    if !valid {
+        return None;
+    }
+    match (name, parameter) {
+        ("bm25", None) => Some(Box::new(Bm25)),
+        ("bm25+", delta) => Some(Box::new(Bm25Plus {
+            delta: delta.unwrap_or(1.0),
+        })),
+        ("bm25l", delta) => Some(Box::new(Bm25L {
+            delta: delta.unwrap_or(0.5),
+        })),
+        ("tfidf", None) => Some(Box::new(TfIdf)),
+        ("dirichlet", mu) => Some(Box::new(Dirichlet {
+            mu: mu.unwrap_or(2000.0),
+        })),
+        _ => None,
+    }
+}
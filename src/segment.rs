@@ -0,0 +1,369 @@
+use crate::{
+    analyzer::AnalyzerConfig,
+    postings::Posting,
+    scorer::{self, Scorer, TermStats},
+    BM25Index, DocId, Metadata, TermPositions,
+};
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    thread::{self, JoinHandle},
+};
+
+/// When immutable segments are merged: while there are at least
+/// `merge_factor` of them, the `merge_factor` smallest are merged into one in
+/// the background.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MergePolicy {
+    pub merge_factor: usize,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy { merge_factor: 10 }
+    }
+}
+
+type Segments = Arc<RwLock<Vec<Arc<BM25Index>>>>;
+
+/// An index made of immutable segments, as in Lucene. New documents go into an
+/// in-memory buffer that is flushed into a segment once it holds
+/// `flush_threshold` documents, searches fan out across all segments with
+/// statistics summed over them, and small segments are merged on a background
+/// thread according to the [`MergePolicy`].
+///
+/// Removing a document from a flushed segment only marks it deleted; it keeps
+/// counting towards document frequencies until its segment is merged.
+/// Segments hold document bodies and metadata, but no fields or titles.
+pub struct SegmentedIndex {
+    analysis: AnalyzerConfig,
+    buffer: BM25Index,
+    flush_threshold: usize,
+    segments: Segments,
+    deleted: Arc<RwLock<HashSet<DocId>>>,
+    merge_policy: MergePolicy,
+    merger: Option<JoinHandle<()>>,
+    scorer: Box<dyn Scorer>,
+    k1: f32,
+    b: f32,
+}
+
+impl SegmentedIndex {
+    pub fn new(analysis: AnalyzerConfig) -> SegmentedIndex {
+        let buffer = BM25Index::with_analyzer(Box::new(analysis.build()));
+        SegmentedIndex {
+            k1: buffer.k1(),
+            b: buffer.b(),
+            analysis,
+            buffer,
+            flush_threshold: 1000,
+            segments: Arc::new(RwLock::new(vec![])),
+            deleted: Arc::new(RwLock::new(HashSet::new())),
+            merge_policy: MergePolicy::default(),
+            merger: None,
+            scorer: Box::new(scorer::Bm25),
+        }
+    }
+
+    /// How many documents the in-memory buffer holds before it is flushed.
+    pub fn set_flush_threshold(&mut self, documents: usize) {
+        self.flush_threshold = documents.max(1);
+    }
+
+    pub fn set_merge_policy(&mut self, merge_policy: MergePolicy) {
+        self.merge_policy = merge_policy;
+    }
+
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = scorer;
+    }
+
+    pub fn set_parameters(&mut self, k1: f32, b: f32) {
+        self.k1 = k1;
+        self.b = b;
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.buffer.analyze(text)
+    }
+
+    /// Number of flushed segments, not counting the buffer.
+    pub fn segment_count(&self) -> usize {
+        self.segments.read().unwrap().len()
+    }
+
+    /// Number of live documents.
+    pub fn doc_count(&self) -> usize {
+        let segments = self.segments.read().unwrap();
+        let flushed: usize = segments.iter().map(|segment| segment.doc_count()).sum();
+        flushed + self.buffer.doc_count() - self.deleted.read().unwrap().len()
+    }
+
+    /// Analyzes and adds a document, flushing the buffer if it is full.
+    pub fn add_text(&mut self, text: &str, metadata: Metadata) -> DocId {
+        let doc = self.buffer.add_text(text);
+        self.buffer.set_metadata(doc, metadata);
+        let id = self.buffer.doc_id(doc);
+        if self.buffer.doc_count() >= self.flush_threshold {
+            self.flush();
+        }
+        id
+    }
+
+    /// Turns the buffered documents into a new segment and starts a merge if
+    /// the policy calls for one.
+    pub fn flush(&mut self) {
+        if self.buffer.slot_count() == 0 {
+            return;
+        }
+        let mut buffer = BM25Index::with_analyzer(Box::new(self.analysis.build()));
+        buffer.next_doc_id = self.buffer.next_doc_id;
+        let segment = std::mem::replace(&mut self.buffer, buffer);
+        if segment.doc_count() > 0 {
+            self.segments.write().unwrap().push(Arc::new(segment));
+        }
+        self.maybe_merge();
+    }
+
+    fn maybe_merge(&mut self) {
+        if self
+            .merger
+            .as_ref()
+            .is_some_and(|merger| !merger.is_finished())
+        {
+            return;
+        }
+        self.wait_for_merges();
+        let merge_factor = self.merge_policy.merge_factor.max(2);
+        if self.segment_count() < merge_factor {
+            return;
+        }
+        let segments = Arc::clone(&self.segments);
+        let deleted = Arc::clone(&self.deleted);
+        let analysis = self.analysis.clone();
+        self.merger = Some(thread::spawn(move || loop {
+            let mut chosen = segments.read().unwrap().clone();
+            if chosen.len() < merge_factor {
+                break;
+            }
+            chosen.sort_by_key(|segment| segment.slot_count());
+            chosen.truncate(merge_factor);
+            let snapshot = deleted.read().unwrap().clone();
+            let merged = BM25Index::merged(&chosen, &snapshot, &analysis);
+            let mut segments = segments.write().unwrap();
+            segments.retain(|segment| !chosen.iter().any(|other| Arc::ptr_eq(segment, other)));
+            if merged.doc_count() > 0 {
+                segments.push(Arc::new(merged));
+            }
+            // Documents deleted before the merge are gone now; later deletions
+            // still apply to the merged segment.
+            deleted.write().unwrap().retain(|id| {
+                !snapshot.contains(id)
+                    || !chosen
+                        .iter()
+                        .any(|segment| segment.doc_index(*id).is_some())
+            });
+        }));
+    }
+
+    /// Blocks until a running background merge has finished.
+    pub fn wait_for_merges(&mut self) {
+        if let Some(merger) = self.merger.take() {
+            merger.join().expect("segment merge panicked");
+        }
+    }
+
+    /// Removes a document, returning whether it was live.
+    pub fn remove(&mut self, id: DocId) -> bool {
+        if let Some(doc) = self.buffer.doc_index(id) {
+            return self.buffer.remove_document(doc);
+        }
+        let flushed = self
+            .segments
+            .read()
+            .unwrap()
+            .iter()
+            .any(|segment| segment.doc_index(id).is_some());
+        flushed && self.deleted.write().unwrap().insert(id)
+    }
+
+    pub fn metadata(&self, id: DocId) -> Option<Metadata> {
+        if self.deleted.read().unwrap().contains(&id) {
+            return None;
+        }
+        if let Some(doc) = self.buffer.doc_index(id) {
+            return Some(self.buffer.metadata(doc).clone());
+        }
+        self.segments.read().unwrap().iter().find_map(|segment| {
+            let doc = segment.doc_index(id)?;
+            Some(segment.metadata(doc).clone())
+        })
+    }
+
+    /// The `k` best documents for `text` as a bag-of-words query. Scores are
+    /// those a single [`BM25Index`] over the same documents would give.
+    pub fn search(&self, text: &str, k: usize) -> Vec<(DocId, f32)> {
+        let terms = self.analyze(text);
+        let segments = self.segments.read().unwrap();
+        let segments: Vec<&BM25Index> = segments
+            .iter()
+            .map(|segment| segment.as_ref())
+            .chain([&self.buffer])
+            .collect();
+        let stats = GlobalStats::new(&segments, &terms);
+        let deleted = self.deleted.read().unwrap();
+        let mut hits: Vec<(DocId, f32)> = segments
+            .par_iter()
+            .flat_map_iter(|segment| {
+                let hits = stats.score(segment, &terms, self.scorer.as_ref(), (self.k1, self.b));
+                hits.into_iter().filter(|(id, _)| !deleted.contains(id))
+            })
+            .collect();
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+}
+
+/// Best first, ties broken by id.
+pub(crate) fn sort_hits(hits: &mut [(DocId, f32)]) {
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+}
+
+/// Collection statistics of the query terms summed over several indexes, so
+/// that each index scores its documents as one index over all of them would.
+pub(crate) struct GlobalStats {
+    doc_count: i32,
+    total_doc_length: i32,
+    avg_doc_length: i32,
+    /// Document and collection frequency of each query term.
+    terms: Vec<(i32, i32)>,
+}
+
+impl GlobalStats {
+    pub(crate) fn new(indexes: &[&BM25Index], terms: &[String]) -> GlobalStats {
+        let doc_count: i32 = indexes.iter().map(|index| index.doc_count).sum();
+        let total_doc_length: i32 = indexes.iter().map(|index| index.total_doc_length).sum();
+        let avg_doc_length = if doc_count > 0 {
+            total_doc_length / doc_count
+        } else {
+            0
+        };
+        let terms = terms
+            .iter()
+            .map(|term| {
+                indexes
+                    .iter()
+                    .filter_map(|index| Some(index.live_freqs(index.posting_list(term)?)))
+                    .fold((0, 0), |(docs, total), (doc_freq, collection_freq)| {
+                        (docs + doc_freq, total + collection_freq)
+                    })
+            })
+            .collect();
+        GlobalStats {
+            doc_count,
+            total_doc_length,
+            avg_doc_length,
+            terms,
+        }
+    }
+
+    /// The id and score of every document of `index` that contains one of
+    /// `terms`, in no particular order.
+    pub(crate) fn score(
+        &self,
+        index: &BM25Index,
+        terms: &[String],
+        scorer: &dyn Scorer,
+        (k1, b): (f32, f32),
+    ) -> Vec<(DocId, f32)> {
+        let mut frequencies: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (position, term) in terms.iter().enumerate() {
+            for (doc, tf) in index
+                .posting_list(term)
+                .into_iter()
+                .flat_map(|postings| postings.as_slice().frequencies())
+            {
+                frequencies
+                    .entry(doc)
+                    .or_insert_with(|| vec![0; terms.len()])[position] = tf;
+            }
+        }
+        frequencies
+            .into_iter()
+            .map(|(doc, tfs)| {
+                let score = tfs
+                    .iter()
+                    .zip(&self.terms)
+                    .map(|(&tf, &(doc_freq, collection_freq))| {
+                        scorer.score(&TermStats {
+                            tf,
+                            doc_length: index.doc_lengths[doc as usize],
+                            avg_doc_length: self.avg_doc_length.max(1) as f32,
+                            doc_freq,
+                            doc_count: self.doc_count,
+                            collection_freq,
+                            collection_length: self.total_doc_length,
+                            k1,
+                            b,
+                            idf_mode: index.idf_mode,
+                        })
+                    })
+                    .sum();
+                (index.doc_id(doc as usize), score)
+            })
+            .collect()
+    }
+}
+
+impl Drop for SegmentedIndex {
+    fn drop(&mut self) {
+        self.wait_for_merges();
+    }
+}
+
+impl BM25Index {
+    /// One index with the live documents of `segments` that are not in
+    /// `deleted`, in id order and keeping their ids, positions and metadata.
+    fn merged(
+        segments: &[Arc<BM25Index>],
+        deleted: &HashSet<DocId>,
+        analysis: &AnalyzerConfig,
+    ) -> BM25Index {
+        let mut documents = vec![];
+        for segment in segments {
+            let mut counts: Vec<HashMap<String, i32>> = vec![HashMap::new(); segment.slot_count()];
+            let mut positions: Vec<TermPositions> = vec![HashMap::new(); segment.slot_count()];
+            for (term_id, postings) in segment.postings.iter().enumerate() {
+                let term = segment.terms.name(term_id as u32);
+                for Posting {
+                    doc,
+                    tf,
+                    positions: at,
+                } in postings.iter()
+                {
+                    counts[doc as usize].insert(term.to_string(), tf);
+                    positions[doc as usize].insert(term.to_string(), at);
+                }
+            }
+            for (doc, (counts, positions)) in counts.into_iter().zip(positions).enumerate() {
+                let id = segment.doc_id(doc);
+                if segment.is_live(doc) && !deleted.contains(&id) {
+                    let length = segment.doc_lengths[doc];
+                    let metadata = segment.metadata(doc).clone();
+                    documents.push((id, counts, positions, length, metadata));
+                }
+            }
+        }
+        documents.sort_by_key(|(id, ..)| *id);
+        let mut merged = BM25Index::with_analyzer(Box::new(analysis.build()));
+        for (id, counts, positions, length, metadata) in documents {
+            merged.next_doc_id = id;
+            merged.insert_document(counts, positions, length);
+            let doc = merged.slot_count() - 1;
+            merged.set_metadata(doc, metadata);
+        }
+        merged
+    }
+}
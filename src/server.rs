@@ -0,0 +1,351 @@
+use crate::{metrics::Metrics, StoredIndex};
+use fulltext::{deadline::Deadline, facet::Facet, DocId, Filter, Metadata, SearchRequest};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::Cursor,
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    query: &'a str,
+    results: Vec<SearchResult<'a>>,
+    /// Passed back as `cursor` to get the next page, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    /// Whether the search ran out of time, so the results are the best of
+    /// the documents it got to.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    timed_out: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    facets: Vec<Facet>,
+}
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    rank: usize,
+    id: DocId,
+    path: &'a str,
+    score: f32,
+    metadata: &'a Metadata,
+}
+
+#[derive(Serialize)]
+struct SimilarResponse<'a> {
+    id: DocId,
+    results: Vec<SearchResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct SuggestResponse<'a> {
+    prefix: &'a str,
+    suggestions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NewDocument {
+    path: Option<String>,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct AddedDocument {
+    id: DocId,
+    path: String,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+fn json<T: Serialize>(status: u16, body: &T) -> HttpResponse {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    Response::from_string(serde_json::to_string(body).unwrap())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    json(
+        status,
+        &ErrorResponse {
+            error: message.to_string(),
+        },
+    )
+}
+
+/// Serves `GET /search?q=...&limit=10&filter=ext%3Dmd&cursor=...`, `GET /suggest?q=...&limit=10`,
+/// `GET /similar?id=...&limit=10`, `POST /documents` and `GET /metrics` until the process is
+/// stopped. Requests are handled one at a time.
+///
+/// Search results are paged: a response with more results after it has a `next_cursor`
+/// to pass as `cursor` for the next page. A page can also be asked for by `offset`. Results
+/// scoring below `min_score`, or zero or below without it, are left out.
+///
+/// A search stops after `timeout`, or after `timeout_ms` milliseconds if a request asks for
+/// less, and returns the results it has with `timed_out` set.
+///
+/// `facets=ext,dir` counts the values of those metadata keys over every matching document,
+/// returned as `facets` with the page of results.
+///
+/// The metrics are in the Prometheus text format: search latencies, rate and timeouts,
+/// responses by status, and the size of the index and hit rate of its query cache.
+pub fn serve(
+    stored: &mut StoredIndex,
+    addr: &str,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let server = Server::http(addr)?;
+    let mut metrics = Metrics::new();
+    println!("Listening on http://{}", addr);
+    for mut request in server.incoming_requests() {
+        let response = handle(stored, &mut metrics, timeout, &mut request);
+        metrics.record_response(response.status_code().0);
+        if let Err(err) = request.respond(response) {
+            eprintln!("Could not send response: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    stored: &mut StoredIndex,
+    metrics: &mut Metrics,
+    timeout: Option<Duration>,
+    request: &mut Request,
+) -> HttpResponse {
+    let url = request.url().to_string();
+    let (path, query_string) = url.split_once('?').unwrap_or((&url, ""));
+    match (request.method(), path) {
+        (Method::Get, "/search") => {
+            let started = Instant::now();
+            let response = search(stored, metrics, timeout, query_string);
+            if response.status_code().0 == 200 {
+                metrics.record_query(started.elapsed());
+            }
+            response
+        }
+        (Method::Get, "/suggest") => suggest(stored, query_string),
+        (Method::Get, "/similar") => similar(stored, query_string),
+        (Method::Post, "/documents") => add_document(stored, request),
+        (Method::Get, "/metrics") => {
+            let header =
+                Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                    .unwrap();
+            Response::from_string(metrics.render(&stored.index)).with_header(header)
+        }
+        (_, "/search")
+        | (_, "/suggest")
+        | (_, "/similar")
+        | (_, "/documents")
+        | (_, "/metrics") => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    }
+}
+
+/// The `q` and `limit` parameters shared by the GET endpoints.
+fn query_and_limit(query_string: &str) -> Result<(String, usize), HttpResponse> {
+    let mut query = None;
+    let mut limit = 10;
+    for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+        match key.as_ref() {
+            "q" => query = Some(value.into_owned()),
+            "limit" => match value.parse() {
+                Ok(value) => limit = value,
+                Err(_) => return Err(error(400, "limit must be a non-negative integer")),
+            },
+            _ => {}
+        }
+    }
+    match query {
+        Some(query) => Ok((query, limit)),
+        None => Err(error(400, "missing q parameter")),
+    }
+}
+
+fn suggest(stored: &StoredIndex, query_string: &str) -> HttpResponse {
+    let (prefix, limit) = match query_and_limit(query_string) {
+        Ok(params) => params,
+        Err(response) => return response,
+    };
+    let term = stored
+        .index
+        .analyze(&prefix)
+        .pop()
+        .unwrap_or_else(|| prefix.to_lowercase());
+    json(
+        200,
+        &SuggestResponse {
+            prefix: &prefix,
+            suggestions: stored.index.suggest(&term, limit),
+        },
+    )
+}
+
+/// The documents most like the one with the `id` parameter.
+fn similar(stored: &StoredIndex, query_string: &str) -> HttpResponse {
+    let mut id = None;
+    let mut limit = 10;
+    for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+        match key.as_ref() {
+            "id" => match value.parse() {
+                Ok(value) => id = Some(value),
+                Err(_) => return error(400, "id must be a document id"),
+            },
+            "limit" => match value.parse() {
+                Ok(value) => limit = value,
+                Err(_) => return error(400, "limit must be a non-negative integer"),
+            },
+            _ => {}
+        }
+    }
+    let Some(id) = id else {
+        return error(400, "missing id parameter");
+    };
+    match stored.index.more_like_this(id, limit) {
+        Some(hits) => json(
+            200,
+            &SimilarResponse {
+                id,
+                results: search_results(stored, &hits, 0),
+            },
+        ),
+        None => error(404, "no such document"),
+    }
+}
+
+/// Hits as returned to clients, ranked from `offset + 1`.
+fn search_results<'a>(
+    stored: &'a StoredIndex,
+    hits: &[(i32, f32)],
+    offset: usize,
+) -> Vec<SearchResult<'a>> {
+    hits.iter()
+        .enumerate()
+        .map(|(rank, (index, score))| SearchResult {
+            rank: offset + rank + 1,
+            id: stored.index.doc_id(*index as usize),
+            path: stored.name(*index as usize),
+            score: *score,
+            metadata: stored.index.metadata(*index as usize),
+        })
+        .collect()
+}
+
+/// A position in the results of a query: the offset of the next result and the
+/// number of document slots when it was handed out. Documents added since
+/// would shift the ranking, so the cursor is then refused rather than letting
+/// a client skip or repeat results.
+fn cursor(offset: usize, stored: &StoredIndex) -> String {
+    format!("{}-{}", offset, stored.index.slot_count())
+}
+
+fn parse_cursor(value: &str, stored: &StoredIndex) -> Result<usize, HttpResponse> {
+    let (offset, slots) = value
+        .split_once('-')
+        .and_then(|(offset, slots)| Some((offset.parse().ok()?, slots.parse::<usize>().ok()?)))
+        .ok_or_else(|| error(400, "invalid cursor"))?;
+    if slots != stored.index.slot_count() {
+        return Err(error(409, "the index changed since the cursor was issued"));
+    }
+    Ok(offset)
+}
+
+fn search(
+    stored: &StoredIndex,
+    metrics: &mut Metrics,
+    mut timeout: Option<Duration>,
+    query_string: &str,
+) -> HttpResponse {
+    let (query, limit) = match query_and_limit(query_string) {
+        Ok(params) => params,
+        Err(response) => return response,
+    };
+    let mut filters = vec![];
+    let mut offset = 0;
+    let mut min_score = None;
+    let mut facets = vec![];
+    for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+        match key.as_ref() {
+            "filter" => match Filter::parse(&value) {
+                Some(filter) => filters.push(filter),
+                None => return error(400, &format!("invalid filter: {}", value)),
+            },
+            "facets" => facets.extend(
+                value
+                    .split(',')
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string),
+            ),
+            "offset" => match value.parse() {
+                Ok(value) => offset = value,
+                Err(_) => return error(400, "offset must be a non-negative integer"),
+            },
+            "min_score" => match value.parse() {
+                Ok(value) => min_score = Some(value),
+                Err(_) => return error(400, "min_score must be a number"),
+            },
+            "cursor" => match parse_cursor(&value, stored) {
+                Ok(value) => offset = value,
+                Err(response) => return response,
+            },
+            "timeout_ms" => match value.parse() {
+                Ok(value) => {
+                    let asked = Duration::from_millis(value);
+                    timeout = Some(timeout.map_or(asked, |timeout| timeout.min(asked)));
+                }
+                Err(_) => return error(400, "timeout_ms must be a non-negative integer"),
+            },
+            _ => {}
+        }
+    }
+    // One more result than asked for tells whether there is a next page.
+    let request = SearchRequest {
+        query: query.clone(),
+        filters,
+        offset,
+        limit: limit.saturating_add(1),
+        min_score,
+        facets,
+    };
+    let deadline = timeout.map(Deadline::after).unwrap_or_default();
+    let mut ranks = stored.index.search_until(&request, &deadline);
+    if ranks.timed_out {
+        metrics.record_timeout();
+    }
+    let next_cursor = (ranks.hits.len() > limit).then(|| cursor(offset + limit, stored));
+    ranks.hits.truncate(limit);
+    let results = search_results(stored, &ranks.hits, offset);
+    json(
+        200,
+        &SearchResponse {
+            query: &query,
+            results,
+            next_cursor,
+            timed_out: ranks.timed_out,
+            facets: ranks.facets,
+        },
+    )
+}
+
+fn add_document(stored: &mut StoredIndex, request: &mut Request) -> HttpResponse {
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        return error(400, "request body must be UTF-8");
+    }
+    let document: NewDocument = match serde_json::from_str(&body) {
+        Ok(document) => document,
+        Err(err) => return error(400, &format!("invalid document: {}", err)),
+    };
+    let path = document
+        .path
+        .unwrap_or_else(|| format!("document-{}", stored.index.slot_count()));
+    let doc = stored.add_text(&path, &document.text);
+    let id = stored.index.doc_id(doc);
+    json(201, &AddedDocument { id, path })
+}
@@ -0,0 +1,137 @@
+use crate::{
+    analyzer::AnalyzerConfig,
+    scorer::{self, Scorer},
+    segment::{sort_hits, GlobalStats},
+    BM25Index, DocId, Metadata,
+};
+use rayon::prelude::*;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// An index partitioned into a fixed number of shards by a hash of each
+/// document's id, for corpora too large to add to or search in one index
+/// at a useful speed. Documents added together are analyzed on all shards in
+/// parallel, and a search asks every shard for its best hits in parallel and
+/// merges them.
+///
+/// Document and collection frequencies are summed over the shards, so scores
+/// are those a single [`BM25Index`] over the same documents would give,
+/// however the documents happen to be spread.
+pub struct ShardedIndex {
+    shards: Vec<BM25Index>,
+    next_doc_id: DocId,
+    scorer: Box<dyn Scorer>,
+    k1: f32,
+    b: f32,
+}
+
+impl ShardedIndex {
+    /// An empty index of `shards` shards, at least one, that analyze text
+    /// as `analysis` says.
+    pub fn new(analysis: AnalyzerConfig, shards: usize) -> ShardedIndex {
+        let shards: Vec<BM25Index> = (0..shards.max(1))
+            .map(|_| BM25Index::with_analyzer(Box::new(analysis.build())))
+            .collect();
+        ShardedIndex {
+            k1: shards[0].k1(),
+            b: shards[0].b(),
+            shards,
+            next_doc_id: 0,
+            scorer: Box::new(scorer::Bm25),
+        }
+    }
+
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = scorer;
+    }
+
+    pub fn set_parameters(&mut self, k1: f32, b: f32) {
+        self.k1 = k1;
+        self.b = b;
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.shards[0].analyze(text)
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard a document with `id` is kept in.
+    fn shard_of(&self, id: DocId) -> usize {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Number of live documents.
+    pub fn doc_count(&self) -> usize {
+        self.shards.iter().map(|shard| shard.doc_count()).sum()
+    }
+
+    /// Analyzes and adds a document.
+    pub fn add_text(&mut self, text: &str, metadata: Metadata) -> DocId {
+        self.add_texts(vec![(text.to_string(), metadata)])[0]
+    }
+
+    /// Analyzes and adds documents, each shard adding its share in parallel,
+    /// and returns their ids in the order given.
+    pub fn add_texts(&mut self, documents: Vec<(String, Metadata)>) -> Vec<DocId> {
+        let ids: Vec<DocId> = (self.next_doc_id..).take(documents.len()).collect();
+        self.next_doc_id += documents.len() as DocId;
+        let mut shares: Vec<Vec<(DocId, String, Metadata)>> = vec![vec![]; self.shards.len()];
+        for (&id, (text, metadata)) in ids.iter().zip(documents) {
+            shares[self.shard_of(id)].push((id, text, metadata));
+        }
+        self.shards
+            .par_iter_mut()
+            .zip(shares)
+            .for_each(|(shard, share)| {
+                for (id, text, metadata) in share {
+                    // Told the id to hand out, each shard keeps the ids
+                    // global rather than counting its own.
+                    shard.next_doc_id = id;
+                    let doc = shard.add_text(&text);
+                    shard.set_metadata(doc, metadata);
+                }
+            });
+        ids
+    }
+
+    /// Removes a document, returning whether it was live.
+    pub fn remove(&mut self, id: DocId) -> bool {
+        let shard = self.shard_of(id);
+        let shard = &mut self.shards[shard];
+        match shard.doc_index(id) {
+            Some(doc) => shard.remove_document(doc),
+            None => false,
+        }
+    }
+
+    pub fn metadata(&self, id: DocId) -> Option<Metadata> {
+        let shard = &self.shards[self.shard_of(id)];
+        let doc = shard.doc_index(id)?;
+        Some(shard.metadata(doc).clone())
+    }
+
+    /// The `k` best documents for `text` as a bag-of-words query. Each shard
+    /// keeps its own best `k`, and those lists are merged.
+    pub fn search(&self, text: &str, k: usize) -> Vec<(DocId, f32)> {
+        let terms = self.analyze(text);
+        let shards: Vec<&BM25Index> = self.shards.iter().collect();
+        let stats = GlobalStats::new(&shards, &terms);
+        let best: Vec<Vec<(DocId, f32)>> = shards
+            .par_iter()
+            .map(|shard| {
+                let mut hits = stats.score(shard, &terms, self.scorer.as_ref(), (self.k1, self.b));
+                sort_hits(&mut hits);
+                hits.truncate(k);
+                hits
+            })
+            .collect();
+        let mut hits: Vec<(DocId, f32)> = best.into_iter().flatten().collect();
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+}
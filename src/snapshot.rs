@@ -0,0 +1,74 @@
+//! Point-in-time copies of an index file, to archive an index and roll it
+//! back. Index files are only ever replaced whole, by renaming a finished
+//! file over them, so a copy opened at any moment is of one complete index,
+//! even while another process is serving or updating it.
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStorage;
+use crate::Bm25Error;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+fn io_error(path: &str) -> impl Fn(io::Error) -> Bm25Error + '_ {
+    move |source| Bm25Error::Io {
+        path: path.to_string(),
+        source,
+    }
+}
+
+/// The file that is written before being renamed to `path`.
+pub(crate) fn temporary_path(path: &str) -> String {
+    format!("{}.{}.tmp", path, std::process::id())
+}
+
+/// Writes the file at `path` with `write`, into a temporary file next to it
+/// that is synced and then renamed into place, so that readers of `path` see
+/// either the old file or the whole new one. Nothing is replaced if `write`
+/// fails.
+pub fn write_atomic(
+    path: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Bm25Error>,
+) -> Result<(), Bm25Error> {
+    let temporary = temporary_path(path);
+    let written = File::create(&temporary)
+        .map_err(io_error(&temporary))
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            let file = out
+                .into_inner()
+                .map_err(|err| io_error(&temporary)(err.into_error()))?;
+            file.sync_all().map_err(io_error(&temporary))
+        })
+        .and_then(|_| fs::rename(&temporary, path).map_err(io_error(path)));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+/// Copies the index at `index` to `destination`. A SQLite database is copied
+/// in one read transaction, so saves that are in progress are left out.
+pub fn snapshot(index: &str, destination: &str) -> Result<(), Bm25Error> {
+    #[cfg(feature = "sqlite")]
+    if SqliteStorage::is_sqlite(index) {
+        return SqliteStorage::open(index)?.snapshot(destination);
+    }
+    copy(index, destination)
+}
+
+/// Puts the snapshot at `snapshot` back in place of the index at `index`,
+/// which is replaced whole. A server that has the index open is unaffected
+/// until it opens it again.
+pub fn restore(snapshot: &str, index: &str) -> Result<(), Bm25Error> {
+    copy(snapshot, index)
+}
+
+fn copy(source: &str, destination: &str) -> Result<(), Bm25Error> {
+    let mut file = File::open(source).map_err(io_error(source))?;
+    write_atomic(destination, |out| {
+        io::copy(&mut file, out).map_err(io_error(source))?;
+        out.flush().map_err(io_error(destination))
+    })
+}
@@ -0,0 +1,156 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A run of consecutive words from a document, with the words that matched the
+/// query marked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub words: Vec<(String, bool)>,
+    pub truncated_start: bool,
+    pub truncated_end: bool,
+}
+
+impl Snippet {
+    /// Joins the words with spaces, wrapping matched words in `open` and
+    /// `close` and marking cut-off ends with `...`.
+    pub fn render(&self, open: &str, close: &str) -> String {
+        let mut parts: Vec<String> = self
+            .words
+            .iter()
+            .map(|(word, matched)| {
+                if *matched {
+                    format!("{}{}{}", open, word, close)
+                } else {
+                    word.clone()
+                }
+            })
+            .collect();
+        if self.truncated_start {
+            parts.insert(0, "...".to_string());
+        }
+        if self.truncated_end {
+            parts.push("...".to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+/// Picks the window of up to `max_words` words with the most words matching
+/// `terms` after analysis; the earliest such window wins ties.
+pub fn best_window(
+    text: &str,
+    terms: &HashSet<String>,
+    analyze: &dyn Fn(&str) -> Vec<String>,
+    max_words: usize,
+) -> Snippet {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let matched: Vec<bool> = words
+        .iter()
+        .map(|word| analyze(word).iter().any(|term| terms.contains(term)))
+        .collect();
+    let width = max_words.min(words.len());
+    let mut count = matched[..width].iter().filter(|&&m| m).count();
+    let (mut best_start, mut best_count) = (0, count);
+    for start in 1..=words.len() - width {
+        count += matched[start + width - 1] as usize;
+        count -= matched[start - 1] as usize;
+        if count > best_count {
+            best_start = start;
+            best_count = count;
+        }
+    }
+    let end = best_start + width;
+    Snippet {
+        words: (best_start..end)
+            .map(|i| (words[i].to_string(), matched[i]))
+            .collect(),
+        truncated_start: best_start > 0,
+        truncated_end: end < words.len(),
+    }
+}
+
+/// A byte range of a document's text that matched a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The words of `text` as byte ranges, without the punctuation around them.
+fn word_spans(text: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut start = None;
+    for (offset, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(offset),
+            (Some(begin), true) => {
+                let word = &text[begin..offset];
+                let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric());
+                if !trimmed.is_empty() {
+                    let begin = begin
+                        + (word.len()
+                            - word
+                                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                                .len());
+                    spans.push(Span {
+                        start: begin,
+                        end: begin + trimmed.len(),
+                    });
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// The spans of `text` that match: each word with a term in `terms` after
+/// analysis, and each run of words whose terms are one of `phrases`, with
+/// overlapping spans merged, in order.
+pub fn highlight(
+    text: &str,
+    terms: &HashSet<String>,
+    phrases: &[Vec<String>],
+    analyze: &dyn Fn(&str) -> Vec<String>,
+) -> Vec<Span> {
+    let words: Vec<(Span, Vec<String>)> = word_spans(text)
+        .into_iter()
+        .map(|span| (span, analyze(&text[span.start..span.end])))
+        .collect();
+    let mut spans: Vec<Span> = words
+        .iter()
+        .filter(|(_, analyzed)| analyzed.iter().any(|term| terms.contains(term)))
+        .map(|&(span, _)| span)
+        .collect();
+    // Each term with the word it came from, so that a phrase can span words
+    // that analysis split or dropped terms of.
+    let tokens: Vec<(usize, &str)> = words
+        .iter()
+        .enumerate()
+        .flat_map(|(word, (_, analyzed))| analyzed.iter().map(move |term| (word, term.as_str())))
+        .collect();
+    for phrase in phrases.iter().filter(|phrase| !phrase.is_empty()) {
+        for window in tokens.windows(phrase.len()) {
+            if window
+                .iter()
+                .map(|&(_, term)| term)
+                .eq(phrase.iter().map(String::as_str))
+            {
+                spans.push(Span {
+                    start: words[window[0].0].0.start,
+                    end: words[window[window.len() - 1].0].0.end,
+                });
+            }
+        }
+    }
+    spans.sort_unstable();
+    let mut merged: Vec<Span> = vec![];
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
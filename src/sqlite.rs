@@ -0,0 +1,303 @@
+//! Index storage in a SQLite database, compiled in with the `sqlite` feature,
+//! for applications that already ship one. The term dictionary, postings and
+//! documents are rows of their own tables, so the database can be inspected
+//! and backed up with the usual tools, and every save is one transaction.
+use crate::{
+    cache::QueryCache,
+    default_analyzer, default_scorer,
+    format::IndexHeader,
+    postings::{PostingList, Terms},
+    snapshot,
+    storage::Storage,
+    BM25Index, Bm25Error, Metadata,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS terms (
+        id INTEGER PRIMARY KEY,
+        term TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE IF NOT EXISTS postings (
+        term INTEGER PRIMARY KEY REFERENCES terms (id),
+        docs INTEGER NOT NULL,
+        list BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS documents (
+        doc INTEGER PRIMARY KEY,
+        id INTEGER NOT NULL,
+        live INTEGER NOT NULL,
+        length INTEGER NOT NULL,
+        terms BLOB NOT NULL,
+        path TEXT,
+        title TEXT,
+        metadata BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS state (
+        key TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+";
+
+/// An index kept in a SQLite database file.
+pub struct SqliteStorage {
+    connection: Connection,
+    path: String,
+    /// Saved along with the next index.
+    header: Option<IndexHeader>,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &str) -> Result<SqliteStorage, Bm25Error> {
+        let storage = SqliteStorage {
+            connection: Connection::open(path).map_err(|err| invalid(path, err))?,
+            path: path.to_string(),
+            header: None,
+        };
+        storage
+            .connection
+            .execute_batch(SCHEMA)
+            .map_err(|err| storage.invalid(err))?;
+        Ok(storage)
+    }
+
+    /// Whether the file at `path` is a SQLite database.
+    pub fn is_sqlite(path: &str) -> bool {
+        let mut header = [0; 16];
+        std::fs::File::open(path)
+            .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+            .is_ok_and(|_| &header == b"SQLite format 3\0")
+    }
+
+    fn invalid(&self, message: impl ToString) -> Bm25Error {
+        invalid(&self.path, message)
+    }
+
+    fn state(&self, key: &str) -> Result<Option<Vec<u8>>, Bm25Error> {
+        self.connection
+            .query_row("SELECT value FROM state WHERE key = ?1", [key], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(|err| self.invalid(err))
+    }
+
+    /// The header saved with the index, or `None` if it was saved before
+    /// index formats were versioned. Versions newer than this release writes
+    /// are an error.
+    pub fn header(&self) -> Result<Option<IndexHeader>, Bm25Error> {
+        let Some(header) = self.state("header")? else {
+            return Ok(None);
+        };
+        IndexHeader::read(&mut header.as_slice(), &self.path)
+    }
+
+    /// Sets the header that is saved, in the same transaction, with the next
+    /// index.
+    pub fn set_header(&mut self, header: IndexHeader) {
+        self.header = Some(header);
+    }
+
+    /// Writes a copy of the database to `destination` as it is at the start
+    /// of one read transaction.
+    pub fn snapshot(&self, destination: &str) -> Result<(), Bm25Error> {
+        let temporary = snapshot::temporary_path(destination);
+        let _ = std::fs::remove_file(&temporary);
+        self.connection
+            .execute("VACUUM INTO ?1", [&temporary])
+            .map_err(|err| self.invalid(err))?;
+        std::fs::rename(&temporary, destination).map_err(|source| Bm25Error::Io {
+            path: destination.to_string(),
+            source,
+        })
+    }
+}
+
+fn invalid(path: &str, message: impl ToString) -> Bm25Error {
+    Bm25Error::InvalidIndex {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn save(&mut self, index: &BM25Index, extra: &[u8]) -> Result<(), Bm25Error> {
+        let path = self.path.clone();
+        let failed = |err: rusqlite::Error| invalid(&path, err);
+        let transaction = self.connection.transaction().map_err(failed)?;
+        transaction
+            .execute_batch("DELETE FROM postings; DELETE FROM terms; DELETE FROM documents;")
+            .map_err(failed)?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO terms (id, term) VALUES (?1, ?2)")
+                .map_err(failed)?;
+            for id in 0..index.terms.len() as u32 {
+                insert
+                    .execute(params![id, index.terms.name(id)])
+                    .map_err(failed)?;
+            }
+            let mut insert = transaction
+                .prepare("INSERT INTO postings (term, docs, list) VALUES (?1, ?2, ?3)")
+                .map_err(failed)?;
+            for (term, postings) in index.postings.iter().enumerate() {
+                if !postings.is_empty() {
+                    insert
+                        .execute(params![term, postings.len(), encode(&path, postings)?])
+                        .map_err(failed)?;
+                }
+            }
+            let mut insert = transaction
+                .prepare(
+                    "INSERT INTO documents (doc, id, live, length, terms, path, title, metadata)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )
+                .map_err(failed)?;
+            for doc in 0..index.slot_count() {
+                let metadata = &index.metadata[doc];
+                insert
+                    .execute(params![
+                        doc,
+                        index.doc_ids[doc],
+                        index.live[doc],
+                        index.doc_lengths[doc],
+                        encode(&path, &index.doc_terms[doc])?,
+                        metadata.path,
+                        metadata.title,
+                        encode(&path, metadata)?,
+                    ])
+                    .map_err(failed)?;
+            }
+            let mut insert = transaction
+                .prepare("INSERT OR REPLACE INTO state (key, value) VALUES (?1, ?2)")
+                .map_err(failed)?;
+            insert
+                .execute(params!["index", encode(&path, &index.without_documents())?])
+                .map_err(failed)?;
+            insert.execute(params!["extra", extra]).map_err(failed)?;
+            if let Some(header) = &self.header {
+                let mut bytes = vec![];
+                header.write(&mut bytes, &path)?;
+                insert.execute(params!["header", bytes]).map_err(failed)?;
+            }
+        }
+        transaction.commit().map_err(failed)
+    }
+
+    fn load(&self) -> Result<Option<(BM25Index, Vec<u8>)>, Bm25Error> {
+        let failed = |err: rusqlite::Error| self.invalid(err);
+        let (Some(rest), Some(extra)) = (self.state("index")?, self.state("extra")?) else {
+            return Ok(None);
+        };
+        let mut index: BM25Index = decode(&self.path, &rest)?;
+
+        let mut terms = self
+            .connection
+            .prepare("SELECT term FROM terms ORDER BY id")
+            .map_err(failed)?;
+        let mut dictionary = Terms::default();
+        for term in terms
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(failed)?
+        {
+            dictionary.intern(&term.map_err(failed)?);
+        }
+        index.terms = dictionary;
+        index.postings = vec![PostingList::default(); index.terms.len()];
+        let mut postings = self
+            .connection
+            .prepare("SELECT term, list FROM postings")
+            .map_err(failed)?;
+        let rows = postings
+            .query_map([], |row| {
+                Ok((row.get::<_, usize>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(failed)?;
+        for row in rows {
+            let (term, list) = row.map_err(failed)?;
+            let slot = index
+                .postings
+                .get_mut(term)
+                .ok_or_else(|| self.invalid(format!("postings of unknown term {}", term)))?;
+            *slot = decode(&self.path, &list)?;
+        }
+
+        let mut documents = self
+            .connection
+            .prepare("SELECT id, live, length, terms, metadata FROM documents ORDER BY doc")
+            .map_err(failed)?;
+        let rows = documents
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, i32>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                ))
+            })
+            .map_err(failed)?;
+        for row in rows {
+            let (id, live, length, terms, metadata) = row.map_err(failed)?;
+            index.doc_ids.push(id);
+            index.live.push(live);
+            index.doc_lengths.push(length);
+            index.doc_terms.push(decode(&self.path, &terms)?);
+            index
+                .metadata
+                .push(decode::<Metadata>(&self.path, &metadata)?);
+        }
+        Ok(Some((index, extra)))
+    }
+}
+
+fn encode<T: Serialize + ?Sized>(path: &str, value: &T) -> Result<Vec<u8>, Bm25Error> {
+    bincode::serialize(value).map_err(|err| invalid(path, err))
+}
+
+fn decode<T: DeserializeOwned>(path: &str, bytes: &[u8]) -> Result<T, Bm25Error> {
+    bincode::deserialize(bytes).map_err(|err| invalid(path, err))
+}
+
+impl BM25Index {
+    /// A copy of the settings and statistics, without the term dictionary,
+    /// postings or per-document data, for backends that store those apart.
+    pub(crate) fn without_documents(&self) -> BM25Index {
+        BM25Index {
+            k1: self.k1,
+            b: self.b,
+            idf_mode: self.idf_mode,
+            doc_lengths: vec![],
+            total_doc_length: self.total_doc_length,
+            avg_doc_length: self.avg_doc_length,
+            doc_count: self.doc_count,
+            doc_terms: vec![],
+            live: vec![],
+            deleted: self.deleted,
+            terms: Terms::default(),
+            postings: vec![],
+            dictionary: self.dictionary.clone(),
+            titles: self.titles.clone(),
+            title_bonus: self.title_bonus,
+            length_adjustment: self.length_adjustment,
+            fields: self.fields.clone(),
+            field_boosts: self.field_boosts.clone(),
+            field_b: self.field_b.clone(),
+            boost_key: self.boost_key.clone(),
+            doc_ids: vec![],
+            next_doc_id: self.next_doc_id,
+            metadata: vec![],
+            analyzer: default_analyzer(),
+            scorer: default_scorer(),
+            cache: Mutex::new(QueryCache::new(0)),
+            field_doc_freqs: Mutex::new(HashMap::new()),
+            synonyms: HashMap::new(),
+            synonym_weight: 0.0,
+            proximity_weight: 0.0,
+            feedback: None,
+        }
+    }
+}
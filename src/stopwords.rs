@@ -0,0 +1,40 @@
+const ENGLISH: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "its", "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with", "from", "has", "have", "he", "she", "we", "you",
+    "i", "his", "her", "our", "your", "been", "were", "which", "what", "who", "so", "than",
+];
+
+const FRENCH: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "eux", "il",
+    "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "même", "mes", "moi", "mon", "ne",
+    "nos", "notre", "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se",
+    "ses", "son", "sur", "ta", "te", "tes", "toi", "ton", "tu", "un", "une", "vos", "votre",
+    "vous", "est",
+];
+
+const GERMAN: &[&str] = &[
+    "aber", "alle", "als", "also", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "das",
+    "dass", "dem", "den", "der", "des", "die", "doch", "du", "ein", "eine", "einem", "einen",
+    "einer", "er", "es", "für", "hat", "ich", "ihr", "im", "in", "ist", "mit", "nach", "nicht",
+    "noch", "nur", "oder", "sich", "sie", "sind", "so", "um", "und", "uns", "von", "vor", "war",
+    "was", "wie", "wir", "zu", "zum", "zur",
+];
+
+const SPANISH: &[&str] = &[
+    "a", "al", "algo", "como", "con", "de", "del", "el", "ella", "ellos", "en", "entre", "es",
+    "esta", "este", "esto", "ha", "la", "las", "le", "les", "lo", "los", "mas", "me", "mi", "muy",
+    "ni", "no", "nos", "o", "para", "pero", "por", "que", "se", "si", "sin", "sobre", "su", "sus",
+    "también", "te", "tu", "un", "una", "uno", "y", "ya", "yo",
+];
+
+/// Built-in stop-word list for a lowercase language name, e.g. `english`.
+pub fn builtin(language: &str) -> Option<&'static [&'static str]> {
+    match language {
+        "english" => Some(ENGLISH),
+        "french" => Some(FRENCH),
+        "german" => Some(GERMAN),
+        "spanish" => Some(SPANISH),
+        _ => None,
+    }
+}
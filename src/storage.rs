@@ -0,0 +1,44 @@
+//! Where an index is kept between runs. A [`Storage`] backend saves and loads
+//! a whole [`BM25Index`] along with whatever bytes the application keeps
+//! next to it. [`MemoryStorage`] keeps it in memory, and with the `sqlite`
+//! feature `sqlite::SqliteStorage` keeps it in a SQLite database.
+use crate::{BM25Index, Bm25Error};
+
+/// A place an index is saved to and loaded from.
+pub trait Storage {
+    /// Replaces what is stored with `index` and `extra`, bytes the application
+    /// keeps with it, e.g. how its text is analyzed. Either all of it is saved
+    /// or, if saving fails, what was stored before is kept.
+    fn save(&mut self, index: &BM25Index, extra: &[u8]) -> Result<(), Bm25Error>;
+
+    /// The stored index and extra bytes, or `None` if nothing was saved yet.
+    /// The index has the default analyzer and scorer.
+    fn load(&self) -> Result<Option<(BM25Index, Vec<u8>)>, Bm25Error>;
+}
+
+/// Keeps a serialized index in memory, e.g. to snapshot one and restore it.
+#[derive(Default)]
+pub struct MemoryStorage {
+    saved: Option<Vec<u8>>,
+}
+
+fn invalid(message: impl ToString) -> Bm25Error {
+    Bm25Error::InvalidIndex {
+        path: "memory".to_string(),
+        message: message.to_string(),
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn save(&mut self, index: &BM25Index, extra: &[u8]) -> Result<(), Bm25Error> {
+        self.saved = Some(bincode::serialize(&(index, extra)).map_err(invalid)?);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Option<(BM25Index, Vec<u8>)>, Bm25Error> {
+        let Some(saved) = &self.saved else {
+            return Ok(None);
+        };
+        bincode::deserialize(saved).map(Some).map_err(invalid)
+    }
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, io};
+
+/// Words that a query term is expanded to, read from a file in the Solr
+/// format: `car => automobile, vehicle` expands only `car`, while
+/// `couch, sofa, settee` makes every word expand to the others. Blank lines and
+/// `#` comments are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Synonyms {
+    expansions: HashMap<String, Vec<String>>,
+}
+
+fn word_list(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(',')
+        .map(|word| word.trim().to_string())
+        .filter(|word| !word.is_empty())
+}
+
+impl Synonyms {
+    pub fn parse(text: &str) -> Synonyms {
+        let mut synonyms = Synonyms::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            match line.split_once("=>") {
+                Some((words, expansions)) => {
+                    let expansions: Vec<String> = word_list(expansions).collect();
+                    for word in word_list(words) {
+                        synonyms.add(word, expansions.iter().cloned());
+                    }
+                }
+                None => {
+                    let words: Vec<String> = word_list(line).collect();
+                    for word in &words {
+                        let others = words.iter().filter(|other| *other != word).cloned();
+                        synonyms.add(word.clone(), others);
+                    }
+                }
+            }
+        }
+        synonyms
+    }
+
+    pub fn load(path: &str) -> io::Result<Synonyms> {
+        Ok(Synonyms::parse(&std::fs::read_to_string(path)?))
+    }
+
+    fn add(&mut self, word: String, expansions: impl Iterator<Item = String>) {
+        let entry = self.expansions.entry(word).or_default();
+        for expansion in expansions {
+            if !entry.contains(&expansion) {
+                entry.push(expansion);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expansions.is_empty()
+    }
+
+    pub fn get(&self, word: &str) -> &[String] {
+        self.expansions
+            .get(word)
+            .map_or(&[], |words| words.as_slice())
+    }
+
+    /// The words and expansions as they are passed through `analyze`. Words
+    /// that do not analyze to a single term are dropped, and expansions that
+    /// analyze to several terms are kept as phrases.
+    pub fn analyzed(
+        &self,
+        analyze: &dyn Fn(&str) -> Vec<String>,
+    ) -> HashMap<String, Vec<Vec<String>>> {
+        let mut analyzed: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+        for (word, expansions) in &self.expansions {
+            let mut terms = analyze(word);
+            if terms.len() != 1 {
+                continue;
+            }
+            let entry = analyzed.entry(terms.remove(0)).or_default();
+            for expansion in expansions {
+                let terms = analyze(expansion);
+                if !terms.is_empty() && !entry.contains(&terms) {
+                    entry.push(terms);
+                }
+            }
+        }
+        analyzed
+    }
+}
@@ -0,0 +1,121 @@
+//! The JavaScript API of the WebAssembly build, for searching e.g. a
+//! documentation site in the browser:
+//!
+//! ```js
+//! const index = new SearchIndex("english");
+//! index.addDocument("/guide/install", "Installing with cargo ...");
+//! for (const hit of index.search("install", 10)) console.log(hit.id, hit.score);
+//! ```
+//!
+//! An index can also be built ahead of time and shipped as the bytes of
+//! `toBytes()`. Nothing here touches the filesystem.
+use crate::{analyzer::AnalyzerConfig, BM25Index};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct SearchResult {
+    id: String,
+    score: f32,
+}
+
+#[wasm_bindgen]
+impl SearchResult {
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+}
+
+/// An index of documents identified by strings, e.g. page URLs. The ids are
+/// kept as the `id` value of each document's metadata.
+#[wasm_bindgen]
+pub struct SearchIndex {
+    index: BM25Index,
+    analysis: AnalyzerConfig,
+    docs: HashMap<String, usize>,
+}
+
+#[wasm_bindgen]
+impl SearchIndex {
+    /// An empty index, stemming with the named language, e.g. "english", if
+    /// one is given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(stemmer: Option<String>) -> SearchIndex {
+        let analysis = AnalyzerConfig {
+            stemmer,
+            ..AnalyzerConfig::default()
+        };
+        SearchIndex {
+            index: BM25Index::with_analyzer(Box::new(analysis.build())),
+            analysis,
+            docs: HashMap::new(),
+        }
+    }
+
+    /// Adds a document, replacing any document with the same id.
+    #[wasm_bindgen(js_name = addDocument)]
+    pub fn add_document(&mut self, id: &str, text: &str) {
+        self.remove_document(id);
+        let doc = self.index.add_text(text);
+        let mut metadata = self.index.metadata(doc).clone();
+        metadata.values.insert("id".to_string(), id.to_string());
+        self.index.set_metadata(doc, metadata);
+        self.docs.insert(id.to_string(), doc);
+    }
+
+    /// Removes a document, returning whether there was one with that id.
+    #[wasm_bindgen(js_name = removeDocument)]
+    pub fn remove_document(&mut self, id: &str) -> bool {
+        match self.docs.remove(id) {
+            Some(doc) => self.index.remove_document(doc),
+            None => false,
+        }
+    }
+
+    /// The `k` best documents for `query`, best first.
+    pub fn search(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        let terms = self.index.analyze(query);
+        let terms: Vec<&str> = terms.iter().map(|term| term.as_str()).collect();
+        self.index
+            .rank_top_k(&terms, k)
+            .into_iter()
+            .map(|(doc, score)| SearchResult {
+                id: self.index.metadata(doc as usize).values["id"].clone(),
+                score,
+            })
+            .collect()
+    }
+
+    /// Number of documents.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.index.doc_count()
+    }
+
+    /// The index as bytes that `fromBytes` reads back.
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(bincode::serialize(&(&self.analysis, &self.index))?)
+    }
+
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<SearchIndex, JsError> {
+        let (analysis, mut index): (AnalyzerConfig, BM25Index) = bincode::deserialize(bytes)?;
+        index.set_analyzer(Box::new(analysis.build()));
+        let docs = (0..index.slot_count())
+            .filter(|&doc| index.is_live(doc))
+            .filter_map(|doc| Some((index.metadata(doc).values.get("id")?.clone(), doc)))
+            .collect();
+        Ok(SearchIndex {
+            index,
+            analysis,
+            docs,
+        })
+    }
+}
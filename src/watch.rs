@@ -0,0 +1,78 @@
+use crate::{is_included, StoredIndex};
+use globset::GlobSet;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+/// Collects file system events under the indexed directory so they can be
+/// applied to the index between queries.
+pub struct DirectoryWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    root: PathBuf,
+    directory: String,
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl DirectoryWatcher {
+    pub fn new(
+        directory: &str,
+        include: GlobSet,
+        exclude: GlobSet,
+    ) -> notify::Result<DirectoryWatcher> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(Path::new(directory), RecursiveMode::Recursive)?;
+        Ok(DirectoryWatcher {
+            _watcher: watcher,
+            events,
+            root: Path::new(directory).canonicalize()?,
+            directory: directory.to_string(),
+            include,
+            exclude,
+        })
+    }
+
+    /// Re-indexes every file that changed since the last call and drops the
+    /// ones that were deleted. Returns how many files were updated.
+    pub fn apply(&self, stored: &mut StoredIndex) -> usize {
+        let mut changed = BTreeSet::new();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => changed.extend(event.paths),
+                Err(err) => eprintln!("Watch error: {}", err),
+            }
+        }
+        let mut updated = 0;
+        for path in changed {
+            let Ok(relative) = path.strip_prefix(&self.root) else {
+                continue;
+            };
+            if !is_included(relative, &self.include, &self.exclude) {
+                continue;
+            }
+            let Some(file_path) = Path::new(&self.directory)
+                .join(relative)
+                .to_str()
+                .map(|path| path.to_string())
+            else {
+                continue;
+            };
+            let removed = stored.remove_file(&file_path);
+            if path.is_file() {
+                if let Err(err) = stored.add_file(&file_path) {
+                    eprintln!("Warning: {}; skipping", err);
+                    continue;
+                }
+            } else if removed == 0 {
+                continue;
+            }
+            updated += 1;
+        }
+        updated
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{deadline::Deadline, BM25Index, RankedResults, SearchRequest};
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+
+// Snapshots are shared between reader threads.
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<BM25Index>();
+};
+
+/// The writing half of an index. Changes are made to a private working copy
+/// and become visible to [`IndexReader`]s only on [`IndexWriter::commit`],
+/// which publishes a snapshot of it.
+pub struct IndexWriter {
+    index: BM25Index,
+    published: Arc<ArcSwap<BM25Index>>,
+}
+
+/// A cheap, cloneable handle for searching from any thread. Each search runs
+/// against the latest committed snapshot, and never waits for the writer.
+#[derive(Clone)]
+pub struct IndexReader {
+    published: Arc<ArcSwap<BM25Index>>,
+}
+
+impl IndexWriter {
+    /// Starts with `index` already committed.
+    pub fn new(index: BM25Index) -> IndexWriter {
+        IndexWriter {
+            published: Arc::new(ArcSwap::from_pointee(index.clone())),
+            index,
+        }
+    }
+
+    pub fn reader(&self) -> IndexReader {
+        IndexReader {
+            published: Arc::clone(&self.published),
+        }
+    }
+
+    /// The working copy, including uncommitted changes.
+    pub fn index(&self) -> &BM25Index {
+        &self.index
+    }
+
+    pub fn index_mut(&mut self) -> &mut BM25Index {
+        &mut self.index
+    }
+
+    pub fn add_text(&mut self, text: &str) -> usize {
+        self.index.add_text(text)
+    }
+
+    pub fn remove_document(&mut self, doc_index: usize) -> bool {
+        self.index.remove_document(doc_index)
+    }
+
+    /// Publishes the working copy. The copy takes time proportional to the
+    /// size of the index, so batch changes between commits.
+    pub fn commit(&mut self) {
+        self.published.store(Arc::new(self.index.clone()));
+    }
+}
+
+impl IndexReader {
+    /// The latest committed snapshot, which stays valid, and unchanged, for as
+    /// long as it is held.
+    pub fn searcher(&self) -> Arc<BM25Index> {
+        self.published.load_full()
+    }
+
+    pub fn search(&self, request: &SearchRequest) -> RankedResults {
+        self.published.load().search(request)
+    }
+
+    pub fn search_until(&self, request: &SearchRequest, deadline: &Deadline) -> RankedResults {
+        self.published.load().search_until(request, deadline)
+    }
+}
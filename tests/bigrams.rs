@@ -0,0 +1,36 @@
+use fulltext::{analyzer::AnalyzerConfig, corpus::CorpusBuilder};
+
+fn corpus(bigrams: bool) -> fulltext::BM25Index {
+    let mut corpus = CorpusBuilder::new()
+        .with_analysis(AnalyzerConfig {
+            bigrams,
+            ..AnalyzerConfig::default()
+        })
+        .with_document("a", "learning the machine works")
+        .with_document("b", "notes on machine learning");
+    corpus.extend((0..4).map(|i| (format!("c{}", i), "an unrelated note")));
+    corpus.build()
+}
+
+fn ranking(index: &fulltext::BM25Index, query: &str) -> Vec<(i32, f32)> {
+    let terms = index.analyze(query);
+    let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+    index.rank(&terms)
+}
+
+#[test]
+fn queries_form_bigrams_too() {
+    let terms = corpus(true).analyze("Machine Learning");
+    assert_eq!(terms, ["machine", "learning", "machine_learning"]);
+}
+
+#[test]
+fn the_adjacent_pair_ranks_above_scattered_words() {
+    // Both documents have both words and are as long; only the bigram
+    // tells them apart.
+    let without = ranking(&corpus(false), "machine learning");
+    assert_eq!(without[0].1, without[1].1);
+    let with = ranking(&corpus(true), "machine learning");
+    assert_eq!(with[0].0, 1);
+    assert!(with[0].1 > with[1].1);
+}
@@ -0,0 +1,33 @@
+use fulltext::corpus::CorpusBuilder;
+
+fn corpus() -> CorpusBuilder {
+    CorpusBuilder::new()
+        .with_document("a", "the quantum entanglement of the photon pairs in a lab")
+        .with_document("b", "quantum entanglement between the photon and the atom")
+        .with_document("c", "the weather of the week and the news of the day")
+        .with_document("d", "photon counting with the lab detector")
+}
+
+#[test]
+fn identical_builds_give_bit_equal_scores() {
+    let first = corpus().build();
+    let slots = first.slot_count();
+    for _ in 0..50 {
+        let again = corpus().build();
+        for a in 0..slots {
+            for b in 0..slots {
+                assert_eq!(
+                    again.similarity(a, b).to_bits(),
+                    first.similarity(a, b).to_bits(),
+                    "similarity({}, {})",
+                    a,
+                    b
+                );
+            }
+        }
+        assert_eq!(
+            again.rank(&["photon", "the"]),
+            first.rank(&["photon", "the"])
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! The `--format feed` output of the command-line tool.
+#![cfg(feature = "cli")]
+
+use serde_json::Value;
+use std::{fs, process::Command};
+
+#[test]
+fn the_feed_has_one_entry_per_result_with_its_fields() {
+    let dir = std::env::temp_dir().join(format!("fulltext-feed-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("owls.txt"),
+        "owls hunt at night in the quiet woods",
+    )
+    .unwrap();
+    fs::write(dir.join("bats.txt"), "bats and owls fly at night").unwrap();
+    for animal in ["bees", "ants", "hens"] {
+        let text = format!("{} work by day", animal);
+        fs::write(dir.join(format!("{}.txt", animal)), text).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_fulltext"))
+        .args(["search", "--format", "feed", "--quiet"])
+        .arg(&dir)
+        .arg("night owls")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let feed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(feed["query"], "night owls");
+    assert!(feed["title"].as_str().unwrap().contains("night owls"));
+    let entries = feed["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    for entry in entries {
+        let id = entry["id"].as_str().unwrap();
+        assert!(
+            id.ends_with("owls.txt") || id.ends_with("bats.txt"),
+            "{}",
+            id
+        );
+        assert!(!entry["title"].as_str().unwrap().is_empty());
+        assert!(entry["score"].as_f64().unwrap() > 0.0);
+        assert!(entry["snippet"].as_str().unwrap().contains("<em>"));
+    }
+}
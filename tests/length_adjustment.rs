@@ -0,0 +1,31 @@
+use fulltext::{corpus::CorpusBuilder, LengthAdjustment};
+
+/// Two documents with the same BM25 score for `apple`, as `b` is 0 so their
+/// lengths do not matter to it, the longer one first, and enough others for
+/// `apple` to have a positive IDF.
+fn corpus() -> fulltext::BM25Index {
+    let mut corpus = CorpusBuilder::new()
+        .with_parameters(1.2, 0.0)
+        .with_document("a", "apple with a long description of the orchard")
+        .with_document("b", "apple tart");
+    corpus.extend((0..4).map(|i| (format!("c{}", i), "pear")));
+    corpus.build()
+}
+
+fn order(index: &fulltext::BM25Index) -> Vec<i32> {
+    index.rank(&["apple"]).iter().map(|hit| hit.0).collect()
+}
+
+#[test]
+fn preferring_short_documents_reorders_equal_scores() {
+    let mut index = corpus();
+    let ranked = index.rank(&["apple"]);
+    assert_eq!(ranked[0].1, ranked[1].1);
+    assert_eq!(order(&index), [0, 1]);
+    for adjustment in [LengthAdjustment::Linear(1.0), LengthAdjustment::Log(1.0)] {
+        index.set_length_adjustment(adjustment);
+        assert_eq!(order(&index), [1, 0], "{:?}", adjustment);
+    }
+    index.set_length_adjustment(LengthAdjustment::None);
+    assert_eq!(order(&index), [0, 1]);
+}
@@ -0,0 +1,35 @@
+use fulltext::corpus::CorpusBuilder;
+
+fn corpus() -> CorpusBuilder {
+    (0..30)
+        .map(|i| {
+            let text = format!(
+                "{} filler {}",
+                "apple ".repeat(i % 5 + 1),
+                "pear ".repeat(i % 3)
+            );
+            (format!("doc{:02}", i), text)
+        })
+        .collect()
+}
+
+#[test]
+fn pages_concatenate_to_the_full_ranking() {
+    let index = corpus().build();
+    let query = ["apple", "pear"];
+    let full = index.rank(&query);
+    assert_eq!(full.len(), 30);
+    let pages: Vec<(i32, f32)> = (0..full.len())
+        .step_by(7)
+        .flat_map(|offset| index.rank_page(&query, offset, 7))
+        .collect();
+    assert_eq!(pages, full);
+}
+
+#[test]
+fn offsets_past_the_end_give_an_empty_page() {
+    let index = corpus().build();
+    assert!(index.rank_page(&["apple"], 30, 10).is_empty());
+    assert!(index.rank_page(&["apple"], 1000, 10).is_empty());
+    assert_eq!(index.rank_page(&["apple"], 28, 10).len(), 2);
+}
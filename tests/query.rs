@@ -0,0 +1,39 @@
+use fulltext::query::{self, Query};
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[test]
+fn a_field_prefix_is_parsed_as_a_field_term() {
+    assert!(query::is_structured("title:rust"));
+    let parsed = query::parse("title:rust", &words).unwrap();
+    let mut fields = vec![];
+    collect_fields(&parsed, &mut fields);
+    assert_eq!(fields, ["title"]);
+}
+
+#[test]
+fn urls_and_paths_are_not_field_terms() {
+    for text in ["https://example.com", "file:///etc/hosts", "std::vec"] {
+        assert!(!query::is_structured(text), "{text}");
+        let parsed = query::parse(text, &words).unwrap();
+        let mut fields = vec![];
+        collect_fields(&parsed, &mut fields);
+        assert!(fields.is_empty(), "{text} parsed as {parsed:?}");
+    }
+}
+
+fn collect_fields(query: &Query, fields: &mut Vec<String>) {
+    match query {
+        Query::FieldTerm(field, _) => fields.push(field.clone()),
+        Query::Group(clauses) => clauses
+            .iter()
+            .for_each(|(_, clause)| collect_fields(clause, fields)),
+        Query::Boost(query, _) => collect_fields(query, fields),
+        _ => {}
+    }
+}
@@ -0,0 +1,30 @@
+use fulltext::corpus::CorpusBuilder;
+
+fn corpus() -> fulltext::BM25Index {
+    let mut corpus = CorpusBuilder::new()
+        .with_document("a", "the quantum entanglement of the photon pairs")
+        .with_document("b", "quantum entanglement between the photon and the atom")
+        .with_document("c", "the weather of the week and the news");
+    // Filler in which only the common words recur.
+    corpus.extend((0..6).map(|i| (format!("filler{}", i), "the and of the day")));
+    corpus.build()
+}
+
+#[test]
+fn a_document_is_most_similar_to_itself() {
+    let index = corpus();
+    let itself = index.similarity(0, 0);
+    assert!((itself - 1.0).abs() < 1e-5, "{}", itself);
+    for other in 1..index.slot_count() {
+        assert!(index.similarity(0, other) < itself);
+    }
+}
+
+#[test]
+fn sharing_rare_terms_is_more_similar_than_sharing_common_ones() {
+    let index = corpus();
+    let rare = index.similarity(0, 1);
+    let common = index.similarity(0, 2);
+    assert!(rare > common, "{} <= {}", rare, common);
+    assert_eq!(index.similarity(0, 1), index.similarity(1, 0));
+}
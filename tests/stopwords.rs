@@ -0,0 +1,85 @@
+//! The word lists `--stopwords` and `--stem-exceptions` load, through the
+//! command-line tool.
+#![cfg(feature = "cli")]
+
+use std::{fs, path::PathBuf, process::Command};
+
+/// A fresh directory for one test, holding a small corpus and the given
+/// word lists.
+fn scratch(name: &str, lists: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fulltext-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("docs")).unwrap();
+    fs::write(dir.join("docs/a.txt"), "the running fox and the dog").unwrap();
+    fs::write(dir.join("docs/b.txt"), "a fox runs over the hills").unwrap();
+    for (file, words) in lists {
+        fs::write(dir.join(file), words).unwrap();
+    }
+    dir
+}
+
+/// The terms the corpus in `dir` is indexed into with `args`.
+fn terms(dir: &PathBuf, args: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_fulltext"))
+        .arg("terms")
+        .args(args)
+        .args(["--format", "tsv", "--limit", "100", "--quiet"])
+        .arg(dir.join("docs"))
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.split('\t').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn words_of_a_stop_word_file_are_not_indexed() {
+    let dir = scratch("stopwords", &[("stop.txt", "# common words\nthe and\nA\n")]);
+    let all = terms(&dir, &[]);
+    assert!(all.contains(&"the".to_string()) && all.contains(&"a".to_string()));
+    let kept = terms(&dir, &["--stopwords", "stop.txt"]);
+    for word in ["the", "and", "a"] {
+        assert!(!kept.contains(&word.to_string()), "{} is indexed", word);
+    }
+    assert!(kept.contains(&"fox".to_string()));
+}
+
+#[test]
+fn words_of_a_stem_exception_file_are_not_stemmed() {
+    let dir = scratch("stem-exceptions", &[("keep.txt", "running\n")]);
+    let stemmed = terms(&dir, &["--stemmer", "english"]);
+    assert!(stemmed.contains(&"run".to_string()));
+    assert!(!stemmed.contains(&"running".to_string()));
+    let kept = terms(
+        &dir,
+        &["--stemmer", "english", "--stem-exceptions", "keep.txt"],
+    );
+    assert!(kept.contains(&"running".to_string()));
+}
+
+#[test]
+fn missing_word_lists_are_reported() {
+    let dir = scratch("missing-lists", &[]);
+    for (flag, kind) in [
+        ("--stopwords", "stop-word"),
+        ("--stem-exceptions", "stem-exception"),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_fulltext"))
+            .args(["terms", flag, "missing.txt"])
+            .arg(dir.join("docs"))
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("Could not read {} file missing.txt", kind)),
+            "{}",
+            stderr
+        );
+    }
+}
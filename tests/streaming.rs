@@ -0,0 +1,31 @@
+use fulltext::corpus::CorpusBuilder;
+use std::sync::mpsc;
+
+fn corpus() -> fulltext::BM25Index {
+    let corpus: CorpusBuilder = (0..3000)
+        .map(|i| {
+            let text = format!("fox {} {}", "dog ".repeat(i % 7), i % 13);
+            (format!("doc{:04}", i), text)
+        })
+        .collect();
+    corpus.build()
+}
+
+#[test]
+fn streamed_hits_match_the_batch_results_in_order() {
+    let index = corpus();
+    let query = ["fox", "dog"];
+    let (sender, receiver) = mpsc::channel();
+    index.rank_streaming(&query, 50, sender);
+    let streamed: Vec<(i32, f32)> = receiver.iter().map(|hit| (hit.index, hit.score)).collect();
+    assert_eq!(streamed, index.rank_top_k(&query, 50));
+    assert_eq!(streamed[..], index.rank(&query)[..50]);
+}
+
+#[test]
+fn streaming_stops_when_the_receiver_is_dropped() {
+    let index = corpus();
+    let (sender, receiver) = mpsc::channel();
+    drop(receiver);
+    index.rank_streaming(&["fox"], 50, sender);
+}
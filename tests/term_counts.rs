@@ -0,0 +1,51 @@
+use fulltext::{BM25Index, Bm25Error};
+use std::collections::HashMap;
+
+const DOCUMENTS: [&str; 3] = [
+    "the cat sat on the mat",
+    "the dog chased the cat",
+    "a bird sang",
+];
+
+fn tokens(text: &str) -> Vec<String> {
+    text.split_whitespace().map(str::to_string).collect()
+}
+
+fn counts(tokens: &[String]) -> HashMap<String, i32> {
+    let mut counts = HashMap::new();
+    for token in tokens {
+        *counts.entry(token.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[test]
+fn counted_documents_score_like_token_streams() {
+    let mut streamed = BM25Index::new();
+    let mut counted = BM25Index::new();
+    for (id, text) in DOCUMENTS.iter().enumerate() {
+        let tokens = tokens(text);
+        streamed.add_document(&tokens);
+        counted
+            .add_document_counts(id as i32, counts(&tokens), tokens.len() as i32)
+            .unwrap();
+    }
+    for query in [&["cat"][..], &["the", "cat"], &["bird", "mat"]] {
+        assert_eq!(counted.rank(query), streamed.rank(query));
+    }
+}
+
+#[test]
+fn counts_must_be_added_at_the_next_index() {
+    let mut index = BM25Index::new();
+    let err = index
+        .add_document_counts(3, counts(&tokens("a b")), 2)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Bm25Error::InvalidDocumentId {
+            expected: 0,
+            found: 3
+        }
+    ));
+}
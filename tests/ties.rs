@@ -0,0 +1,24 @@
+use fulltext::corpus::CorpusBuilder;
+
+#[test]
+fn a_uniform_corpus_is_flagged_as_all_tied() {
+    let corpus: CorpusBuilder = (0..5).map(|i| (format!("doc{}", i), "red apple")).collect();
+    let index = corpus.with_document("other", "green pear").build();
+    let results = index.rank_with_metadata(&["apple"]);
+    assert_eq!(results.hits.len(), 5);
+    assert!(results.all_tied);
+    // Ties are broken by document index, so the order is still the same on
+    // every run.
+    let docs: Vec<i32> = results.hits.iter().map(|hit| hit.0).collect();
+    assert_eq!(docs, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn distinct_scores_are_not_flagged() {
+    let index = CorpusBuilder::new()
+        .with_document("a", "apple apple apple")
+        .with_document("b", "apple pie")
+        .with_document("c", "green pear")
+        .build();
+    assert!(!index.rank_with_metadata(&["apple"]).all_tied);
+}
@@ -0,0 +1,46 @@
+use fulltext::corpus::CorpusBuilder;
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(str::to_string).collect()
+}
+
+fn corpus() -> fulltext::BM25Index {
+    let mut corpus = CorpusBuilder::new()
+        .with_document("a", "notes on ownership and borrowing in rust")
+        .with_document("b", "rust book rust book rust book, the rust book reviewed")
+        .with_document("c", "a rust book club meets weekly");
+    // Enough other documents that both query terms have a positive IDF.
+    corpus.extend([
+        ("d", "gardening in spring"),
+        ("e", "tides and moons"),
+        ("f", "baking bread"),
+        ("g", "chess openings"),
+    ]);
+    let mut index = corpus.build();
+    index.set_title(0, words("rust book"));
+    index.set_title(1, words("reviews"));
+    index.set_title(2, words("rust book club"));
+    index
+}
+
+#[test]
+fn an_exact_title_match_ranks_above_a_body_heavy_match() {
+    let mut index = corpus();
+    let query = ["rust", "book"];
+    assert_eq!(index.rank(&query)[0].0, 1);
+    index.set_title_bonus(10.0);
+    let ranked = index.rank(&query);
+    assert_eq!(ranked[0].0, 0);
+    assert_eq!(ranked[1].0, 1);
+}
+
+#[test]
+fn a_title_that_only_contains_the_query_gets_no_bonus() {
+    let mut index = corpus();
+    let before = index.rank(&["rust", "book"]);
+    index.set_title_bonus(10.0);
+    let after = index.rank(&["rust", "book"]);
+    let score = |ranked: &[(i32, f32)], doc| ranked.iter().find(|hit| hit.0 == doc).unwrap().1;
+    assert_eq!(score(&after, 2), score(&before, 2));
+    assert_eq!(score(&after, 0), score(&before, 0) + 10.0);
+}
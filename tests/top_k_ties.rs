@@ -0,0 +1,27 @@
+use fulltext::corpus::CorpusBuilder;
+
+#[test]
+fn equal_scores_at_the_heap_boundary_are_chosen_by_document() {
+    // Twelve documents score the same, more than fit in the heap; the filler
+    // keeps "apple" in under half the corpus so its score is positive.
+    let corpus: CorpusBuilder = (0..12)
+        .map(|i| (format!("tied{}", i), "red apple".to_string()))
+        .chain((0..15).map(|i| (format!("filler{}", i), format!("pear number{}", i))))
+        .collect();
+    let index = corpus.build();
+    let ranked = index.rank(&["apple"]);
+    assert_eq!(ranked.len(), 12);
+    assert!(ranked.windows(2).all(|pair| pair[0].1 == pair[1].1));
+    for k in [1, 5, 11] {
+        let top = index.rank_top_k(&["apple"], k);
+        assert_eq!(top, ranked[..k], "k = {k}");
+        // Ties are broken by document index, so the k lowest of them win.
+        let mut tied: Vec<i32> = ranked.iter().map(|hit| hit.0).collect();
+        tied.sort_unstable();
+        let docs: Vec<i32> = top.iter().map(|hit| hit.0).collect();
+        assert_eq!(docs, tied[..k], "k = {k}");
+        for _ in 0..10 {
+            assert_eq!(index.rank_top_k(&["apple"], k), top, "k = {k}");
+        }
+    }
+}